@@ -0,0 +1,98 @@
+// Compares the cost of getting one frame on screen the old way, one fill_rect per
+// chip-8 pixel, against uploading it to the window's streaming texture. Run with
+//
+//     cargo run --release --example render_benchmark
+//
+// Presenting waits on vsync with some drivers, so both are timed without it.
+
+extern crate chip_8;
+extern crate sdl2;
+
+use std::time::{Duration, Instant};
+
+use chip_8::palette::Palette;
+use chip_8::video::VideoWindow;
+use chip_8::{HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+const FRAMES: u32 = 600;
+const SCALE: u32 = 10;
+
+type Screen = [[u8; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT];
+
+fn main() {
+    let sdl_context = sdl2::init().unwrap();
+    let palette = Palette::default();
+
+    // a checkerboard that shifts every frame, so every pixel changes
+    let screens: Vec<Screen> = (0..2)
+        .map(|phase| {
+            let mut screen = [[0; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT];
+            for (y, row) in screen.iter_mut().enumerate() {
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    *pixel = ((x + y + phase) % 2) as u8;
+                }
+            }
+            screen
+        })
+        .collect();
+
+    for &hires in [false, true].iter() {
+        let mode = if hires { "hires" } else { "lowres" };
+
+        let mut canvas = fill_rect_canvas(&sdl_context);
+        let before = time(|frame| fill_rect_draw(&mut canvas, &palette, &screens[frame % 2], hires));
+        drop(canvas);
+
+        let mut window = VideoWindow::new(&sdl_context, "render benchmark", SCALE, true, palette);
+        let after = time(|frame| {
+            window.update(&screens[frame % 2], hires);
+            window.present();
+        });
+        drop(window);
+
+        println!("{:6}  fill_rect: {:8.3} ms/frame  streaming texture: {:8.3} ms/frame",
+            mode, per_frame(before), per_frame(after));
+    }
+}
+
+fn fill_rect_canvas(sdl_context: &sdl2::Sdl) -> Canvas<Window> {
+    let video = sdl_context.video().unwrap();
+    let window = video.window("render benchmark", SCREEN_WIDTH as u32 * SCALE, SCREEN_HEIGHT as u32 * SCALE)
+        .build()
+        .unwrap();
+    window.into_canvas().build().unwrap()
+}
+
+// How VideoWindow::draw used to work, one rectangle per pixel
+fn fill_rect_draw(canvas: &mut Canvas<Window>, palette: &Palette, pixels: &Screen, hires: bool) {
+    let (width, height, scale) = if hires {
+        (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT, SCALE / 2)
+    } else {
+        (SCREEN_WIDTH, SCREEN_HEIGHT, SCALE)
+    };
+
+    for (y, row) in pixels.iter().take(height).enumerate() {
+        for (x, &col) in row.iter().take(width).enumerate() {
+            let (r, g, b) = palette.color(col);
+            canvas.set_draw_color(Color::RGB(r, g, b));
+            let _ = canvas.fill_rect(Rect::new(x as i32 * scale as i32, y as i32 * scale as i32, scale, scale));
+        }
+    }
+    canvas.present();
+}
+
+fn time<F: FnMut(usize)>(mut frame: F) -> Duration {
+    let start = Instant::now();
+    for i in 0..FRAMES {
+        frame(i as usize);
+    }
+    start.elapsed()
+}
+
+fn per_frame(total: Duration) -> f64 {
+    total.as_secs_f64() * 1000.0 / FRAMES as f64
+}
@@ -0,0 +1,541 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use crate::cpu::PROGRAM_START;
+use crate::instruction::Instruction;
+use crate::rom::MAX_ROM_SIZE;
+
+// Assembles Cowgod's mnemonics, the ones Instruction prints, into a rom that
+// loads at PROGRAM_START. A line is an optional label, then an instruction or
+// a directive, then an optional comment:
+//
+//     ; draws the player
+//     SPEED equ 2
+//     loop:   LD I, player
+//             DRW V0, V1, 4
+//             ADD V0, SPEED
+//             JP loop
+//     player: db 0x60, 0xF0, 0x90, 0x60
+//     include "levels.asm"
+//
+// db takes bytes and "strings", dw takes big endian words. Numbers are decimal,
+// 0x or $ hex, or 0b binary, and anywhere a number goes a name can too, with
+// + and - between them. Labels and constants can be used before they're
+// defined, everything is resolved once the whole program has been read.
+
+/// Files can include each other this deep, which also stops an include cycle
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// Words that can't be used as names since they're operands
+const RESERVED: [&str; 13] = ["I", "DT", "ST", "K", "F", "HF", "B", "R", "PITCH", "LONG", "EQU", "DB", "DW"];
+
+// Everything encode knows, to tell a typo from wrong operands
+const MNEMONICS: [&str; 30] = [
+    "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SCD", "SCU", "PLANE", "JP", "CALL", "SE", "SNE",
+    "SAVE", "LOAD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "LD",
+];
+
+// An assembled program
+#[derive(Debug, PartialEq)]
+pub struct Program {
+    /// the rom, starting at PROGRAM_START
+    pub bytes: Vec<u8>,
+    /// address of every label
+    pub labels: BTreeMap<String, u16>,
+}
+
+impl Program {
+
+    /// The labels as a symbol file, one '<address> <label>' per line in address order
+    pub fn symbol_file(&self) -> String {
+        let mut labels: Vec<(&u16, &String)> = self.labels.iter().map(|(label, address)| (address, label)).collect();
+        labels.sort();
+        let mut output = String::new();
+        for (address, label) in labels {
+            let _ = writeln!(output, "{:#06x} {}", address, label);
+        }
+        output
+    }
+}
+
+// A line of source, after includes have been read in
+struct Line {
+    file: String,
+    number: usize,
+    text: String,
+}
+
+impl Line {
+    fn error(&self, message: String) -> String {
+        format!("{} line {}: {}", self.file, self.number, message)
+    }
+}
+
+// What a line holds, once its label is taken off
+enum Statement {
+    Constant(String, String),
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Instruction(String, Vec<String>),
+}
+
+// An instruction operand
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    V(u8),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    LargeFont,
+    Bcd,
+    Flags,
+    Pitch,
+    Long(String),
+    Value(String),
+}
+
+// Assembles a file, reading included files relative to the file including them
+pub fn assemble_file(path: &str) -> Result<Program, String> {
+    let source = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+    assemble(path, &source, &mut |path| fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error)))
+}
+
+// Assembles source named by file, with read fetching the text of included files
+pub fn assemble(file: &str, source: &str, read: &mut dyn FnMut(&str) -> Result<String, String>) -> Result<Program, String> {
+
+    let mut lines = Vec::new();
+    read_lines(file, source, read, 0, &mut lines)?;
+
+    // first pass: where everything goes, so names can be used before they're defined
+    let mut statements = Vec::new();
+    let mut labels = BTreeMap::new();
+    let mut names = BTreeSet::new();
+    let mut address = PROGRAM_START;
+
+    for line in &lines {
+        let (label, statement) = parse_line(&line.text).map_err(|message| line.error(message))?;
+
+        if let Some(label) = label {
+            define(&label, &mut names).map_err(|message| line.error(message))?;
+            labels.insert(label, address as u16);
+        }
+
+        let statement = match statement {
+            Some(statement) => statement,
+            None => continue,
+        };
+        let size = match statement {
+            Statement::Constant(ref name, _) => {
+                define(name, &mut names).map_err(|message| line.error(message))?;
+                0
+            },
+            Statement::Bytes(ref values) => values.iter().map(|value| string_literal(value).map_or(1, str::len)).sum(),
+            Statement::Words(ref values) => values.len() * 2,
+            // the only instruction with a second word
+            Statement::Instruction(ref mnemonic, ref operands) => {
+                match (mnemonic.as_str(), operands.get(1).map(|operand| parse_operand(operand))) {
+                    ("LD", Some(Operand::Long(_))) => 4,
+                    _ => 2,
+                }
+            },
+        };
+
+        statements.push((line, statement));
+        address += size;
+    }
+
+    if address - PROGRAM_START > MAX_ROM_SIZE {
+        return Err(format!("program is {} bytes, only {} fit in memory", address - PROGRAM_START, MAX_ROM_SIZE));
+    }
+
+    // constants in order, each can use labels and the constants before it
+    let mut symbols: BTreeMap<String, i64> = labels.iter().map(|(label, &address)| (label.clone(), address as i64)).collect();
+    for &(line, ref statement) in &statements {
+        if let Statement::Constant(ref name, ref value) = *statement {
+            let value = evaluate(value, &symbols).map_err(|message| line.error(message))?;
+            symbols.insert(name.clone(), value);
+        }
+    }
+
+    // second pass: the bytes
+    let mut bytes = Vec::with_capacity(address - PROGRAM_START);
+    for (line, statement) in statements {
+        let result = match statement {
+            Statement::Constant(..) => Ok(()),
+            Statement::Bytes(values) => values.iter().try_for_each(|value| {
+                match string_literal(value) {
+                    Some(text) => bytes.extend_from_slice(text.as_bytes()),
+                    None => bytes.push(byte(value, &symbols)?),
+                }
+                Ok(())
+            }),
+            Statement::Words(values) => values.iter().try_for_each(|value| {
+                let word = word(value, &symbols)?;
+                bytes.push((word >> 8) as u8);
+                bytes.push(word as u8);
+                Ok(())
+            }),
+            Statement::Instruction(mnemonic, operands) => {
+                encode(&mnemonic, &operands, &symbols).map(|encoded| bytes.extend_from_slice(&encoded))
+            },
+        };
+        result.map_err(|message| line.error(message))?;
+    }
+
+    Ok(Program {
+        bytes: bytes,
+        labels: labels,
+    })
+}
+
+// Appends the lines of a file to lines, reading in its includes where they are
+fn read_lines(file: &str, source: &str, read: &mut dyn FnMut(&str) -> Result<String, String>, depth: usize,
+              lines: &mut Vec<Line>) -> Result<(), String> {
+
+    for (number, text) in source.lines().enumerate() {
+        let line = Line {
+            file: String::from(file),
+            number: number + 1,
+            text: String::from(strip_comment(text).trim()),
+        };
+
+        let mut words = line.text.splitn(2, char::is_whitespace);
+        if !words.next().unwrap_or("").eq_ignore_ascii_case("include") {
+            lines.push(line);
+            continue;
+        }
+
+        let argument = words.next().unwrap_or("").trim();
+        let included = string_literal(argument)
+            .ok_or_else(|| line.error(String::from("include needs a file name in quotes")))?;
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(line.error(format!("includes nested more than {} deep", MAX_INCLUDE_DEPTH)));
+        }
+
+        let path = Path::new(file).parent().unwrap_or_else(|| Path::new("")).join(included);
+        let path = path.to_string_lossy();
+        let source = read(&path).map_err(|message| line.error(message))?;
+        read_lines(&path, &source, read, depth + 1, lines)?;
+    }
+
+    Ok(())
+}
+
+// The line up to a ';' that isn't in a string
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => (),
+        }
+    }
+    line
+}
+
+// Splits a line into its label and statement
+fn parse_line(text: &str) -> Result<(Option<String>, Option<Statement>), String> {
+
+    let mut text = text;
+    let mut label = None;
+
+    let first = text.split_whitespace().next().unwrap_or("");
+    if first.ends_with(':') && !first.starts_with('"') {
+        let name = &first[..first.len() - 1];
+        check_name(name)?;
+        label = Some(String::from(name));
+        text = text[first.len()..].trim_start();
+    }
+
+    if text.is_empty() {
+        return Ok((label, None));
+    }
+
+    let (word, rest) = match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim()),
+        None => (text, ""),
+    };
+
+    // NAME equ value
+    let mut rest_words = rest.splitn(2, char::is_whitespace);
+    if matches!(rest_words.next(), Some(equ) if equ.eq_ignore_ascii_case("equ")) {
+        if label.is_some() {
+            return Err(String::from("a constant can't have a label"));
+        }
+        check_name(word)?;
+        let value = rest_words.next().unwrap_or("").trim();
+        if value.is_empty() {
+            return Err(format!("no value given for '{}'", word));
+        }
+        return Ok((None, Some(Statement::Constant(String::from(word), String::from(value)))));
+    }
+
+    let operands = split_operands(rest)?;
+    let mnemonic = word.to_ascii_uppercase();
+    let statement = match mnemonic.as_str() {
+        "DB" | "DW" if operands.is_empty() => return Err(format!("{} needs at least one value", word)),
+        "DB" => Statement::Bytes(operands),
+        "DW" => Statement::Words(operands),
+        _ => Statement::Instruction(mnemonic, operands),
+    };
+
+    Ok((label, Some(statement)))
+}
+
+// Operands are separated by commas, which can be in strings
+fn split_operands(text: &str) -> Result<Vec<String>, String> {
+
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut operands = Vec::new();
+    let mut operand = String::new();
+    let mut quoted = false;
+    for character in text.chars() {
+        match character {
+            '"' => {
+                quoted = !quoted;
+                operand.push(character);
+            },
+            ',' if !quoted => operands.push(operand.split_off(0)),
+            _ => operand.push(character),
+        }
+    }
+    if quoted {
+        return Err(String::from("string is missing its closing quote"));
+    }
+    operands.push(operand);
+
+    operands.iter()
+        .map(|operand| match operand.trim() {
+            "" => Err(String::from("empty operand")),
+            operand => Ok(String::from(operand)),
+        })
+        .collect()
+}
+
+// Labels and constants start with a letter or '_' and go on with letters,
+// digits and '_'
+fn check_name(name: &str) -> Result<(), String> {
+    let mut characters = name.chars();
+    let valid = matches!(characters.next(), Some(first) if first.is_ascii_alphabetic() || first == '_')
+        && characters.all(|character| character.is_ascii_alphanumeric() || character == '_');
+    if !valid {
+        return Err(format!("invalid name '{}'", name));
+    }
+    if RESERVED.contains(&name.to_ascii_uppercase().as_str()) || register(name).is_some() {
+        return Err(format!("'{}' is reserved and can't be a name", name));
+    }
+    Ok(())
+}
+
+// Labels and constants share one set of names
+fn define(name: &str, names: &mut BTreeSet<String>) -> Result<(), String> {
+    if !names.insert(String::from(name)) {
+        return Err(format!("'{}' is already defined", name));
+    }
+    Ok(())
+}
+
+// The text inside a "string", None if it isn't one
+fn string_literal(text: &str) -> Option<&str> {
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        Some(&text[1..text.len() - 1])
+    } else {
+        None
+    }
+}
+
+// V0-VF
+fn register(text: &str) -> Option<u8> {
+    let mut characters = text.chars();
+    match (characters.next(), characters.next(), characters.next()) {
+        (Some('V'), Some(digit), None) | (Some('v'), Some(digit), None) => digit.to_digit(16).map(|x| x as u8),
+        _ => None,
+    }
+}
+
+fn parse_operand(text: &str) -> Operand {
+    if let Some(x) = register(text) {
+        return Operand::V(x);
+    }
+    let upper = text.to_ascii_uppercase();
+    match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "HF" => Operand::LargeFont,
+        "B" => Operand::Bcd,
+        "R" => Operand::Flags,
+        "PITCH" => Operand::Pitch,
+        _ if upper.starts_with("LONG ") => Operand::Long(String::from(text[5..].trim())),
+        _ => Operand::Value(String::from(text)),
+    }
+}
+
+// A number, a name, or a sum of them
+fn evaluate(text: &str, symbols: &BTreeMap<String, i64>) -> Result<i64, String> {
+
+    let mut total = 0i64;
+    let mut sign = 1;
+    let mut term = String::new();
+    let mut expect_term = true;
+
+    let add_term = |term: &mut String, sign: i64, total: &mut i64| -> Result<(), String> {
+        let value = term_value(term.trim(), symbols)?;
+        *total = total.checked_add(sign * value).ok_or_else(|| format!("'{}' is too large", text))?;
+        term.clear();
+        Ok(())
+    };
+
+    for character in text.chars() {
+        match character {
+            '+' | '-' if !term.trim().is_empty() => {
+                add_term(&mut term, sign, &mut total)?;
+                sign = if character == '-' { -1 } else { 1 };
+                expect_term = true;
+            },
+            '-' if expect_term && term.trim().is_empty() => sign = -sign,
+            '+' if expect_term && term.trim().is_empty() => (),
+            _ => {
+                term.push(character);
+                expect_term = false;
+            },
+        }
+    }
+    if term.trim().is_empty() {
+        return Err(format!("invalid value '{}'", text));
+    }
+    add_term(&mut term, sign, &mut total)?;
+
+    Ok(total)
+}
+
+fn term_value(term: &str, symbols: &BTreeMap<String, i64>) -> Result<i64, String> {
+    let lower = term.to_ascii_lowercase();
+    let number = if let Some(hex) = lower.strip_prefix("0x").or_else(|| lower.strip_prefix('$')) {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+    } else if term.starts_with(|character: char| character.is_ascii_digit()) {
+        term.parse()
+    } else {
+        return symbols.get(term).cloned().ok_or_else(|| format!("unknown name '{}'", term));
+    };
+    number.map_err(|_| format!("invalid number '{}'", term))
+}
+
+// A value that has to fit in a range
+fn ranged(text: &str, symbols: &BTreeMap<String, i64>, low: i64, high: i64, what: &str) -> Result<i64, String> {
+    let value = evaluate(text, symbols)?;
+    if value < low || value > high {
+        return Err(format!("{} doesn't fit in {} ({})", text, what, value));
+    }
+    Ok(value)
+}
+
+// Bytes can be written signed, -1 is 0xFF
+fn byte(text: &str, symbols: &BTreeMap<String, i64>) -> Result<u8, String> {
+    ranged(text, symbols, -0x80, 0xFF, "a byte").map(|value| value as u8)
+}
+
+fn word(text: &str, symbols: &BTreeMap<String, i64>) -> Result<u16, String> {
+    ranged(text, symbols, -0x8000, 0xFFFF, "a word").map(|value| value as u16)
+}
+
+fn address(text: &str, symbols: &BTreeMap<String, i64>) -> Result<u16, String> {
+    ranged(text, symbols, 0, 0xFFF, "an address").map(|value| value as u16)
+}
+
+fn nibble(text: &str, symbols: &BTreeMap<String, i64>) -> Result<u8, String> {
+    ranged(text, symbols, 0, 0xF, "a nibble").map(|value| value as u8)
+}
+
+// The bytes of one instruction
+fn encode(mnemonic: &str, operands: &[String], symbols: &BTreeMap<String, i64>) -> Result<Vec<u8>, String> {
+
+    let parsed: Vec<Operand> = operands.iter().map(|operand| parse_operand(operand)).collect();
+    let mut long = None;
+
+    let instruction = match (mnemonic, parsed.as_slice()) {
+        ("CLS", []) => Instruction::Clear,
+        ("RET", []) => Instruction::Return,
+        ("SCR", []) => Instruction::ScrollRight,
+        ("SCL", []) => Instruction::ScrollLeft,
+        ("EXIT", []) => Instruction::Exit,
+        ("LOW", []) => Instruction::LowRes,
+        ("HIGH", []) => Instruction::HighRes,
+        ("AUDIO", []) => Instruction::LoadAudio,
+        ("SCD", [Operand::Value(n)]) => Instruction::ScrollDown(nibble(n, symbols)?),
+        ("SCU", [Operand::Value(n)]) => Instruction::ScrollUp(nibble(n, symbols)?),
+        ("PLANE", [Operand::Value(n)]) => Instruction::SelectPlanes(nibble(n, symbols)?),
+        ("JP", [Operand::Value(nnn)]) => Instruction::Jump(address(nnn, symbols)?),
+        ("JP", [Operand::V(0), Operand::Value(nnn)]) => Instruction::JumpOffset(address(nnn, symbols)?),
+        ("CALL", [Operand::Value(nnn)]) => Instruction::Call(address(nnn, symbols)?),
+        ("SE", [Operand::V(x), Operand::V(y)]) => Instruction::SkipEqual(*x, *y),
+        ("SE", [Operand::V(x), Operand::Value(kk)]) => Instruction::SkipEqualByte(*x, byte(kk, symbols)?),
+        ("SNE", [Operand::V(x), Operand::V(y)]) => Instruction::SkipNotEqual(*x, *y),
+        ("SNE", [Operand::V(x), Operand::Value(kk)]) => Instruction::SkipNotEqualByte(*x, byte(kk, symbols)?),
+        ("SAVE", [Operand::V(x), Operand::V(y)]) => Instruction::SaveRange(*x, *y),
+        ("LOAD", [Operand::V(x), Operand::V(y)]) => Instruction::LoadRange(*x, *y),
+        ("ADD", [Operand::V(x), Operand::V(y)]) => Instruction::Add(*x, *y),
+        ("ADD", [Operand::V(x), Operand::Value(kk)]) => Instruction::AddByte(*x, byte(kk, symbols)?),
+        ("ADD", [Operand::I, Operand::V(x)]) => Instruction::AddIndex(*x),
+        ("OR", [Operand::V(x), Operand::V(y)]) => Instruction::Or(*x, *y),
+        ("AND", [Operand::V(x), Operand::V(y)]) => Instruction::And(*x, *y),
+        ("XOR", [Operand::V(x), Operand::V(y)]) => Instruction::Xor(*x, *y),
+        ("SUB", [Operand::V(x), Operand::V(y)]) => Instruction::Subtract(*x, *y),
+        ("SHR", [Operand::V(x), Operand::V(y)]) => Instruction::ShiftRight(*x, *y),
+        ("SUBN", [Operand::V(x), Operand::V(y)]) => Instruction::SubtractNegated(*x, *y),
+        ("SHL", [Operand::V(x), Operand::V(y)]) => Instruction::ShiftLeft(*x, *y),
+        ("RND", [Operand::V(x), Operand::Value(kk)]) => Instruction::Random(*x, byte(kk, symbols)?),
+        ("DRW", [Operand::V(x), Operand::V(y), Operand::Value(n)]) => Instruction::Draw(*x, *y, nibble(n, symbols)?),
+        ("SKP", [Operand::V(x)]) => Instruction::SkipKey(*x),
+        ("SKNP", [Operand::V(x)]) => Instruction::SkipNotKey(*x),
+        ("LD", [Operand::V(x), Operand::V(y)]) => Instruction::Load(*x, *y),
+        ("LD", [Operand::V(x), Operand::Value(kk)]) => Instruction::LoadByte(*x, byte(kk, symbols)?),
+        ("LD", [Operand::I, Operand::Value(nnn)]) => Instruction::LoadIndex(address(nnn, symbols)?),
+        ("LD", [Operand::I, Operand::Long(nnnn)]) => {
+            long = Some(word(nnnn, symbols)?);
+            Instruction::LoadLongIndex
+        },
+        ("LD", [Operand::V(x), Operand::DelayTimer]) => Instruction::LoadDelay(*x),
+        ("LD", [Operand::V(x), Operand::Key]) => Instruction::WaitKey(*x),
+        ("LD", [Operand::DelayTimer, Operand::V(x)]) => Instruction::SetDelay(*x),
+        ("LD", [Operand::SoundTimer, Operand::V(x)]) => Instruction::SetSound(*x),
+        ("LD", [Operand::Font, Operand::V(x)]) => Instruction::LoadFont(*x),
+        ("LD", [Operand::LargeFont, Operand::V(x)]) => Instruction::LoadLargeFont(*x),
+        ("LD", [Operand::Bcd, Operand::V(x)]) => Instruction::StoreBcd(*x),
+        ("LD", [Operand::Pitch, Operand::V(x)]) => Instruction::SetPitch(*x),
+        ("LD", [Operand::IndirectI, Operand::V(x)]) => Instruction::StoreRegisters(*x),
+        ("LD", [Operand::V(x), Operand::IndirectI]) => Instruction::LoadRegisters(*x),
+        ("LD", [Operand::Flags, Operand::V(x)]) => Instruction::StoreFlags(*x),
+        ("LD", [Operand::V(x), Operand::Flags]) => Instruction::LoadFlags(*x),
+        _ if MNEMONICS.contains(&mnemonic) => {
+            return Err(format!("invalid operands for {}: '{}'", mnemonic, operands.join(", ")));
+        },
+        _ => return Err(format!("unknown instruction '{}'", mnemonic)),
+    };
+
+    let opcode = instruction.encode();
+    let mut bytes = vec![(opcode >> 8) as u8, opcode as u8];
+    if let Some(nnnn) = long {
+        bytes.push((nnnn >> 8) as u8);
+        bytes.push(nnnn as u8);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+#[path = "./assembler_test.rs"]
+mod assembler_test;
@@ -0,0 +1,137 @@
+use super::*;
+use crate::disasm::{self, Syntax};
+
+fn no_includes(path: &str) -> Result<String, String> {
+    Err(format!("{}: not found", path))
+}
+
+fn assemble_text(source: &str) -> Result<Program, String> {
+    assemble("test.asm", source, &mut no_includes)
+}
+
+#[test]
+fn test_instructions() {
+    let program = assemble_text("
+        CLS
+        LD V0, 0x12
+        LD VA, vB
+        ADD I, V3
+        DRW V0, V1, 15
+        SE V2, $ff
+        SNE V2, V3
+        LD [I], V5
+        LD V5, [I]
+        LD B, V7
+        LD F, V1
+        LD HF, V1
+        LD V4, K
+        LD DT, V4
+        LD ST, V4
+        LD V4, DT
+        SKP V9
+        SKNP V9
+        JP V0, 0x300
+        RND V1, 0b1111
+        SAVE V1, V4
+        PLANE 3
+        LD I, LONG 0xABCD
+    ").unwrap();
+
+    assert_eq!(program.bytes, [
+        0x00, 0xE0, 0x60, 0x12, 0x8A, 0xB0, 0xF3, 0x1E, 0xD0, 0x1F, 0x32, 0xFF, 0x92, 0x30, 0xF5, 0x55,
+        0xF5, 0x65, 0xF7, 0x33, 0xF1, 0x29, 0xF1, 0x30, 0xF4, 0x0A, 0xF4, 0x15, 0xF4, 0x18, 0xF4, 0x07,
+        0xE9, 0x9E, 0xE9, 0xA1, 0xB3, 0x00, 0xC1, 0x0F, 0x51, 0x42, 0xF3, 0x01, 0xF0, 0x00, 0xAB, 0xCD,
+    ]);
+}
+
+#[test]
+fn test_labels_constants_and_data() {
+    // constants can only use the constants before them
+    assert_eq!(assemble_text("WIDTH equ HEIGHT\nHEIGHT equ 1"), Err(String::from("test.asm line 1: unknown name 'HEIGHT'")));
+
+    let program = assemble_text("
+        SPEED equ 2                 ; constants can use labels
+        END equ player + 4
+    start:
+        LD I, player
+        ADD V0, SPEED
+        ADD V1, -1
+        JP start                    ; comment
+    player: db 0x60, 0xF0, \"a;b\", -1
+        dw END, 0x1234
+    ").unwrap();
+
+    assert_eq!(program.bytes, [
+        0xA2, 0x08, 0x70, 0x02, 0x71, 0xFF, 0x12, 0x00,
+        0x60, 0xF0, b'a', b';', b'b', 0xFF, 0x02, 0x0C, 0x12, 0x34,
+    ]);
+    assert_eq!(program.labels.get("player"), Some(&0x208));
+    assert_eq!(program.symbol_file(), "0x0200 start\n0x0208 player\n");
+}
+
+#[test]
+fn test_include() {
+    let mut read = |path: &str| match path {
+        "src/sprites.asm" => Ok(String::from("include \"more/digits.asm\"\nsmile: db 0x66")),
+        "src/more/digits.asm" => Ok(String::from("one: db 0x20\nLD V0, ,")),
+        _ => Err(format!("{}: not found", path)),
+    };
+    let result = assemble("src/main.asm", "LD I, smile\ninclude \"sprites.asm\"", &mut read);
+    assert_eq!(result, Err(String::from("src/more/digits.asm line 2: empty operand")));
+
+    let mut read = |path: &str| match path {
+        "sprites.asm" => Ok(String::from("smile: db 0x66")),
+        _ => Err(format!("{}: not found", path)),
+    };
+    let program = assemble("main.asm", "LD I, smile\ninclude \"sprites.asm\"", &mut read).unwrap();
+    assert_eq!(program.bytes, [0xA2, 0x02, 0x66]);
+
+    let mut itself = |_: &str| Ok(String::from("include \"loop.asm\""));
+    let error = assemble("loop.asm", "include \"loop.asm\"", &mut itself).unwrap_err();
+    assert!(error.ends_with("includes nested more than 16 deep"));
+}
+
+#[test]
+fn test_errors() {
+    let error = |source: &str| assemble_text(source).unwrap_err();
+
+    assert_eq!(error("CLS\nMOV V0, V1"), "test.asm line 2: unknown instruction 'MOV'");
+    assert_eq!(error("LD DT, DT"), "test.asm line 1: invalid operands for LD: 'DT, DT'");
+    assert_eq!(error("JP nowhere"), "test.asm line 1: unknown name 'nowhere'");
+    assert_eq!(error("LD V0, 256"), "test.asm line 1: 256 doesn't fit in a byte (256)");
+    assert_eq!(error("JP 0x1000"), "test.asm line 1: 0x1000 doesn't fit in an address (4096)");
+    assert_eq!(error("DRW V0, V1, 16"), "test.asm line 1: 16 doesn't fit in a nibble (16)");
+    assert_eq!(error("a: CLS\na: RET"), "test.asm line 2: 'a' is already defined");
+    assert_eq!(error("vA: CLS"), "test.asm line 1: 'vA' is reserved and can't be a name");
+    assert_eq!(error("db \"open"), "test.asm line 1: string is missing its closing quote");
+    assert_eq!(error("LD V0, 0xZZ"), "test.asm line 1: invalid number '0xZZ'");
+}
+
+#[test]
+fn test_round_trip() {
+    // reachable code, an odd aligned jump target, a sprite and a byte of data
+    let source = "
+        CLS
+        LD I, LONG dot
+        DRW V0, V1, 1
+        CALL draw
+        JP odd
+        db 0xFF
+    odd:
+        SE V0, 0
+        JP odd
+        EXIT
+    draw:
+        LD I, box
+        DRW V2, V3, 2
+        RET
+    box: db 0x3C, 0x42
+    dot: db 0x80, 0xAB
+    ";
+    let program = assemble_text(source).unwrap();
+
+    let disassembly = disasm::disassemble(&program.bytes, Syntax::Plain);
+    let reassembled = assemble_text(&disassembly).unwrap();
+    assert_eq!(reassembled.bytes, program.bytes);
+    assert_eq!(disasm::disassemble(&reassembled.bytes, Syntax::Plain), disassembly);
+}
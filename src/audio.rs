@@ -0,0 +1,171 @@
+use std::f32::consts::PI;
+
+use crate::cpu::State;
+use crate::scheduler::TIMER_HZ;
+
+/// XO-CHIP plays its audio pattern at 4000 bits a second when the pitch register is 64
+const PATTERN_BASE_RATE: f32 = 4000.0;
+/// Fade in and out over 5ms so starting and stopping the tone doesn't click
+const RAMP_SECONDS: f32 = 0.005;
+
+// Shape of the buzzer's tone
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sine,
+}
+
+impl Waveform {
+
+    /// Looks up a waveform by name, used for the command line
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name.to_ascii_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        }
+    }
+}
+
+// How the buzzer sounds while the sound timer is running
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneSettings {
+    /// pitch of the tone in hz, ignored while an XO-CHIP audio pattern is loaded
+    pub frequency: f32,
+    /// 0.0 is silent, 1.0 is full scale
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for ToneSettings {
+    fn default() -> ToneSettings {
+        ToneSettings {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+// Something that can play the buzzer. Frontends call play_frame once after every
+// emulated frame, so the sound follows emulated time rather than the wall clock.
+pub trait AudioBackend {
+    fn play_frame(&mut self, state: &State);
+}
+
+// Plays nothing, for headless runs and machines without an audio device
+pub struct NullAudio;
+
+impl AudioBackend for NullAudio {
+    fn play_frame(&mut self, _state: &State) {}
+}
+
+// Turns emulated frames into samples. Each frame gets exactly its share of the
+// sample rate (carrying the leftover the same way the scheduler does with
+// instructions), and the phase carries across frames so the tone is continuous.
+pub struct ToneGenerator {
+    sample_rate: u32,
+    settings: ToneSettings,
+    /// position in the current cycle of the tone, 0.0 to 1.0
+    phase: f32,
+    /// position in the XO-CHIP audio pattern, in bits
+    pattern_position: f32,
+    /// current volume, ramps towards the settings volume or 0.0
+    gain: f32,
+    /// samples owed from earlier frames, in 1/60ths of a sample
+    remainder: u32,
+}
+
+impl ToneGenerator {
+
+    pub fn new(sample_rate: u32, settings: ToneSettings) -> ToneGenerator {
+        ToneGenerator {
+            sample_rate: sample_rate,
+            settings: settings,
+            phase: 0.0,
+            pattern_position: 0.0,
+            gain: 0.0,
+            remainder: 0,
+        }
+    }
+
+    // Appends one frame's worth of samples for the given state
+    pub fn generate_frame(&mut self, state: &State, samples: &mut Vec<f32>) {
+
+        let total = self.sample_rate + self.remainder;
+        self.remainder = total % TIMER_HZ;
+        let count = total / TIMER_HZ;
+
+        let sample_rate = self.sample_rate as f32;
+        let target = if state.beep { self.settings.volume } else { 0.0 };
+        let ramp_step = self.settings.volume.max(f32::EPSILON) / (RAMP_SECONDS * sample_rate);
+        let pattern_rate = PATTERN_BASE_RATE * 2f32.powf((state.pitch as f32 - 64.0) / 48.0);
+
+        for _ in 0..count {
+            // move the gain towards its target a little each sample
+            if self.gain < target {
+                self.gain = (self.gain + ramp_step).min(target);
+            } else if self.gain > target {
+                self.gain = (self.gain - ramp_step).max(target);
+            }
+
+            let value = match state.audio_pattern {
+                Some(pattern) => {
+                    let bit = self.pattern_position as usize % 128;
+                    self.pattern_position = (self.pattern_position + pattern_rate / sample_rate) % 128.0;
+                    if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 { 1.0 } else { -1.0 }
+                },
+                None => {
+                    let value = match self.settings.waveform {
+                        Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+                        Waveform::Sine => (self.phase * 2.0 * PI).sin(),
+                    };
+                    self.phase = (self.phase + self.settings.frequency / sample_rate) % 1.0;
+                    value
+                },
+            };
+
+            samples.push(value * self.gain);
+        }
+    }
+}
+
+// Feeds a queue the sound card drains at its own pace. A frame is only
+// generated while the queue has room for it, so when the emulator runs ahead of
+// the sound card, or fast forwards, whole frames are left out rather than
+// generated and thrown away. What is queued then carries on from exactly where
+// the last queued frame stopped, and the tone doesn't click.
+pub struct FrameQueue {
+    generator: ToneGenerator,
+    /// reused between frames
+    samples: Vec<f32>,
+    /// most samples the queue may hold before frames are left out
+    max_queued: usize,
+}
+
+impl FrameQueue {
+
+    pub fn new(generator: ToneGenerator, max_queued_frames: u32) -> FrameQueue {
+        let max_queued = (generator.sample_rate * max_queued_frames / TIMER_HZ) as usize;
+        FrameQueue {
+            generator: generator,
+            samples: Vec::new(),
+            max_queued: max_queued,
+        }
+    }
+
+    /// Samples to queue for a frame, given how many are still queued. None
+    /// leaves the frame out, and the generator where it was.
+    pub fn next_frame(&mut self, state: &State, queued: usize) -> Option<&[f32]> {
+        if queued >= self.max_queued {
+            return None;
+        }
+        self.samples.clear();
+        self.generator.generate_frame(state, &mut self.samples);
+        Some(&self.samples)
+    }
+}
+
+#[cfg(test)]
+#[path = "./audio_test.rs"]
+mod audio_test;
@@ -0,0 +1,116 @@
+use super::*;
+use crate::{HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH};
+
+const BLANK: [[u8; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT] = [[0; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT];
+
+fn state(beep: bool, audio_pattern: Option<&[u8; 16]>) -> State<'_> {
+    State {
+        video_buffer: &BLANK,
+        video_changed: false,
+        hires: false,
+        beep: beep,
+        audio_pattern: audio_pattern,
+        pitch: 64,
+        exited: false,
+    }
+}
+
+#[test]
+fn test_samples_follow_emulated_time() {
+    // 44101 doesn't divide by 60, the leftover is carried between frames
+    let mut generator = ToneGenerator::new(44101, ToneSettings::default());
+    let mut samples = Vec::new();
+    for _ in 0..60 {
+        generator.generate_frame(&state(true, None), &mut samples);
+    }
+    assert_eq!(samples.len(), 44101);
+}
+
+#[test]
+fn test_silent_without_beep() {
+    let mut generator = ToneGenerator::new(48000, ToneSettings::default());
+    let mut samples = Vec::new();
+    generator.generate_frame(&state(false, None), &mut samples);
+    assert_eq!(samples.len(), 800);
+    assert!(samples.iter().all(|&sample| sample == 0.0));
+}
+
+#[test]
+fn test_start_and_stop_ramp() {
+    let settings = ToneSettings { frequency: 440.0, volume: 0.5, waveform: Waveform::Square };
+    let mut generator = ToneGenerator::new(48000, settings);
+    let ramp_step = 0.5 / (RAMP_SECONDS * 48000.0);
+
+    let mut samples = Vec::new();
+    generator.generate_frame(&state(true, None), &mut samples);
+    generator.generate_frame(&state(false, None), &mut samples);
+
+    // the loudness never changes faster than the ramp allows
+    assert!(samples[0].abs() <= ramp_step + 0.0001);
+    assert!(samples.windows(2).all(|pair| (pair[1].abs() - pair[0].abs()).abs() <= ramp_step + 0.0001));
+    assert!(samples.contains(&0.5));
+    // and fades out within 5ms of the sound timer stopping
+    assert!(samples[800 + 241..].iter().all(|&sample| sample == 0.0));
+}
+
+#[test]
+fn test_sine_is_continuous() {
+    let settings = ToneSettings { frequency: 1000.0, volume: 1.0, waveform: Waveform::Sine };
+    let mut generator = ToneGenerator::new(48000, settings);
+    let mut samples = Vec::new();
+    for _ in 0..3 {
+        generator.generate_frame(&state(true, None), &mut samples);
+    }
+
+    // once ramped up, neighbouring samples are never more than one step of the sine apart
+    let max_step = 2.0 * PI * 1000.0 / 48000.0 + 0.001;
+    assert!(samples[240..].windows(2).all(|pair| (pair[1] - pair[0]).abs() <= max_step));
+}
+
+#[test]
+fn test_audio_pattern() {
+    let settings = ToneSettings { frequency: 440.0, volume: 1.0, waveform: Waveform::Square };
+    let mut generator = ToneGenerator::new(48000, settings);
+    let mut samples = Vec::new();
+
+    let high = [0xFF; 16];
+    generator.generate_frame(&state(true, Some(&high)), &mut samples);
+    assert!(samples.iter().all(|&sample| sample >= 0.0));
+
+    samples.clear();
+    let low = [0x00; 16];
+    generator.generate_frame(&state(true, Some(&low)), &mut samples);
+    assert!(samples.iter().all(|&sample| sample <= 0.0));
+}
+
+#[test]
+fn test_waveform_from_name() {
+    assert_eq!(Waveform::from_name("Sine"), Some(Waveform::Sine));
+    assert_eq!(Waveform::from_name("square"), Some(Waveform::Square));
+    assert_eq!(Waveform::from_name("saw"), None);
+}
+
+#[test]
+fn test_frames_left_out_when_queue_is_full() {
+    let settings = ToneSettings { frequency: 1000.0, volume: 1.0, waveform: Waveform::Sine };
+    let mut frames = FrameQueue::new(ToneGenerator::new(48000, settings), 4);
+    let mut output: Vec<f32> = Vec::new();
+    let mut queued = 0;
+
+    // fast forwarding at 10x, the sound card only plays one frame's worth per host frame
+    for _ in 0..30 {
+        for _ in 0..10 {
+            if let Some(samples) = frames.next_frame(&state(true, None), queued) {
+                output.extend_from_slice(samples);
+                queued += samples.len();
+            }
+        }
+        queued -= queued.min(800);
+    }
+
+    // the queue fills up once, after that one frame is queued for each one played
+    assert_eq!(output.len(), (4 + 29) * 800);
+    // and what was queued joins up, one step of the sine apart at most
+    let max_step = 2.0 * PI * 1000.0 / 48000.0 + 0.001;
+    assert!(output[240..].windows(2).all(|pair| (pair[1] - pair[0]).abs() <= max_step));
+}
@@ -1,21 +1,50 @@
-use rand;
-use rand::Rng;
-
 use crate::Rom;
 
-use crate::font::FONT;
+use crate::fault::{CpuFault, FaultAction, FaultPolicy, StepOutcome};
+use crate::font::{FONT, LARGE_FONT};
+use crate::instruction::Instruction;
+use crate::quirks::Quirks;
+use crate::random::{RandomSource, XorShift};
+use crate::savestate::{self, SaveStateError, StateReader, StateWriter};
+use crate::scheduler::{Scheduler, DEFAULT_INSTRUCTIONS_PER_SECOND};
+use crate::trace::{Snapshot, Tracer};
+use crate::watch::{Access, WatchHit, WatchTarget, Watchpoint};
 use crate::SCREEN_HEIGHT;
 use crate::SCREEN_WIDTH;
+use crate::HIRES_SCREEN_HEIGHT;
+use crate::HIRES_SCREEN_WIDTH;
 
 
 const OPCODE_SIZE: u16 = 2;
+/// XO-CHIP programs can address a full 64kb
+pub const MEMORY_SIZE: usize = 0x10000;
+pub const PROGRAM_START: usize = 0x200;
+const FONT_ADDRESS: u16 = 0x50;
+const LARGE_FONT_ADDRESS: u16 = 0xA0;
 
 // Again references https://github.com/starrhorne/chip8-rust/blob/master/src/processor.rs#L11
 pub struct State<'a> {
     // Lifetime, will refernece the video buffer array from cpu
-    pub video_buffer: &'a [[u8; SCREEN_WIDTH]; SCREEN_HEIGHT],
+    pub video_buffer: &'a [[u8; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT],
     pub video_changed: bool,
+    /// when false only the top left SCREEN_WIDTH x SCREEN_HEIGHT of the buffer is in use
+    pub hires: bool,
     pub beep: bool,
+    /// XO-CHIP 1-bit audio pattern, None until the program loads one with F002
+    pub audio_pattern: Option<&'a [u8; 16]>,
+    /// XO-CHIP playback rate of the audio pattern, 64 is 4000 samples a second
+    pub pitch: u8,
+    /// set once the program runs 00FD
+    pub exited: bool,
+}
+
+// A chip-8 key going down or coming up. Frontends that see key events should
+// pass them on with CPU::key_event, so a key tapped between two frames still
+// reaches FX0A.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyEvent {
+    Down(u8),
+    Up(u8),
 }
 
 struct Registers {
@@ -27,6 +56,16 @@ struct Registers {
     delay_timer: u8,
     sound_timer: u8,
     keypad: [bool; 16],
+    /// keys that went down or came up since the start of the frame, one bit per key
+    keys_pressed: u16,
+    keys_released: u16,
+    /// key FX0A saw go down and is waiting to come up, with the release quirk on
+    waiting_key: Option<u8>,
+    /// SUPER-CHIP user flags (RPL registers on the HP-48), saved with FX75
+    rpl_flags: [u8; 16],
+    /// XO-CHIP audio pattern buffer and pitch register
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
 }
 
 struct Stack {
@@ -38,17 +77,50 @@ struct Stack {
 }
 
 pub struct CPU {
-    /// 4kb of internal memory
-    pub memory: [u8; 4096],
+    /// 64kb of internal memory, original programs only use the first 4kb
+    pub memory: [u8; MEMORY_SIZE],
     /// program counter, points to current memory location, should be >= 0x000
     program_counter: u16,
     /// Chip registers
     registers: Registers,
     /// stack for subroutines
     stack: Stack,
-    /// video location
-    pub video_buffer: [[u8; SCREEN_WIDTH]; SCREEN_HEIGHT],
+    /// video location, sized for hires mode
+    pub video_buffer: [[u8; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT],
     video_changed: bool,
+    /// SUPER-CHIP 128x64 mode
+    hires: bool,
+    /// XO-CHIP bitplanes affected by drawing, clearing and scrolling. Each pixel in
+    /// the video buffer holds one bit per plane.
+    selected_planes: u8,
+    /// set by 00FD, nothing more runs after this
+    exited: bool,
+    /// set when a fault stops the cpu under the halt policy
+    halted: bool,
+    /// how each kind of fault is handled
+    fault_policy: FaultPolicy,
+    /// set at each timer tick, cleared by a draw when the display wait quirk is on
+    vblank: bool,
+    /// behaviors that differ between interpreters
+    quirks: Quirks,
+    /// decides how many instructions run in each 60hz frame
+    scheduler: Scheduler,
+    /// source of CXNN's random numbers
+    rng: Box<dyn RandomSource>,
+    /// instructions run since the cpu was created, including ones waiting on a key
+    instruction_count: u64,
+    /// logs each instruction run, None when tracing is off
+    tracer: Option<Tracer>,
+    /// watchpoints by number, see the watch module
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_watchpoint: usize,
+    /// hits since they were last taken
+    watch_hits: Vec<WatchHit>,
+    /// set by a watchpoint that pauses, cleared by resume
+    paused: bool,
+    /// registers the current instruction wrote, one bit each for V0-VF then I,
+    /// DT and ST, so register watchpoints see writes that don't change the value
+    registers_written: u32,
 }
 
 
@@ -59,6 +131,7 @@ enum PCActions {
     Skip,
     StepBack,
     Jump(u16),
+    Fault(CpuFault),
 }
 
 impl PCActions {
@@ -69,8 +142,13 @@ impl PCActions {
 
 impl CPU {
 
-    /// constructor
-    pub fn new() -> CPU {
+    /// constructor, random numbers are seeded from the operating system
+    pub fn new(quirks: Quirks) -> CPU {
+        CPU::with_rng(quirks, Box::new(XorShift::from_entropy()))
+    }
+
+    /// constructor with a chosen random source, so runs can be repeated
+    pub fn with_rng(quirks: Quirks, rng: Box<dyn RandomSource>) -> CPU {
 
         // load in built in fonts into memory
         let mem = CPU::load_fonts();
@@ -84,23 +162,99 @@ impl CPU {
                 delay_timer: 0,
                 sound_timer: 0,
                 keypad: [false; 16],
+                keys_pressed: 0,
+                keys_released: 0,
+                waiting_key: None,
+                rpl_flags: [0; 16],
+                audio_pattern: None,
+                pitch: 64,
             },
             stack: Stack {
                 addresses: [0; 16],
                 stack_pointer: 0,
             },
-            video_buffer: [[0; SCREEN_WIDTH]; SCREEN_HEIGHT],
+            video_buffer: [[0; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT],
             video_changed: false,
+            hires: false,
+            selected_planes: 1,
+            exited: false,
+            halted: false,
+            fault_policy: FaultPolicy::default(),
+            vblank: false,
+            quirks: quirks,
+            scheduler: Scheduler::new(DEFAULT_INSTRUCTIONS_PER_SECOND),
+            rng: rng,
+            instruction_count: 0,
+            tracer: None,
+            watchpoints: Vec::new(),
+            next_watchpoint: 1,
+            watch_hits: Vec::new(),
+            paused: false,
+            registers_written: 0,
         }
     }
 
+    /// Sets what happens when the rom does something the cpu can't carry on from
+    pub fn set_fault_policy(&mut self, fault_policy: FaultPolicy) {
+        self.fault_policy = fault_policy;
+    }
+
+    /// Starts logging every instruction run, see the trace module
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Stops tracing and hands back the tracer, so it can be finished
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Adds a watchpoint, returning the number it's known by
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.next_watchpoint;
+        self.next_watchpoint += 1;
+        self.watchpoints.push((id, watchpoint));
+        id
+    }
+
+    /// Removes a watchpoint by number, false if there's no such watchpoint
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|&(watchpoint_id, _)| watchpoint_id != id);
+        self.watchpoints.len() < count
+    }
+
+    pub fn watchpoints(&self) -> &[(usize, Watchpoint)] {
+        &self.watchpoints
+    }
+
+    /// Watchpoint hits since the last call, oldest first
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    /// Whether a watchpoint has paused the cpu
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Carries on after a watchpoint paused the cpu
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Sets how many instructions run per second of emulated time
+    pub fn set_speed(&mut self, instructions_per_second: u32) {
+        self.scheduler.set_instructions_per_second(instructions_per_second);
+    }
+
     pub fn load_rom(&mut self, rom: Rom) {
 
         let mem = &rom.memory[..rom.size];
 
         for (i, &byte) in mem.iter().enumerate() {
 
-            let memory_address = 0x200 + i;
+            let memory_address = PROGRAM_START + i;
 
             if memory_address < self.memory.len() {
                 self.memory[memory_address] = byte;
@@ -109,23 +263,77 @@ impl CPU {
 
     }
 
-    fn load_fonts() -> [u8; 4096] {
+    fn load_fonts() -> [u8; MEMORY_SIZE] {
 
-        let mut mem = [0; 4096];
-        let font_start_address = 0x50;
+        let mut mem = [0; MEMORY_SIZE];
 
         for (i, &byte) in FONT.iter().enumerate() {
-            mem[font_start_address + i] = byte;
+            mem[FONT_ADDRESS as usize + i] = byte;
+        }
+
+        for (i, &byte) in LARGE_FONT.iter().enumerate() {
+            mem[LARGE_FONT_ADDRESS as usize + i] = byte;
         }
 
         mem
     }
 
-    // Represents an operation occuring and the things that happen around that
-    pub fn tick(&mut self, keypad: [bool; 16]) -> State {
+    // Runs one 60hz frame of emulated time: this frame's share of instructions,
+    // then a single tick of the delay and sound timers. A fault that halts the
+    // cpu ends the frame early and is returned instead.
+    pub fn run_frame(&mut self, keypad: [bool; 16]) -> Result<State<'_>, CpuFault> {
+
+        self.begin_frame(keypad);
+
+        for _ in 0..self.scheduler.instructions_for_frame() {
+            match self.step()? {
+                StepOutcome::Exited | StepOutcome::Halted | StepOutcome::Paused => break,
+                StepOutcome::Executed | StepOutcome::Waiting => (),
+            }
+        }
+
+        self.end_frame();
+
+        Ok(self.state())
+
+    }
 
-        self.registers.keypad = keypad;
+    // Starts a frame for callers stepping instructions themselves: latches the
+    // keypad and opens the vertical blank. Keys that changed since the last
+    // frame count as key events, so frontends that only know which keys are
+    // held work too.
+    pub fn begin_frame(&mut self, keypad: [bool; 16]) {
+        for (key, &down) in keypad.iter().enumerate() {
+            self.key_event(if down { KeyEvent::Down(key as u8) } else { KeyEvent::Up(key as u8) });
+        }
         self.video_changed = false;
+        self.vblank = true;
+    }
+
+    // Presses or releases a key before the next frame. Events that don't change
+    // the key, a press of a key that's already down, are ignored.
+    pub fn key_event(&mut self, event: KeyEvent) {
+        let (key, down) = match event {
+            KeyEvent::Down(key) => (key as usize & 0x0F, true),
+            KeyEvent::Up(key) => (key as usize & 0x0F, false),
+        };
+        if self.registers.keypad[key] == down {
+            return;
+        }
+        self.registers.keypad[key] = down;
+        if down {
+            self.registers.keys_pressed |= 1 << key;
+        } else {
+            self.registers.keys_released |= 1 << key;
+        }
+    }
+
+    // Ends a frame, delay and sound registers count down once per frame while non-zero
+    pub fn end_frame(&mut self) {
+        // presses FX0A didn't pick up this frame are gone, a key held since
+        // has to be pressed again
+        self.registers.keys_pressed = 0;
+        self.registers.keys_released = 0;
 
         if self.registers.delay_timer > 0 {
             self.registers.delay_timer -= 1
@@ -133,104 +341,619 @@ impl CPU {
         if self.registers.sound_timer > 0 {
             self.registers.sound_timer -= 1
         }
-        let operation = self.get_operation();
-        self.run_operation(operation);
+    }
 
+    // What a frontend needs to present the current frame
+    pub fn state(&self) -> State<'_> {
         State {
             video_buffer: &self.video_buffer,
             video_changed: self.video_changed,
+            hires: self.hires,
             beep: self.registers.sound_timer > 0,
+            audio_pattern: self.registers.audio_pattern.as_ref(),
+            pitch: self.registers.pitch,
+            exited: self.exited,
+        }
+    }
+
+    /// Instructions run since the cpu was created, for measuring speed
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    // Inspection and editing for debuggers. Setters take effect from the next
+    // instruction, nothing checks that the values make sense to the rom.
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.program_counter = address;
+    }
+
+    /// V0-VF
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers.general_registers
+    }
+
+    pub fn set_register(&mut self, register: usize, value: u8) {
+        self.registers.general_registers[register & 0x0F] = value;
+    }
+
+    pub fn index(&self) -> u16 {
+        self.registers.index
+    }
+
+    pub fn set_index(&mut self, index: u16) {
+        self.registers.index = index;
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.registers.delay_timer
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.registers.delay_timer = value;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.registers.sound_timer
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.registers.sound_timer = value;
+    }
+
+    /// Return addresses of the subroutines being run, outermost first
+    pub fn stack(&self) -> &[u16] {
+        &self.stack.addresses[..self.stack.stack_pointer as usize]
+    }
+
+    /// Set once a fault has stopped the cpu under the halt policy
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// The instruction at the program counter, None if it isn't a valid opcode
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        self.get_operation().ok().and_then(Instruction::decode)
+    }
+
+    // Snapshot of everything needed to carry on from exactly this point, see
+    // the savestate module for the format. The speed and fault policy are
+    // settings of the frontend and aren't included.
+    pub fn save_state(&self) -> Vec<u8> {
+
+        let mut writer = StateWriter::new();
+
+        writer.bytes(&self.memory);
+        writer.u16(self.program_counter);
+
+        writer.bytes(&self.registers.general_registers);
+        writer.u16(self.registers.index);
+        writer.u8(self.registers.delay_timer);
+        writer.u8(self.registers.sound_timer);
+        writer.u16(self.registers.keypad.iter().rev().fold(0, |bits, &down| bits << 1 | down as u16));
+        writer.bool(self.registers.waiting_key.is_some());
+        writer.u8(self.registers.waiting_key.unwrap_or(0));
+        writer.bytes(&self.registers.rpl_flags);
+        writer.bool(self.registers.audio_pattern.is_some());
+        writer.bytes(&self.registers.audio_pattern.unwrap_or([0; 16]));
+        writer.u8(self.registers.pitch);
+
+        for &address in self.stack.addresses.iter() {
+            writer.u16(address);
+        }
+        writer.u8(self.stack.stack_pointer);
+
+        // pixels only use the low two bits, so four of them are packed per byte
+        for row in self.video_buffer.iter() {
+            for pixels in row.chunks(4) {
+                writer.u8(pixels.iter().enumerate().fold(0, |byte, (i, &pixel)| byte | (pixel & 0b11) << (i * 2)));
+            }
+        }
+        writer.bool(self.hires);
+        writer.u8(self.selected_planes);
+
+        writer.bool(self.exited);
+        writer.bool(self.halted);
+        writer.bool(self.vblank);
+        writer.u8(savestate::quirks_to_bits(&self.quirks));
+        writer.u32(self.scheduler.remainder());
+        writer.u64(self.rng.state());
+
+        writer.finish()
+    }
+
+    // Restores a snapshot taken by save_state. The whole state is checked before
+    // anything is changed, so a bad state leaves the cpu as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+
+        let mut reader = StateReader::new(data)?;
+
+        let mut memory = [0; MEMORY_SIZE];
+        memory.copy_from_slice(reader.bytes(MEMORY_SIZE)?);
+        let program_counter = reader.u16()?;
+
+        let mut general_registers = [0; 16];
+        general_registers.copy_from_slice(reader.bytes(16)?);
+        let index = reader.u16()?;
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let keys = reader.u16()?;
+        let mut keypad = [false; 16];
+        for (key, down) in keypad.iter_mut().enumerate() {
+            *down = keys & (1 << key) != 0;
+        }
+        let is_waiting_key = reader.bool()?;
+        let waiting_key = reader.u8()?;
+        if waiting_key > 0x0F {
+            return Err(SaveStateError::Corrupt("waiting key out of range"));
+        }
+        let mut rpl_flags = [0; 16];
+        rpl_flags.copy_from_slice(reader.bytes(16)?);
+        let has_audio_pattern = reader.bool()?;
+        let mut audio_pattern = [0; 16];
+        audio_pattern.copy_from_slice(reader.bytes(16)?);
+        let pitch = reader.u8()?;
+
+        let mut addresses = [0; 16];
+        for address in addresses.iter_mut() {
+            *address = reader.u16()?;
+        }
+        let stack_pointer = reader.u8()?;
+        if stack_pointer as usize > addresses.len() {
+            return Err(SaveStateError::Corrupt("stack pointer out of range"));
+        }
+
+        let mut video_buffer = [[0; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT];
+        for row in video_buffer.iter_mut() {
+            for pixels in row.chunks_mut(4) {
+                let byte = reader.u8()?;
+                for (i, pixel) in pixels.iter_mut().enumerate() {
+                    *pixel = (byte >> (i * 2)) & 0b11;
+                }
+            }
+        }
+        let hires = reader.bool()?;
+        let selected_planes = reader.u8()?;
+        if selected_planes > 0b11 {
+            return Err(SaveStateError::Corrupt("selected planes out of range"));
+        }
+
+        let exited = reader.bool()?;
+        let halted = reader.bool()?;
+        let vblank = reader.bool()?;
+        let quirks = savestate::quirks_from_bits(reader.u8()?);
+        let remainder = reader.u32()?;
+        let rng_state = reader.u64()?;
+        reader.finish()?;
+
+        self.memory = memory;
+        self.program_counter = program_counter;
+        self.registers = Registers {
+            general_registers: general_registers,
+            index: index,
+            delay_timer: delay_timer,
+            sound_timer: sound_timer,
+            keypad: keypad,
+            keys_pressed: 0,
+            keys_released: 0,
+            waiting_key: if is_waiting_key { Some(waiting_key) } else { None },
+            rpl_flags: rpl_flags,
+            audio_pattern: if has_audio_pattern { Some(audio_pattern) } else { None },
+            pitch: pitch,
+        };
+        self.stack = Stack {
+            addresses: addresses,
+            stack_pointer: stack_pointer,
+        };
+        self.video_buffer = video_buffer;
+        self.hires = hires;
+        self.selected_planes = selected_planes;
+        self.exited = exited;
+        self.halted = halted;
+        self.vblank = vblank;
+        self.quirks = quirks;
+        self.scheduler.set_remainder(remainder);
+        self.rng.set_state(rng_state);
+        // the frontend has to redraw whatever screen was restored
+        self.video_changed = true;
+
+        Ok(())
+    }
+
+    // Represents an operation occuring, fetching and running the instruction at the
+    // program counter. Faults are handled by the fault policy, only halting ones
+    // are returned.
+    pub fn step(&mut self) -> Result<StepOutcome, CpuFault> {
+
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+        if self.halted {
+            return Ok(StepOutcome::Halted);
+        }
+        if self.paused {
+            return Ok(StepOutcome::Paused);
+        }
+
+        self.instruction_count += 1;
+
+        let pc = self.program_counter;
+        let operation = self.get_operation();
+
+        // registers are only looked at when the instruction is traced or watched
+        let traced = match (&self.tracer, &operation) {
+            (Some(tracer), &Ok(operation)) if tracer.wants(pc, operation) => Some(operation),
+            _ => None,
+        };
+        let watching_registers = self.watchpoints.iter().any(|(_, watchpoint)| !watchpoint.is_memory());
+        let before = if traced.is_some() || watching_registers { Some(self.snapshot()) } else { None };
+        let hits_before = self.watch_hits.len();
+        self.registers_written = 0;
+
+        let result = match operation {
+            Ok(operation) => self.run_operation(operation),
+            Err(fault) => Err(fault),
+        };
+
+        if let Some(before) = before {
+            let after = self.snapshot();
+            if watching_registers {
+                self.watch_registers(pc, &before, &after);
+            }
+            if let Some(operation) = traced {
+                let long = self.read_word(pc.wrapping_add(OPCODE_SIZE));
+                if let Some(ref mut tracer) = self.tracer {
+                    tracer.record(self.instruction_count, pc, operation, long, &before, &after);
+                }
+            }
+        }
+
+        let outcome = match result {
+            Ok(outcome) => Ok(outcome),
+            Err(fault) => match self.fault_policy.action(fault.kind()) {
+                FaultAction::Halt => {
+                    self.halted = true;
+                    Err(fault)
+                },
+                FaultAction::Log => {
+                    eprintln!("{}", fault);
+                    self.skip_faulting_instruction();
+                    Ok(StepOutcome::Executed)
+                },
+                FaultAction::Ignore => {
+                    self.skip_faulting_instruction();
+                    Ok(StepOutcome::Executed)
+                },
+            },
+        };
+
+        // the instruction that hit a pausing watchpoint has still run
+        if self.watch_hits[hits_before..].iter().any(|hit| hit.pause) {
+            self.paused = true;
+            if let Ok(StepOutcome::Executed) | Ok(StepOutcome::Waiting) = outcome {
+                return Ok(StepOutcome::Paused);
+            }
+        }
+
+        outcome
+    }
+
+    // The registers a trace reports changes to
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.registers.general_registers,
+            index: self.registers.index,
+            delay_timer: self.registers.delay_timer,
+            sound_timer: self.registers.sound_timer,
+        }
+    }
+
+    // Reads a byte of memory for an instruction, which read watchpoints see
+    fn read_memory(&mut self, address: usize) -> u8 {
+        let value = self.memory[address];
+        if !self.watchpoints.is_empty() {
+            self.watch_memory(address, Access::Read, value, value);
+        }
+        value
+    }
+
+    // Writes a byte of memory for an instruction, which write watchpoints see
+    fn write_memory(&mut self, address: usize, value: u8) {
+        if !self.watchpoints.is_empty() {
+            let old = self.memory[address];
+            self.watch_memory(address, Access::Write, old, value);
+        }
+        self.memory[address] = value;
+    }
+
+    fn watch_memory(&mut self, address: usize, access: Access, old: u8, new: u8) {
+        for &(id, ref watchpoint) in self.watchpoints.iter() {
+            if watchpoint.hits_memory(address, access, new) {
+                self.watch_hits.push(WatchHit {
+                    id: id,
+                    pc: self.program_counter,
+                    target: WatchTarget::Memory(address as u16, address as u16),
+                    access: access,
+                    old: old as u16,
+                    new: new as u16,
+                    pause: watchpoint.pause,
+                });
+            }
+        }
+    }
+
+    // Records hits on watched registers the instruction at pc wrote, whether
+    // or not the value changed
+    fn watch_registers(&mut self, pc: u16, before: &Snapshot, after: &Snapshot) {
+        let mut writes = Vec::new();
+        for x in 0..16 {
+            writes.push((WatchTarget::V(x as u8), before.registers[x] as u16, after.registers[x] as u16));
         }
+        writes.push((WatchTarget::Index, before.index, after.index));
+        writes.push((WatchTarget::DelayTimer, before.delay_timer as u16, after.delay_timer as u16));
+        writes.push((WatchTarget::SoundTimer, before.sound_timer as u16, after.sound_timer as u16));
+
+        let written = self.registers_written;
+        for (bit, (target, old, new)) in writes.into_iter().enumerate() {
+            if written & (1 << bit) == 0 {
+                continue;
+            }
+            for &(id, ref watchpoint) in self.watchpoints.iter() {
+                if watchpoint.hits_register(target, new) {
+                    self.watch_hits.push(WatchHit {
+                        id: id,
+                        pc: pc,
+                        target: target,
+                        access: Access::Write,
+                        old: old,
+                        new: new,
+                        pause: watchpoint.pause,
+                    });
+                }
+            }
+        }
+    }
 
+    // Register writes made by instructions, which register watchpoints see.
+    // The bits match the order watch_registers lists the registers in.
+    fn write_register(&mut self, register: usize, value: u8) {
+        self.registers.general_registers[register] = value;
+        self.registers_written |= 1 << register;
+    }
+
+    fn write_index(&mut self, index: u16) {
+        self.registers.index = index;
+        self.registers_written |= 1 << 16;
+    }
+
+    fn write_delay_timer(&mut self, value: u8) {
+        self.registers.delay_timer = value;
+        self.registers_written |= 1 << 17;
+    }
+
+    fn write_sound_timer(&mut self, value: u8) {
+        self.registers.sound_timer = value;
+        self.registers_written |= 1 << 18;
+    }
+
+    // Carries on past a fault as if the instruction wasn't there
+    fn skip_faulting_instruction(&mut self) {
+        self.program_counter = self.program_counter.wrapping_add(OPCODE_SIZE);
+    }
+
+    // Width of the screen in the current resolution
+    fn screen_width(&self) -> usize {
+        if self.hires { HIRES_SCREEN_WIDTH } else { SCREEN_WIDTH }
+    }
+
+    // Height of the screen in the current resolution
+    fn screen_height(&self) -> usize {
+        if self.hires { HIRES_SCREEN_HEIGHT } else { SCREEN_HEIGHT }
     }
 
     // Take the next two codes and combine them into an u16 bit opcode
-    fn get_operation(&self) -> u16 {
-        (self.memory[self.program_counter as usize] as u16) << 8 | (self.memory[(self.program_counter + 1) as usize] as u16)
-    }
-
-    fn run_operation(&mut self, operation: u16) {
-        // mask out 4 nibbles
-        let nibbles = (
-            (operation & 0xF000) >> 12 as u8,
-            (operation & 0x0F00) >> 8 as u8,
-            (operation & 0x00F0) >> 4 as u8,
-            (operation & 0x000F) as u8,
-        );
-
-        // mask out last three nibbles for some operations
-        let nnn = (operation & 0x0FFF) as u16;
-        // mask out 2 nibbles
-        let kk = (operation & 0x00FF) as u8;
-        // get individual reference to last three nibbles
-        let x = nibbles.1 as usize;
-        let y = nibbles.2 as usize;
-        let n = nibbles.3 as usize;
-
-        let pc_action = match nibbles { 
-            (0x00, 0x00, 0x0e, 0x00) => self.clear(),
-            (0x00, 0x00, 0x0e, 0x0e) => self.return_operation(),
-            (0x01, _, _, _) => CPU::jump(nnn),
-            (0x02, _, _, _) => self.call(nnn),
-            (0x03, _, _, _) => self.skip_is_equal(x, kk),
-            (0x04, _, _, _) => self.skip_is_not_equal(x, kk),
-            (0x05, _, _, 0x00) => self.compare_registers(x, y),
-            (0x06, _, _, _) => self.load(x, kk),
-            (0x07, _, _, _) => self.add_to_register(x, kk),
-            (0x08, _, _, 0x00) => self.load_from_register(x, y),
-            (0x08, _, _, 0x01) => self.set_or(x, y),
-            (0x08, _, _, 0x02) => self.set_and(x, y),
-            (0x08, _, _, 0x03) => self.set_xor(x, y),
-            (0x08, _, _, 0x04) => self.add_from_register(x, y),
-            (0x08, _, _, 0x05) => self.subtract_from_register(x, y),
-            (0x08, _, _, 0x06) => self.shift_right(x),
-            (0x08, _, _, 0x07) => self.subtract_no_borrow_from_register(x, y),
-            (0x08, _, _, 0x0e) => self.shift_left(x),
-            (0x09, _, _, 0x00) => self.skip_is_not_equal_register(x, y),
-            (0x0a, _, _, _) => self.load_index(nnn),
-            (0x0b, _, _, _) => self.jump_plus_vo(nnn),
-            (0x0c, _, _, _) => self.random(x, kk),
-            (0x0d, _, _, _) => self.display(x, y, n),
-            (0x0e, _, 0x09, 0x0e) => self.skip_if_key(x),
-            (0x0e, _, 0x0a, 0x01) => self.skip_if_not_key(x),
-            (0x0f, _, 0x00, 0x07) => self.load_register_from_delay(x),
-            (0x0f, _, 0x00, 0x0a) => self.wait_key_press(x),
-            (0x0f, _, 0x01, 0x05) => self.load_delay_from_register(x),
-            (0x0f, _, 0x01, 0x08) => self.load_sound_from_register(x),
-            (0x0f, _, 0x01, 0x0e) => self.add_index(x),
-            (0x0f, _, 0x02, 0x09) => self.index_sprite(x),
-            (0x0f, _, 0x03, 0x03) => self.store_bcd(x),
-            (0x0f, _, 0x05, 0x05) => self.store_registers(x),
-            (0x0f, _, 0x06, 0x05) => self.load_registers_from_index(x),
-            _ => PCActions::Next,
+    fn get_operation(&self) -> Result<u16, CpuFault> {
+        if self.program_counter as usize + 1 >= MEMORY_SIZE {
+            return Err(CpuFault::PcOutOfRange { pc: self.program_counter });
+        }
+        Ok(self.read_word(self.program_counter))
+    }
+
+    // Checks that length bytes starting at address are all inside memory
+    fn check_memory(&self, address: usize, length: usize) -> Result<(), CpuFault> {
+        if address + length > MEMORY_SIZE {
+            Err(CpuFault::MemoryOutOfBounds { pc: self.program_counter, address: address + length - 1 })
+        } else {
+            Ok(())
+        }
+    }
+
+    // Reads a big endian word, wrapping around the end of memory
+    fn read_word(&self, address: u16) -> u16 {
+        (self.memory[address as usize] as u16) << 8 | (self.memory[address.wrapping_add(1) as usize] as u16)
+    }
+
+    fn run_operation(&mut self, operation: u16) -> Result<StepOutcome, CpuFault> {
+
+        let instruction = match Instruction::decode(operation) {
+            Some(instruction) => instruction,
+            None => return Err(CpuFault::InvalidOpcode { pc: self.program_counter, opcode: operation }),
         };
 
-        match pc_action {
-            PCActions::Next => self.program_counter += OPCODE_SIZE,
-            PCActions::Skip => self.program_counter += 2 * OPCODE_SIZE,
-            PCActions::Jump(addr) => self.program_counter = addr,
-            PCActions::StepBack => (),
+        self.execute(instruction)
+    }
+
+    // Runs a decoded instruction and moves the program counter along
+    fn execute(&mut self, instruction: Instruction) -> Result<StepOutcome, CpuFault> {
+
+        let pc_action = match instruction {
+            Instruction::ScrollDown(n) => self.scroll_down(n as usize),
+            Instruction::ScrollUp(n) => self.scroll_up(n as usize),
+            Instruction::Clear => self.clear(),
+            Instruction::Return => self.return_operation(),
+            Instruction::ScrollRight => self.scroll_right(),
+            Instruction::ScrollLeft => self.scroll_left(),
+            Instruction::Exit => self.exit(),
+            Instruction::LowRes => self.set_resolution(false),
+            Instruction::HighRes => self.set_resolution(true),
+            Instruction::Jump(nnn) => CPU::jump(nnn),
+            Instruction::Call(nnn) => self.call(nnn),
+            Instruction::SkipEqualByte(x, kk) => self.skip_is_equal(x as usize, kk),
+            Instruction::SkipNotEqualByte(x, kk) => self.skip_is_not_equal(x as usize, kk),
+            Instruction::SkipEqual(x, y) => self.compare_registers(x as usize, y as usize),
+            Instruction::SaveRange(x, y) => self.store_register_range(x as usize, y as usize),
+            Instruction::LoadRange(x, y) => self.load_register_range(x as usize, y as usize),
+            Instruction::LoadByte(x, kk) => self.load(x as usize, kk),
+            Instruction::AddByte(x, kk) => self.add_to_register(x as usize, kk),
+            Instruction::Load(x, y) => self.load_from_register(x as usize, y as usize),
+            Instruction::Or(x, y) => self.set_or(x as usize, y as usize),
+            Instruction::And(x, y) => self.set_and(x as usize, y as usize),
+            Instruction::Xor(x, y) => self.set_xor(x as usize, y as usize),
+            Instruction::Add(x, y) => self.add_from_register(x as usize, y as usize),
+            Instruction::Subtract(x, y) => self.subtract_from_register(x as usize, y as usize),
+            Instruction::ShiftRight(x, y) => self.shift_right(x as usize, y as usize),
+            Instruction::SubtractNegated(x, y) => self.subtract_no_borrow_from_register(x as usize, y as usize),
+            Instruction::ShiftLeft(x, y) => self.shift_left(x as usize, y as usize),
+            Instruction::SkipNotEqual(x, y) => self.skip_is_not_equal_register(x as usize, y as usize),
+            Instruction::LoadIndex(nnn) => self.load_index(nnn),
+            Instruction::JumpOffset(nnn) => self.jump_plus_vo((nnn >> 8) as usize, nnn),
+            Instruction::Random(x, kk) => self.random(x as usize, kk),
+            Instruction::Draw(x, y, n) => self.display(x as usize, y as usize, n as usize),
+            Instruction::SkipKey(x) => self.skip_if_key(x as usize),
+            Instruction::SkipNotKey(x) => self.skip_if_not_key(x as usize),
+            Instruction::LoadLongIndex => self.load_long_index(),
+            Instruction::SelectPlanes(n) => self.select_planes(n as usize),
+            Instruction::LoadAudio => self.load_audio_pattern(),
+            Instruction::LoadDelay(x) => self.load_register_from_delay(x as usize),
+            Instruction::WaitKey(x) => self.wait_key_press(x as usize),
+            Instruction::SetDelay(x) => self.load_delay_from_register(x as usize),
+            Instruction::SetSound(x) => self.load_sound_from_register(x as usize),
+            Instruction::AddIndex(x) => self.add_index(x as usize),
+            Instruction::LoadFont(x) => self.index_sprite(x as usize),
+            Instruction::LoadLargeFont(x) => self.index_large_sprite(x as usize),
+            Instruction::StoreBcd(x) => self.store_bcd(x as usize),
+            Instruction::SetPitch(x) => self.load_pitch(x as usize),
+            Instruction::StoreRegisters(x) => self.store_registers(x as usize),
+            Instruction::LoadRegisters(x) => self.load_registers_from_index(x as usize),
+            Instruction::StoreFlags(x) => self.store_flags(x as usize),
+            Instruction::LoadFlags(x) => self.load_flags(x as usize),
+        };
+
+        let next = match pc_action {
+            PCActions::Next => self.program_counter.checked_add(OPCODE_SIZE),
+            PCActions::Skip => self.program_counter.checked_add(self.skip_size()),
+            PCActions::Jump(addr) => Some(addr),
+            PCActions::StepBack => {
+                return Ok(if self.exited { StepOutcome::Exited } else { StepOutcome::Waiting });
+            },
+            PCActions::Fault(fault) => return Err(fault),
+        };
+
+        match next {
+            Some(addr) if (addr as usize) < MEMORY_SIZE - 1 => {
+                self.program_counter = addr;
+                Ok(StepOutcome::Executed)
+            },
+            _ => Err(CpuFault::PcOutOfRange { pc: self.program_counter }),
         }
 
     }
 
-    // CLS: Clears video memory
+    // A skipped F000 NNNN is four bytes long, so step over the address as well
+    fn skip_size(&self) -> u16 {
+        if self.read_word(self.program_counter.wrapping_add(OPCODE_SIZE)) == 0xF000 {
+            3 * OPCODE_SIZE
+        } else {
+            2 * OPCODE_SIZE
+        }
+    }
+
+    // CLS: Clears the selected planes of video memory
     fn clear(&mut self) -> PCActions {
 
-        for y in 0..SCREEN_HEIGHT {
-            for x in 0..SCREEN_WIDTH {
-                self.video_buffer[y][x] = 0;
+        for y in 0..HIRES_SCREEN_HEIGHT {
+            for x in 0..HIRES_SCREEN_WIDTH {
+                self.video_buffer[y][x] &= !self.selected_planes;
+            }
+        }
+
+        self.video_changed = true;
+
+        PCActions::Next
+    }
+
+    // SCD n: Scrolls the screen down n pixels, blank rows come in at the top
+    fn scroll_down(&mut self, rows: usize) -> PCActions {
+        self.scroll(0, rows as isize)
+    }
+
+    // SCU n: XO-CHIP, scrolls the screen up n pixels
+    fn scroll_up(&mut self, rows: usize) -> PCActions {
+        self.scroll(0, -(rows as isize))
+    }
+
+    // SCR: Scrolls the screen right 4 pixels
+    fn scroll_right(&mut self) -> PCActions {
+        self.scroll(4, 0)
+    }
+
+    // SCL: Scrolls the screen left 4 pixels
+    fn scroll_left(&mut self) -> PCActions {
+        self.scroll(-4, 0)
+    }
+
+    // Moves the selected planes by (dx, dy) pixels, whatever moves off the edge is lost
+    fn scroll(&mut self, dx: isize, dy: isize) -> PCActions {
+
+        let width = self.screen_width() as isize;
+        let height = self.screen_height() as isize;
+        let planes = self.selected_planes;
+
+        // walk against the direction of travel so pixels are read before being overwritten
+        let rows: Vec<isize> = if dy > 0 { (0..height).rev().collect() } else { (0..height).collect() };
+        let cols: Vec<isize> = if dx > 0 { (0..width).rev().collect() } else { (0..width).collect() };
+
+        for &y in rows.iter() {
+            for &x in cols.iter() {
+                let (source_x, source_y) = (x - dx, y - dy);
+                let moved = if source_x >= 0 && source_x < width && source_y >= 0 && source_y < height {
+                    self.video_buffer[source_y as usize][source_x as usize] & planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.video_buffer[y as usize][x as usize];
+                *pixel = (*pixel & !planes) | moved;
             }
         }
 
         self.video_changed = true;
+        PCActions::Next
+    }
 
+    // EXIT: Stops the interpreter, the program counter stays on this instruction
+    fn exit(&mut self) -> PCActions {
+        self.exited = true;
+        PCActions::StepBack
+    }
+
+    // LOW/HIGH: Switches between 64x32 and 128x64, every plane is cleared on a switch
+    fn set_resolution(&mut self, hires: bool) -> PCActions {
+        self.hires = hires;
+        self.video_buffer = [[0; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT];
+        self.video_changed = true;
         PCActions::Next
     }
 
     // RET: Acts as a return. Decrements the stack and returns
     // to the location on top of stack.
     fn return_operation(&mut self) -> PCActions {
+        if self.stack.stack_pointer == 0 {
+            return PCActions::Fault(CpuFault::StackUnderflow { pc: self.program_counter });
+        }
         self.stack.stack_pointer -= 1;
         PCActions::Jump(self.stack.addresses[self.stack.stack_pointer as usize])
     }
@@ -242,6 +965,9 @@ impl CPU {
 
     // CALL: jumps to address and increments the stack with previous address.
     fn call(&mut self, location: u16) -> PCActions {
+        if self.stack.stack_pointer as usize >= self.stack.addresses.len() {
+            return PCActions::Fault(CpuFault::StackOverflow { pc: self.program_counter });
+        }
         self.stack.addresses[self.stack.stack_pointer as usize] = self.program_counter + OPCODE_SIZE;
         self.stack.stack_pointer += 1;
         PCActions::Jump(location)
@@ -264,48 +990,98 @@ impl CPU {
 
     // Load data into selected register
     fn load(&mut self, register: usize, data: u8) -> PCActions {
-        self.registers.general_registers[register] = data;
+        self.write_register(register, data);
         PCActions::Next
     }
 
     // Adds data to the data in register
     fn add_to_register(&mut self, register: usize, data: u8) -> PCActions {
-        self.registers.general_registers[register] = ((self.registers.general_registers[register] as u16) + (data as u16)) as u8;
+        self.write_register(register, ((self.registers.general_registers[register] as u16) + (data as u16)) as u8);
+        PCActions::Next
+    }
+
+    // Save registers VX through VY to memory starting at Index, without changing Index.
+    // Works in either direction if X is greater than Y.
+    fn store_register_range(&mut self, register_x: usize, register_y: usize) -> PCActions {
+        let index = self.registers.index as usize;
+        let length = CPU::range_length(register_x, register_y);
+        if let Err(fault) = self.check_memory(index, length) {
+            return PCActions::Fault(fault);
+        }
+        for offset in 0..length {
+            let register = CPU::range_register(register_x, register_y, offset);
+            self.write_memory(index + offset, self.registers.general_registers[register]);
+        }
+        PCActions::Next
+    }
+
+    // Load registers VX through VY from memory starting at Index, without changing Index
+    fn load_register_range(&mut self, register_x: usize, register_y: usize) -> PCActions {
+        let index = self.registers.index as usize;
+        let length = CPU::range_length(register_x, register_y);
+        if let Err(fault) = self.check_memory(index, length) {
+            return PCActions::Fault(fault);
+        }
+        for offset in 0..length {
+            let register = CPU::range_register(register_x, register_y, offset);
+            let value = self.read_memory(index + offset);
+            self.write_register(register, value);
+        }
         PCActions::Next
     }
 
+    // Number of registers from X to Y inclusive
+    fn range_length(register_x: usize, register_y: usize) -> usize {
+        if register_x <= register_y { register_y - register_x + 1 } else { register_x - register_y + 1 }
+    }
+
+    // The register at offset in the range from X to Y, counting down if X is greater
+    fn range_register(register_x: usize, register_y: usize, offset: usize) -> usize {
+        if register_x <= register_y { register_x + offset } else { register_x - offset }
+    }
+
     // Load data from register y into register x
     fn load_from_register(&mut self, register_x: usize, register_y: usize) -> PCActions {
-        self.registers.general_registers[register_x] = self.registers.general_registers[register_y];
+        self.write_register(register_x, self.registers.general_registers[register_y]);
         PCActions::Next
     }
 
     // Load the result of the or operation on register y and register x
     fn set_or(&mut self, register_x: usize, register_y: usize) -> PCActions {
-        self.registers.general_registers[register_x] |= self.registers.general_registers[register_y];
+        self.write_register(register_x, self.registers.general_registers[register_x] | self.registers.general_registers[register_y]);
+        self.reset_flag_after_logic();
         PCActions::Next
     }
 
     // Load the result of the and operation on register y and register x
     fn set_and(&mut self, register_x: usize, register_y: usize) -> PCActions {
-        self.registers.general_registers[register_x] &= self.registers.general_registers[register_y];
+        self.write_register(register_x, self.registers.general_registers[register_x] & self.registers.general_registers[register_y]);
+        self.reset_flag_after_logic();
         PCActions::Next
     }
 
     // Load the result of the xor operation on register y and register x
     fn set_xor(&mut self, register_x: usize, register_y: usize) -> PCActions {
-        self.registers.general_registers[register_x] ^= self.registers.general_registers[register_y];
+        self.write_register(register_x, self.registers.general_registers[register_x] ^ self.registers.general_registers[register_y]);
+        self.reset_flag_after_logic();
         PCActions::Next
     }
 
+    // The original interpreter used VF as scratch space for the logic ops
+    fn reset_flag_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.write_register(0x0F, 0);
+        }
+    }
+
     // Load the result of the addition operation on register y and register x
     fn add_from_register(&mut self, register_x: usize, register_y: usize) -> PCActions {
         let x_value = self.registers.general_registers[register_x] as u16;
         let y_value = self.registers.general_registers[register_y] as u16;
         let result = x_value + y_value;
         // Send result to carry register (register vf)
-        self.registers.general_registers[0x0F] = if result > 0xFF { 1 } else { 0 };
-        self.registers.general_registers[register_x] = result as u8;
+        self.write_register(0x0F, if result > 0xFF { 1 } else { 0 });
+        self.write_register(register_x, result as u8);
         PCActions::Next
     }
 
@@ -314,15 +1090,18 @@ impl CPU {
     fn subtract_from_register(&mut self, register_x: usize, register_y: usize) -> PCActions {
         let x_value = self.registers.general_registers[register_x] as u8;
         let y_value = self.registers.general_registers[register_y] as u8;
-        self.registers.general_registers[0x0F] = if x_value > y_value { 1 } else { 0 };
-        self.registers.general_registers[register_x] = x_value.wrapping_sub(y_value);
+        self.write_register(0x0F, if x_value > y_value { 1 } else { 0 });
+        self.write_register(register_x, x_value.wrapping_sub(y_value));
         PCActions::Next
     }
 
-    // Shifts register left and moves value into carry register if odd
-    fn shift_right(&mut self, register: usize) -> PCActions {
-        self.registers.general_registers[0x0F] = self.registers.general_registers[register] & 1;
-        self.registers.general_registers[register] >>= 1;
+    // Shifts register right and moves value into carry register if odd.
+    // With the shift quirk, VY is shifted into VX instead.
+    fn shift_right(&mut self, register_x: usize, register_y: usize) -> PCActions {
+        let source = if self.quirks.shift_uses_vy { register_y } else { register_x };
+        let value = self.registers.general_registers[source];
+        self.write_register(register_x, value >> 1);
+        self.write_register(0x0F, value & 1);
         PCActions::Next
     }
 
@@ -330,15 +1109,18 @@ impl CPU {
     fn subtract_no_borrow_from_register(&mut self, register_x: usize, register_y: usize) -> PCActions {
         let x_value = self.registers.general_registers[register_x] as u8;
         let y_value = self.registers.general_registers[register_y] as u8;
-        self.registers.general_registers[0x0F] = if y_value > x_value { 1 } else { 0 };
-        self.registers.general_registers[register_x] = y_value.wrapping_sub(x_value);
+        self.write_register(0x0F, if y_value > x_value { 1 } else { 0 });
+        self.write_register(register_x, y_value.wrapping_sub(x_value));
         PCActions::Next
     }
 
-    // Shifts register right and moves value into carry register if is 1
-    fn shift_left(&mut self, register: usize) -> PCActions {
-        self.registers.general_registers[0x0F] = (self.registers.general_registers[register] & 0b10000000) >> 7;
-        self.registers.general_registers[register] <<= 1;
+    // Shifts register left and moves value into carry register if the top bit is 1.
+    // With the shift quirk, VY is shifted into VX instead.
+    fn shift_left(&mut self, register_x: usize, register_y: usize) -> PCActions {
+        let source = if self.quirks.shift_uses_vy { register_y } else { register_x };
+        let value = self.registers.general_registers[source];
+        self.write_register(register_x, value << 1);
+        self.write_register(0x0F, (value & 0b10000000) >> 7);
         PCActions::Next
     }
 
@@ -349,20 +1131,22 @@ impl CPU {
 
     // Load data into the index register
     fn load_index(&mut self, data: u16) -> PCActions {
-        self.registers.index = data;
+        self.write_index(data);
         PCActions::Next
     }
 
     // JP V0, addr
     // The program counter is set to nnn plus the value of V0.
-    fn jump_plus_vo(&mut self, data: u16) -> PCActions {
-        PCActions::Jump((self.registers.general_registers[0] as u16) + data)
+    // With the jump quirk (BXNN) the high nibble of nnn picks the register instead.
+    fn jump_plus_vo(&mut self, register: usize, data: u16) -> PCActions {
+        let offset_register = if self.quirks.jump_uses_vx { register } else { 0 };
+        PCActions::Jump((self.registers.general_registers[offset_register] as u16) + data)
     }
 
     // Generates a random 8-bit unsigned int, which is ANDed and stored in Vx
     fn random(&mut self, register: usize, data: u8) -> PCActions {
-        let mut rng = rand::thread_rng();
-        self.registers.general_registers[register] = rng.gen::<u8>() & data;
+        let value = self.rng.next_byte() & data;
+        self.write_register(register, value);
         PCActions::Next
     }
 
@@ -370,85 +1154,207 @@ impl CPU {
     // https://github.com/starrhorne/chip8-rust/blob/master/src/processor.rs
     // Draws sprite to screen
     fn display(&mut self, register_x: usize, register_y: usize, num_of_bytes: usize) -> PCActions {
-        
+
+        // DXY0 is a SUPER-CHIP 16x16 sprite, stored as two bytes per row
+        let (rows, sprite_width) = if num_of_bytes == 0 { (16, 16) } else { (num_of_bytes, 8) };
+        let bytes_per_row = sprite_width / 8;
+        let planes = self.selected_planes.count_ones() as usize;
+
+        if let Err(fault) = self.check_memory(self.registers.index as usize, planes * rows * bytes_per_row) {
+            return PCActions::Fault(fault);
+        }
+
+        // The VIP only drew during vertical blank, so hold here until the next one
+        if self.quirks.display_wait {
+            if !self.vblank {
+                return PCActions::StepBack;
+            }
+            self.vblank = false;
+        }
 
         // reset collision register
-        self.registers.general_registers[0x0F] = 0;
-
-        // n number of bytes stored in I register
-        for row in 0..num_of_bytes {
-            let y: usize = (self.registers.general_registers[register_y] as usize + row) % SCREEN_HEIGHT;
-            let current_byte = self.memory[(self.registers.index as usize + row)];
-            // We know 8 columns because a byte is 8
-            for col in 0..8 {
-                let x: usize = (self.registers.general_registers[register_x] as usize + col) % SCREEN_WIDTH;
-                let bit_on = (current_byte >> (7 - col)) & 1;
-                // check if the bits overlap
-                println!("x : {}, y : {}", x , y);
-                self.registers.general_registers[0x0F] |= bit_on & self.video_buffer[y][x];
-                // xor bit status with what is in video buffer at that position
-                self.video_buffer[y][x] ^= bit_on;
+        self.write_register(0x0F, 0);
+
+        let width = self.screen_width();
+        let height = self.screen_height();
+
+        // The starting position always wraps, only the sprite itself is clipped
+        let start_x = self.registers.general_registers[register_x] as usize % width;
+        let start_y = self.registers.general_registers[register_y] as usize % height;
+
+        // XO-CHIP draws one copy of the sprite per selected plane, each plane's
+        // data following the last one in memory
+        let mut sprite_address = self.registers.index as usize;
+
+        for plane in 0..2 {
+            let plane_bit = 1 << plane;
+            if self.selected_planes & plane_bit == 0 {
+                continue;
             }
+
+            // n number of rows stored in I register
+            for row in 0..rows {
+                let row_address = sprite_address + row * bytes_per_row;
+                if self.quirks.clip_sprites && start_y + row >= height {
+                    break;
+                }
+                let y: usize = (start_y + row) % height;
+                let mut current_row: u16 = 0;
+                for byte in 0..bytes_per_row {
+                    current_row = current_row << 8 | self.read_memory(row_address + byte) as u16;
+                }
+                for col in 0..sprite_width {
+                    if self.quirks.clip_sprites && start_x + col >= width {
+                        break;
+                    }
+                    let x: usize = (start_x + col) % width;
+                    if (current_row >> (sprite_width - 1 - col)) & 1 == 0 {
+                        continue;
+                    }
+                    // check if the bits overlap
+                    if self.video_buffer[y][x] & plane_bit != 0 {
+                        self.write_register(0x0F, 1);
+                    }
+                    // xor bit status with what is in video buffer at that position
+                    self.video_buffer[y][x] ^= plane_bit;
+                }
+            }
+
+            sprite_address += rows * bytes_per_row;
         }
 
         self.video_changed = true;
         PCActions::Next
     }
 
-    // Skip if selected key (by register) is pressed
+    // Skip if selected key (by register) is pressed, only the low nibble picks the key
     fn skip_if_key(&self, register: usize) -> PCActions {
-        PCActions::skip_if(self.registers.keypad[self.registers.general_registers[register] as usize])
+        PCActions::skip_if(self.registers.keypad[self.registers.general_registers[register] as usize & 0x0F])
     }
 
     // Skip if selected key (by register) is not pressed
     fn skip_if_not_key(&self, register: usize) -> PCActions {
-        PCActions::skip_if(!self.registers.keypad[self.registers.general_registers[register] as usize])
+        PCActions::skip_if(!self.registers.keypad[self.registers.general_registers[register] as usize & 0x0F])
     }
 
     // Load delay timer value into selected register
     fn load_register_from_delay(&mut self, register: usize) -> PCActions {
-        self.registers.general_registers[register] = self.registers.delay_timer;
+        self.write_register(register, self.registers.delay_timer);
         PCActions::Next
     }
 
-    // Wait for keypress, if no keypress occurs stepback the PC counter
+    // Wait for a key to be pressed, stepping the PC counter back until one is.
+    // A key already held when the wait starts doesn't count, it has to be
+    // pressed again. With the release quirk the wait goes on until the key
+    // pressed comes back up, like the VIP, so one press can't get through
+    // several waits in a row.
     fn wait_key_press(&mut self, register: usize) -> PCActions {
-        for i in 0..self.registers.keypad.len() {
-            if self.registers.keypad[i] {
-                self.registers.general_registers[register] = i as u8;
-                return PCActions::Next
+        let key = match self.registers.waiting_key {
+            Some(key) => key,
+            None => {
+                let pressed = self.registers.keys_pressed;
+                if pressed == 0 {
+                    return PCActions::StepBack;
+                }
+                let key = pressed.trailing_zeros() as u8;
+                self.registers.keys_pressed &= !(1 << key);
+                key
+            },
+        };
+
+        if self.quirks.wait_key_release {
+            let released = self.registers.keys_released & (1 << key) != 0 || !self.registers.keypad[key as usize];
+            if !released {
+                self.registers.waiting_key = Some(key);
+                return PCActions::StepBack;
             }
+            self.registers.keys_released &= !(1 << key);
+            self.registers.waiting_key = None;
         }
-        PCActions::StepBack
+
+        self.write_register(register, key);
+        PCActions::Next
     }
 
     // Set delay timer to the value in selected register
     fn load_delay_from_register(&mut self, register: usize) -> PCActions {
-        self.registers.delay_timer = self.registers.general_registers[register];
+        self.write_delay_timer(self.registers.general_registers[register]);
         PCActions::Next
     }
 
     // Set delay timer to the value in selected register
     fn load_sound_from_register(&mut self, register: usize) -> PCActions {
-        self.registers.sound_timer = self.registers.general_registers[register];
+        self.write_sound_timer(self.registers.general_registers[register]);
         PCActions::Next
     }
 
     // Add to index from the value in the selected register
     fn add_index(&mut self, register: usize) -> PCActions {
-        self.registers.index += self.registers.general_registers[register] as u16;
+        let index = self.registers.index as usize + self.registers.general_registers[register] as usize;
+        if index >= MEMORY_SIZE {
+            return PCActions::Fault(CpuFault::MemoryOutOfBounds { pc: self.program_counter, address: index });
+        }
+        self.write_index(index as u16);
         PCActions::Next
     }
 
     // Load a sprite to the index register based on value in selected register
     fn index_sprite(&mut self, register: usize) -> PCActions {
 
-        // offset to the location in memory where the font set is saved
-        let offset = 0x50;
-        let register_value = self.registers.general_registers[register];
+        let register_value = self.registers.general_registers[register] as u16;
 
         // Each font character is 5 bytes.
-        self.registers.index = offset + (5 * register_value) as u16;
+        self.write_index(FONT_ADDRESS + 5 * register_value);
+
+        PCActions::Next
+    }
+
+    // i := long NNNN: XO-CHIP, loads the index register from the word after this
+    // instruction. Skipping past that word makes this four bytes long.
+    fn load_long_index(&mut self) -> PCActions {
+        // the next instruction has to start inside memory as well as the word
+        let next = match self.program_counter.checked_add(2 * OPCODE_SIZE) {
+            Some(next) if self.program_counter as usize + 3 < MEMORY_SIZE => next,
+            _ => return PCActions::Fault(CpuFault::PcOutOfRange { pc: self.program_counter }),
+        };
+        let index = self.read_word(self.program_counter + OPCODE_SIZE);
+        self.write_index(index);
+        PCActions::Jump(next)
+    }
+
+    // plane n: XO-CHIP, selects which bitplanes later instructions draw to
+    fn select_planes(&mut self, planes: usize) -> PCActions {
+        self.selected_planes = planes as u8 & 0b11;
+        PCActions::Next
+    }
+
+    // audio: XO-CHIP, loads the 16 byte audio pattern starting at Index
+    fn load_audio_pattern(&mut self) -> PCActions {
+        let index = self.registers.index as usize;
+        if let Err(fault) = self.check_memory(index, 16) {
+            return PCActions::Fault(fault);
+        }
+        let mut pattern = [0; 16];
+        for (offset, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.read_memory(index + offset);
+        }
+        self.registers.audio_pattern = Some(pattern);
+        PCActions::Next
+    }
+
+    // pitch := vx: XO-CHIP, sets the playback rate of the audio pattern
+    fn load_pitch(&mut self, register: usize) -> PCActions {
+        self.registers.pitch = self.registers.general_registers[register];
+        PCActions::Next
+    }
+
+    // Load a large SUPER-CHIP digit to the index register based on value in selected register
+    fn index_large_sprite(&mut self, register: usize) -> PCActions {
+
+        let register_value = self.registers.general_registers[register] as u16;
+
+        // Each large digit is 10 bytes.
+        self.write_index(LARGE_FONT_ADDRESS + 10 * register_value);
 
         PCActions::Next
     }
@@ -459,36 +1365,73 @@ impl CPU {
         let register_value = self.registers.general_registers[register];
         let index: usize = self.registers.index as usize;
 
-        self.memory[index] = register_value / 100;
-        self.memory[index + 1] = (register_value % 100) / 10;
-        self.memory[index + 2] = register_value % 10;
+        if let Err(fault) = self.check_memory(index, 3) {
+            return PCActions::Fault(fault);
+        }
+
+        self.write_memory(index, register_value / 100);
+        self.write_memory(index + 1, (register_value % 100) / 10);
+        self.write_memory(index + 2, register_value % 10);
 
         PCActions::Next
 
     }
 
+    // Store registers V0 through the selected register in memory starting at Index
     fn store_registers(&mut self, register: usize) -> PCActions {
 
+        if let Err(fault) = self.check_memory(self.registers.index as usize, register + 1) {
+            return PCActions::Fault(fault);
+        }
+
         for i in 0..register + 1 {
-            self.memory[self.registers.index as usize + i] = self.registers.general_registers[i];
+            self.write_memory(self.registers.index as usize + i, self.registers.general_registers[i]);
+        }
+
+        if self.quirks.load_store_increments_index {
+            self.write_index(self.registers.index.wrapping_add(register as u16 + 1));
         }
 
         PCActions::Next
 
     }
 
+    // Load registers V0 through the selected register from memory starting at Index
     fn load_registers_from_index(&mut self, register: usize) -> PCActions {
 
+        if let Err(fault) = self.check_memory(self.registers.index as usize, register + 1) {
+            return PCActions::Fault(fault);
+        }
+
         for i in 0..register + 1 {
-            self.registers.general_registers[i] = self.memory[self.registers.index as usize + i]
+            let value = self.read_memory(self.registers.index as usize + i);
+            self.write_register(i, value);
+        }
+
+        if self.quirks.load_store_increments_index {
+            self.write_index(self.registers.index.wrapping_add(register as u16 + 1));
         }
 
         PCActions::Next
 
     }
 
+    // Save registers V0 through the selected register to the user flags
+    fn store_flags(&mut self, register: usize) -> PCActions {
+        self.registers.rpl_flags[..register + 1].copy_from_slice(&self.registers.general_registers[..register + 1]);
+        PCActions::Next
+    }
+
+    // Restore registers V0 through the selected register from the user flags
+    fn load_flags(&mut self, register: usize) -> PCActions {
+        for i in 0..register + 1 {
+            self.write_register(i, self.registers.rpl_flags[i]);
+        }
+        PCActions::Next
+    }
+
 }
 
 #[cfg(test)]
 #[path = "./cpu_test.rs"]
-mod cpu_test;
\ No newline at end of file
+mod cpu_test;
@@ -1,8 +1,9 @@
 use super::*;
+use crate::rom::MAX_ROM_SIZE;
 
 #[test]
 fn test_constructor() {
-    let chip = CPU::new();
+    let chip = CPU::new(Quirks::default());
     assert_eq!(chip.program_counter, 0x200);
     assert_eq!(chip.stack.stack_pointer, 0);
     assert_eq!(chip.stack.addresses, [0; 16]);
@@ -12,8 +13,8 @@ fn test_constructor() {
 #[test]
 fn test_load_rom() {
 
-    let mut chip = CPU::new();
-    let mut rom_memory = [0; 3584];
+    let mut chip = CPU::new(Quirks::default());
+    let mut rom_memory = [0; MAX_ROM_SIZE];
     let rom_size = 3;
 
     rom_memory[0] = 1;
@@ -34,12 +35,12 @@ fn test_load_rom() {
 
 #[test]
 fn test_clear() {
-    let mut chip = CPU::new();
-    chip.video_buffer = [[128; SCREEN_WIDTH]; SCREEN_HEIGHT];
-    chip.run_operation(0x00e0);
+    let mut chip = CPU::new(Quirks::default());
+    chip.video_buffer = [[1; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT];
+    chip.run_operation(0x00e0).unwrap();
 
-    for y in 0..SCREEN_HEIGHT {
-        for x in 0..SCREEN_WIDTH {
+    for y in 0..HIRES_SCREEN_HEIGHT {
+        for x in 0..HIRES_SCREEN_WIDTH {
             assert_eq!(chip.video_buffer[y][x], 0);
         }
     }
@@ -50,220 +51,220 @@ fn test_clear() {
 
 #[test]
 fn test_return() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.stack.stack_pointer = 4;
     chip.stack.addresses[3] = 0xfefa;
-    chip.run_operation(0x00ee);
+    chip.run_operation(0x00ee).unwrap();
     assert_eq!(chip.stack.stack_pointer, 3);
     assert_eq!(chip.program_counter, 0xfefa);
 }
 
 #[test]
 fn test_jump() {
-    let mut chip = CPU::new();
-    chip.run_operation(0x1524);
+    let mut chip = CPU::new(Quirks::default());
+    chip.run_operation(0x1524).unwrap();
     assert_eq!(chip.program_counter, 0x0524);
 }
 
 #[test]
 fn test_call() {
-    let mut chip = CPU::new();
-    chip.run_operation(0x2121);
+    let mut chip = CPU::new(Quirks::default());
+    chip.run_operation(0x2121).unwrap();
     assert_eq!(chip.stack.stack_pointer, 1);
     assert_eq!(chip.stack.addresses[0], 0x202);
 }
 
 #[test]
 fn test_skip_is_equal() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[2] = 0x11;
-    chip.run_operation(0x3211);
+    chip.run_operation(0x3211).unwrap();
     assert_eq!(chip.program_counter, 0x204);
-    chip.run_operation(0x3212);
+    chip.run_operation(0x3212).unwrap();
     assert_eq!(chip.program_counter, 0x206);
 }
 
 #[test]
 fn test_skip_is_not_equal() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[2] = 0x11;
-    chip.run_operation(0x4211);
+    chip.run_operation(0x4211).unwrap();
     assert_eq!(chip.program_counter, 0x202);
     chip.registers.general_registers[2] = 0x12;
-    chip.run_operation(0x4211);
+    chip.run_operation(0x4211).unwrap();
     assert_eq!(chip.program_counter, 0x206);
 }
 
 #[test]
 fn test_compare_registers() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[0] = 0x21;
     chip.registers.general_registers[1] = 0x23;
     chip.registers.general_registers[2] = 0x21;
 
-    chip.run_operation(0x5200);
+    chip.run_operation(0x5200).unwrap();
     assert_eq!(chip.program_counter, 0x204);
 
-    chip.run_operation(0x5210);
+    chip.run_operation(0x5210).unwrap();
     assert_eq!(chip.program_counter, 0x206);
 }
 
 #[test]
 fn test_load() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[0] = 0x21;
-    chip.run_operation(0x6032);
+    chip.run_operation(0x6032).unwrap();
     assert_eq!(chip.registers.general_registers[0], 0x0032);
 }
 
 #[test]
 fn test_add_to_register() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[3] = 0x55;
-    chip.run_operation(0x7322);
+    chip.run_operation(0x7322).unwrap();
     assert_eq!(chip.registers.general_registers[0x0003], 0x77);
-    chip.run_operation(0x73aa);
+    chip.run_operation(0x73aa).unwrap();
     assert_eq!(chip.registers.general_registers[0x0003], 0x21);
 }
 #[test]
 fn test_load_from_register() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[5] = 0x61;
-    chip.run_operation(0x8590);
+    chip.run_operation(0x8590).unwrap();
     assert_eq!(chip.registers.general_registers[5], chip.registers.general_registers[9]);
 }
 
 #[test]
 fn test_set_or() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[3] = 0x34;
     chip.registers.general_registers[6] = 0x7f;
-    chip.run_operation(0x8361);
+    chip.run_operation(0x8361).unwrap();
     assert_eq!(chip.registers.general_registers[3], 0x34 | 0x7f)
 }
 
 #[test]
 fn test_set_and() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[3] = 0x23;
     chip.registers.general_registers[6] = 0x4f;
-    chip.run_operation(0x8362);
+    chip.run_operation(0x8362).unwrap();
     assert_eq!(chip.registers.general_registers[3], 0x23 & 0x4f)
 }
 
 #[test]
 fn test_set_xor() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[3] = 0x96;
     chip.registers.general_registers[6] = 0xfe;
-    chip.run_operation(0x8363);
+    chip.run_operation(0x8363).unwrap();
     assert_eq!(chip.registers.general_registers[3], 0x96 ^ 0xfe)
 }
 
 #[test]
 fn test_add_from_register() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[0xa] = 0x22;
     chip.registers.general_registers[0xc] = 0x55;
     chip.registers.general_registers[0x3] = 0xaa;
-    chip.run_operation(0x8ac4);
+    chip.run_operation(0x8ac4).unwrap();
     assert_eq!(chip.registers.general_registers[0xa], 0x22 + 0x55);
     assert_eq!(chip.registers.general_registers[0xf], 0);
-    chip.run_operation(0x8a34);
+    chip.run_operation(0x8a34).unwrap();
     assert_eq!(chip.registers.general_registers[0xa], 0x21);
     assert_eq!(chip.registers.general_registers[0xf], 1);
 }
 
 #[test]
 fn test_subtract_from_register() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[0xa] = 0x22;
     chip.registers.general_registers[0xc] = 0x55;
     chip.registers.general_registers[0x3] = 0xaa;
-    chip.run_operation(0x8ac5);
+    chip.run_operation(0x8ac5).unwrap();
     assert_eq!(chip.registers.general_registers[0xa], 0xCD);
     assert_eq!(chip.registers.general_registers[0xf], 0);
-    chip.run_operation(0x83c5);
+    chip.run_operation(0x83c5).unwrap();
     assert_eq!(chip.registers.general_registers[0x3], 0x55);
     assert_eq!(chip.registers.general_registers[0xf], 1);    
 }
 
 #[test]
 fn test_shift_right() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[0xe] = 0xa2;
-    chip.run_operation(0x8ee6);
+    chip.run_operation(0x8ee6).unwrap();
     assert_eq!(chip.registers.general_registers[0xe], 0xa2 >> 1);
     assert_eq!(chip.registers.general_registers[0xf], 0);
     chip.registers.general_registers[0xe] = 0xa3;
-    chip.run_operation(0x8ee6);
+    chip.run_operation(0x8ee6).unwrap();
     assert_eq!(chip.registers.general_registers[0xe], 0xa3 >> 1);
     assert_eq!(chip.registers.general_registers[0xf], 1);
 }
 
 #[test]
 fn test_subtract_no_borrow_from_register() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[0xa] = 0x22;
     chip.registers.general_registers[0xc] = 0x55;
     chip.registers.general_registers[0x3] = 0xaa;
-    chip.run_operation(0x8ac7);
+    chip.run_operation(0x8ac7).unwrap();
     assert_eq!(chip.registers.general_registers[0xa], 0x55 - 0x22);
     assert_eq!(chip.registers.general_registers[0xf], 1);
-    chip.run_operation(0x83c7);
+    chip.run_operation(0x83c7).unwrap();
     assert_eq!(chip.registers.general_registers[0x3], 0xab);
     assert_eq!(chip.registers.general_registers[0xf], 0);    
 }
 
 #[test]
 fn test_shift_left() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[0xe] = 0x42;
-    chip.run_operation(0x8eee);
+    chip.run_operation(0x8eee).unwrap();
     assert_eq!(chip.registers.general_registers[0xe], 0x42 << 1);
     assert_eq!(chip.registers.general_registers[0xf], 0);
     chip.registers.general_registers[0xe] = 0xf1;
-    chip.run_operation(0x8eee);
+    chip.run_operation(0x8eee).unwrap();
     assert_eq!(chip.registers.general_registers[0xe], 0xf1 << 1);
     assert_eq!(chip.registers.general_registers[0xf], 1);
 }
 
 #[test]
 fn test_skip_is_not_equal_register() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[0] = 0x21;
     chip.registers.general_registers[1] = 0x23;
     chip.registers.general_registers[2] = 0x21;
 
-    chip.run_operation(0x9010);
+    chip.run_operation(0x9010).unwrap();
     assert_eq!(chip.program_counter, 0x204);
 
-    chip.run_operation(0x9020);
+    chip.run_operation(0x9020).unwrap();
     assert_eq!(chip.program_counter, 0x206);
 }
 
 #[test]
 fn test_load_index() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     assert_eq!(chip.registers.index, 0);
-    chip.run_operation(0xa123);
+    chip.run_operation(0xa123).unwrap();
     assert_eq!(chip.registers.index, 0x123);
-    chip.run_operation(0xa125);
+    chip.run_operation(0xa125).unwrap();
     assert_eq!(chip.registers.index, 0x125);
 }
 
 #[test]
 fn test_jump_plus_vo() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[0] = 0x32;
     assert_eq!(chip.program_counter, 0x200);
-    chip.run_operation(0xb123);
+    chip.run_operation(0xb123).unwrap();
     assert_eq!(chip.program_counter, 0x32 + 0x123);
 }
 
 #[test]
 fn test_display() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.index = 0x20;
     chip.memory[0x20] = 0b11111111;
     chip.memory[0x21] = 0b00000000;
@@ -273,7 +274,7 @@ fn test_display() {
     chip.video_buffer[22][21] = 0;
     chip.registers.general_registers[0] = 20;
     chip.registers.general_registers[1] = 21;
-    chip.run_operation(0xd012);
+    chip.run_operation(0xd012).unwrap();
 
     assert_eq!(chip.video_buffer[21][20], 0);
     assert_eq!(chip.video_buffer[21][21], 1);
@@ -286,88 +287,180 @@ fn test_display() {
 
 #[test]
 fn test_skip_if_key() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.keypad[2] = true;
     chip.registers.general_registers[3] = 2;
     chip.registers.general_registers[4] = 3;
-    chip.run_operation(0xE39E);
+    chip.run_operation(0xE39E).unwrap();
     assert_eq!(chip.program_counter, 0x204);
-    chip.run_operation(0xE49E);
+    chip.run_operation(0xE49E).unwrap();
     assert_eq!(chip.program_counter, 0x206);
 }
 
 #[test]
 fn test_skip_if_not_key() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.keypad[2] = true;
     chip.registers.general_registers[3] = 2;
     chip.registers.general_registers[4] = 3;
-    chip.run_operation(0xE3A1);
+    chip.run_operation(0xE3A1).unwrap();
     assert_eq!(chip.program_counter, 0x202);
-    chip.run_operation(0xE4A1);
+    chip.run_operation(0xE4A1).unwrap();
     assert_eq!(chip.program_counter, 0x206);
 }
 
 #[test]
 fn test_load_register_from_delay() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.delay_timer = 0x43;
-    chip.run_operation(0xF507);
+    chip.run_operation(0xF507).unwrap();
     assert_eq!(chip.registers.general_registers[5], 0x43);
 }
 
 #[test]
 fn test_wait_key_press() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     assert_eq!(chip.program_counter, 0x200);
-    chip.run_operation(0xF50A);
+    chip.run_operation(0xF50A).unwrap();
     assert_eq!(chip.program_counter, 0x200);
-    chip.run_operation(0xF50A);
+    chip.run_operation(0xF50A).unwrap();
     assert_eq!(chip.program_counter, 0x200);
-    chip.registers.keypad[3] = true;
-    chip.run_operation(0xF50A);
+    chip.key_event(KeyEvent::Down(3));
+    chip.run_operation(0xF50A).unwrap();
     assert_eq!(chip.program_counter, 0x202);
     assert_eq!(chip.registers.general_registers[5], 3)
 }
 
+// Runs FX0A for a frame with the keypad given, returns whether it finished
+fn wait_key_frame(chip: &mut CPU, keypad: [bool; 16]) -> bool {
+    chip.program_counter = 0x200;
+    chip.begin_frame(keypad);
+    chip.run_operation(0xF50A).unwrap();
+    chip.end_frame();
+    chip.program_counter == 0x202
+}
+
+fn keys(down: &[usize]) -> [bool; 16] {
+    let mut keypad = [false; 16];
+    for &key in down {
+        keypad[key] = true;
+    }
+    keypad
+}
+
+#[test]
+fn test_wait_key_press_ignores_held_key() {
+    let mut chip = CPU::new(Quirks::default());
+    assert!(wait_key_frame(&mut chip, keys(&[4])));
+    assert_eq!(chip.registers.general_registers[5], 4);
+
+    // still held, the next wait needs a new press
+    assert!(!wait_key_frame(&mut chip, keys(&[4])));
+    assert!(!wait_key_frame(&mut chip, keys(&[])));
+    assert!(wait_key_frame(&mut chip, keys(&[4])));
+}
+
+#[test]
+fn test_wait_key_press_only_counts_once() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.begin_frame(keys(&[6]));
+    chip.run_operation(0xF50A).unwrap();
+    assert_eq!(chip.program_counter, 0x202);
+    chip.run_operation(0xF50A).unwrap();
+    assert_eq!(chip.program_counter, 0x202);
+}
+
+#[test]
+fn test_wait_key_release_held() {
+    let mut chip = CPU::new(Quirks::COSMAC_VIP);
+    assert!(!wait_key_frame(&mut chip, keys(&[2])));
+    assert!(!wait_key_frame(&mut chip, keys(&[2])));
+    assert!(!wait_key_frame(&mut chip, keys(&[2])));
+    assert!(wait_key_frame(&mut chip, keys(&[])));
+    assert_eq!(chip.registers.general_registers[5], 2);
+}
+
+#[test]
+fn test_wait_key_release_tapped_between_frames() {
+    let mut chip = CPU::new(Quirks::COSMAC_VIP);
+    chip.key_event(KeyEvent::Down(9));
+    chip.key_event(KeyEvent::Up(9));
+    assert!(wait_key_frame(&mut chip, keys(&[])));
+    assert_eq!(chip.registers.general_registers[5], 9);
+
+    // a tap FX0A wasn't waiting for is forgotten at the end of the frame
+    chip.key_event(KeyEvent::Down(9));
+    chip.key_event(KeyEvent::Up(9));
+    chip.end_frame();
+    assert!(!wait_key_frame(&mut chip, keys(&[])));
+}
+
+#[test]
+fn test_wait_key_release_several_keys() {
+    let mut chip = CPU::new(Quirks::COSMAC_VIP);
+    assert!(!wait_key_frame(&mut chip, keys(&[0xA, 3])));
+
+    // the lowest key pressed is the one waited on, others coming and going don't count
+    assert!(!wait_key_frame(&mut chip, keys(&[3, 0xA])));
+    assert!(!wait_key_frame(&mut chip, keys(&[3, 7])));
+    assert!(wait_key_frame(&mut chip, keys(&[7])));
+    assert_eq!(chip.registers.general_registers[5], 3);
+
+    // 7 was pressed during the last wait and is still held
+    assert!(!wait_key_frame(&mut chip, keys(&[7])));
+    assert!(!wait_key_frame(&mut chip, keys(&[])));
+}
+
+#[test]
+fn test_key_events_update_keypad() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.key_event(KeyEvent::Down(0xE));
+    assert!(chip.registers.keypad[0xE]);
+    chip.registers.general_registers[0] = 0xE;
+    chip.run_operation(0xE09E).unwrap();
+    assert_eq!(chip.program_counter, 0x204);
+    chip.key_event(KeyEvent::Up(0xE));
+    assert!(!chip.registers.keypad[0xE]);
+}
+
 #[test]
 fn test_load_delay_from_register() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[5] = 0x43;
-    chip.run_operation(0xF515);
+    chip.run_operation(0xF515).unwrap();
     assert_eq!(chip.registers.delay_timer, 0x43);
 }
 
 #[test]
 fn test_load_sound_from_register() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[5] = 0x43;
-    chip.run_operation(0xF518);
+    chip.run_operation(0xF518).unwrap();
     assert_eq!(chip.registers.sound_timer, 0x43);
 }
 
 #[test]
 fn test_add_index() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.index = 0x232;
     chip.registers.general_registers[7] = 0x21;
-    chip.run_operation(0xF71E);
+    chip.run_operation(0xF71E).unwrap();
     assert_eq!(chip.registers.index, 0x232 + 0x21);
 }
 
 #[test]
 fn test_index_sprite() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[0xA] = 0x7;
-    chip.run_operation(0xFA29);
+    chip.run_operation(0xFA29).unwrap();
     assert_eq!(chip.registers.index, 0x73);
 }
 
 #[test]
 fn test_store_bcd() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     chip.registers.general_registers[0xB] = 0xae;
-    chip.run_operation(0xFB33);
+    chip.run_operation(0xFB33).unwrap();
     assert_eq!(chip.memory[0x0], 1);
     assert_eq!(chip.memory[0x1], 7);
     assert_eq!(chip.memory[0x2], 4);
@@ -375,10 +468,10 @@ fn test_store_bcd() {
 
 #[test]
 fn test_store_registers() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     let test_registers: [u8; 16] = [122,100,22,76,0,5,21,13,90,32,73,88,23,2,131,1];
     chip.registers.general_registers = test_registers;
-    chip.run_operation(0xFF55);
+    chip.run_operation(0xFF55).unwrap();
 
     for i in 0..5 {
         assert_eq!(chip.registers.general_registers[i], chip.memory[i])
@@ -387,14 +480,601 @@ fn test_store_registers() {
 
 #[test]
 fn test_load_registers_from_index() {
-    let mut chip = CPU::new();
+    let mut chip = CPU::new(Quirks::default());
     let test_registers: [u8; 16] = [122,100,22,76,0,5,21,13,90,32,73,88,23,2,131,1];
     for i in 0..test_registers.len() {
         chip.memory[i] = test_registers[i];
     }
-    chip.run_operation(0xFF65);
+    chip.run_operation(0xFF65).unwrap();
 
     for i in 0..5 {
         assert_eq!(chip.registers.general_registers[i], chip.memory[i])
     }
-}
\ No newline at end of file
+}
+#[test]
+fn test_quirk_shift_in_place() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.registers.general_registers[0x1] = 0x81;
+    chip.registers.general_registers[0x2] = 0x0c;
+    chip.run_operation(0x8126).unwrap();
+    assert_eq!(chip.registers.general_registers[0x1], 0x40);
+    assert_eq!(chip.registers.general_registers[0xf], 1);
+    chip.run_operation(0x812e).unwrap();
+    assert_eq!(chip.registers.general_registers[0x1], 0x80);
+    assert_eq!(chip.registers.general_registers[0xf], 0);
+}
+
+#[test]
+fn test_quirk_shift_uses_vy() {
+    let mut chip = CPU::new(Quirks { shift_uses_vy: true, ..Quirks::default() });
+    chip.registers.general_registers[0x1] = 0x81;
+    chip.registers.general_registers[0x2] = 0x0d;
+    chip.run_operation(0x8126).unwrap();
+    assert_eq!(chip.registers.general_registers[0x1], 0x06);
+    assert_eq!(chip.registers.general_registers[0xf], 1);
+    chip.registers.general_registers[0x2] = 0x84;
+    chip.run_operation(0x812e).unwrap();
+    assert_eq!(chip.registers.general_registers[0x1], 0x08);
+    assert_eq!(chip.registers.general_registers[0x2], 0x84);
+    assert_eq!(chip.registers.general_registers[0xf], 1);
+}
+
+#[test]
+fn test_quirk_load_store_keeps_index() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.registers.index = 0x300;
+    chip.run_operation(0xF355).unwrap();
+    assert_eq!(chip.registers.index, 0x300);
+    chip.run_operation(0xF365).unwrap();
+    assert_eq!(chip.registers.index, 0x300);
+}
+
+#[test]
+fn test_quirk_load_store_increments_index() {
+    let mut chip = CPU::new(Quirks { load_store_increments_index: true, ..Quirks::default() });
+    chip.registers.index = 0x300;
+    chip.run_operation(0xF355).unwrap();
+    assert_eq!(chip.registers.index, 0x304);
+    chip.run_operation(0xF065).unwrap();
+    assert_eq!(chip.registers.index, 0x305);
+}
+
+#[test]
+fn test_quirk_jump_uses_v0() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.registers.general_registers[0] = 0x10;
+    chip.registers.general_registers[3] = 0x20;
+    chip.run_operation(0xB300).unwrap();
+    assert_eq!(chip.program_counter, 0x310);
+}
+
+#[test]
+fn test_quirk_jump_uses_vx() {
+    let mut chip = CPU::new(Quirks { jump_uses_vx: true, ..Quirks::default() });
+    chip.registers.general_registers[0] = 0x10;
+    chip.registers.general_registers[3] = 0x20;
+    chip.run_operation(0xB300).unwrap();
+    assert_eq!(chip.program_counter, 0x320);
+}
+
+#[test]
+fn test_quirk_logic_keeps_vf() {
+    let mut chip = CPU::new(Quirks::default());
+    for &operation in [0x8121, 0x8122, 0x8123].iter() {
+        chip.registers.general_registers[0xf] = 0x5;
+        chip.run_operation(operation).unwrap();
+        assert_eq!(chip.registers.general_registers[0xf], 0x5);
+    }
+}
+
+#[test]
+fn test_quirk_logic_resets_vf() {
+    let mut chip = CPU::new(Quirks { logic_resets_vf: true, ..Quirks::default() });
+    for &operation in [0x8121, 0x8122, 0x8123].iter() {
+        chip.registers.general_registers[0xf] = 0x5;
+        chip.run_operation(operation).unwrap();
+        assert_eq!(chip.registers.general_registers[0xf], 0);
+    }
+}
+
+#[test]
+fn test_quirk_sprites_wrap() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.registers.index = 0x20;
+    chip.memory[0x20] = 0b11000000;
+    chip.memory[0x21] = 0b11000000;
+    chip.registers.general_registers[0] = (SCREEN_WIDTH - 1) as u8;
+    chip.registers.general_registers[1] = (SCREEN_HEIGHT - 1) as u8;
+    chip.run_operation(0xd012).unwrap();
+    assert_eq!(chip.video_buffer[SCREEN_HEIGHT - 1][SCREEN_WIDTH - 1], 1);
+    assert_eq!(chip.video_buffer[SCREEN_HEIGHT - 1][0], 1);
+    assert_eq!(chip.video_buffer[0][SCREEN_WIDTH - 1], 1);
+    assert_eq!(chip.video_buffer[0][0], 1);
+}
+
+#[test]
+fn test_quirk_sprites_clip() {
+    let mut chip = CPU::new(Quirks { clip_sprites: true, ..Quirks::default() });
+    chip.registers.index = 0x20;
+    chip.memory[0x20] = 0b11000000;
+    chip.memory[0x21] = 0b11000000;
+    chip.registers.general_registers[0] = (SCREEN_WIDTH - 1) as u8;
+    chip.registers.general_registers[1] = (SCREEN_HEIGHT - 1) as u8;
+    chip.run_operation(0xd012).unwrap();
+    assert_eq!(chip.video_buffer[SCREEN_HEIGHT - 1][SCREEN_WIDTH - 1], 1);
+    assert_eq!(chip.video_buffer[SCREEN_HEIGHT - 1][0], 0);
+    assert_eq!(chip.video_buffer[0][SCREEN_WIDTH - 1], 0);
+    assert_eq!(chip.video_buffer[0][0], 0);
+
+    // the starting position still wraps before clipping
+    chip.run_operation(0x00e0).unwrap();
+    chip.registers.general_registers[0] = (SCREEN_WIDTH + 2) as u8;
+    chip.registers.general_registers[1] = 0;
+    chip.run_operation(0xd011).unwrap();
+    assert_eq!(chip.video_buffer[0][2], 1);
+    assert_eq!(chip.video_buffer[0][3], 1);
+}
+
+#[test]
+fn test_quirk_display_no_wait() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.run_operation(0xd015).unwrap();
+    chip.run_operation(0xd015).unwrap();
+    assert_eq!(chip.program_counter, 0x204);
+}
+
+#[test]
+fn test_quirk_display_wait() {
+    let mut chip = CPU::new(Quirks { display_wait: true, ..Quirks::default() });
+    chip.run_operation(0xd015).unwrap();
+    assert_eq!(chip.program_counter, 0x200);
+    assert!(!chip.video_changed);
+
+    chip.vblank = true;
+    chip.run_operation(0xd015).unwrap();
+    assert_eq!(chip.program_counter, 0x202);
+    assert!(chip.video_changed);
+
+    // only one draw per vertical blank
+    chip.run_operation(0xd015).unwrap();
+    assert_eq!(chip.program_counter, 0x202);
+}
+
+#[test]
+fn test_quirk_presets() {
+    assert_eq!(Quirks::from_name("vip"), Some(Quirks::COSMAC_VIP));
+    assert_eq!(Quirks::from_name("SCHIP"), Some(Quirks::SCHIP));
+    assert_eq!(Quirks::from_name("xo-chip"), Some(Quirks::XO_CHIP));
+    assert_eq!(Quirks::from_name("chip-48"), Some(Quirks::CHIP_48));
+    assert_eq!(Quirks::from_name("unknown"), None);
+}
+
+#[test]
+fn test_resolution() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.video_buffer[3][3] = 1;
+    chip.run_operation(0x00ff).unwrap();
+    assert!(chip.hires);
+    assert_eq!(chip.video_buffer[3][3], 0);
+    assert!(chip.video_changed);
+    chip.run_operation(0x00fe).unwrap();
+    assert!(!chip.hires);
+    assert_eq!(chip.program_counter, 0x204);
+}
+
+#[test]
+fn test_scroll_down() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.video_buffer[0][5] = 1;
+    chip.video_buffer[SCREEN_HEIGHT - 1][5] = 1;
+    chip.run_operation(0x00c3).unwrap();
+    assert_eq!(chip.video_buffer[0][5], 0);
+    assert_eq!(chip.video_buffer[3][5], 1);
+    // pixels scrolled off the bottom of the lores screen are gone
+    assert_eq!(chip.video_buffer[SCREEN_HEIGHT + 2][5], 0);
+}
+
+#[test]
+fn test_scroll_right_and_left() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.run_operation(0x00ff).unwrap();
+    chip.video_buffer[1][0] = 1;
+    chip.video_buffer[1][HIRES_SCREEN_WIDTH - 1] = 1;
+    chip.run_operation(0x00fb).unwrap();
+    assert_eq!(chip.video_buffer[1][0], 0);
+    assert_eq!(chip.video_buffer[1][4], 1);
+    assert_eq!(chip.video_buffer[1][HIRES_SCREEN_WIDTH - 1], 0);
+    chip.run_operation(0x00fc).unwrap();
+    assert_eq!(chip.video_buffer[1][4], 0);
+    assert_eq!(chip.video_buffer[1][0], 1);
+}
+
+#[test]
+fn test_exit() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.memory[0x200] = 0x00;
+    chip.memory[0x201] = 0xfd;
+    chip.memory[0x202] = 0x60;
+    chip.memory[0x203] = 0x01;
+    let state = chip.run_frame([false; 16]).unwrap();
+    assert!(state.exited);
+    chip.step().unwrap();
+    assert_eq!(chip.program_counter, 0x200);
+    assert_eq!(chip.registers.general_registers[0], 0);
+}
+
+#[test]
+fn test_display_large_sprite() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.run_operation(0x00ff).unwrap();
+    chip.registers.index = 0x300;
+    for row in 0..16 {
+        chip.memory[0x300 + row * 2] = 0x80;
+        chip.memory[0x300 + row * 2 + 1] = 0x01;
+    }
+    chip.registers.general_registers[0] = 100;
+    chip.registers.general_registers[1] = 40;
+    chip.run_operation(0xd010).unwrap();
+
+    for row in 0..16 {
+        assert_eq!(chip.video_buffer[40 + row][100], 1);
+        assert_eq!(chip.video_buffer[40 + row][101], 0);
+        assert_eq!(chip.video_buffer[40 + row][115], 1);
+    }
+    assert_eq!(chip.video_buffer[56][100], 0);
+    assert_eq!(chip.registers.general_registers[0xf], 0);
+}
+
+#[test]
+fn test_index_large_sprite() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.registers.general_registers[0x2] = 0x3;
+    chip.run_operation(0xF230).unwrap();
+    assert_eq!(chip.registers.index, 0xA0 + 30);
+    assert_eq!(chip.memory[0xA0 + 30], 0x3C);
+}
+
+#[test]
+fn test_store_and_load_flags() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.registers.general_registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+    chip.run_operation(0xF375).unwrap();
+    chip.registers.general_registers = [0; 16];
+    chip.run_operation(0xF285).unwrap();
+    assert_eq!(chip.registers.general_registers[..4], [1, 2, 3, 0]);
+}
+
+#[test]
+fn test_load_long_index() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.memory[0x200] = 0xF0;
+    chip.memory[0x201] = 0x00;
+    chip.memory[0x202] = 0xBE;
+    chip.memory[0x203] = 0xEF;
+    chip.run_operation(0xF000).unwrap();
+    assert_eq!(chip.registers.index, 0xBEEF);
+    assert_eq!(chip.program_counter, 0x204);
+}
+
+#[test]
+fn test_long_index_at_end_of_memory() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.memory[0xFFFC..].copy_from_slice(&[0xF0, 0x00, 0x12, 0x34]);
+    chip.program_counter = 0xFFFC;
+    assert_eq!(chip.step(), Err(CpuFault::PcOutOfRange { pc: 0xFFFC }));
+    assert_eq!(chip.registers.index, 0);
+
+    let mut chip = CPU::new(Quirks::default());
+    chip.memory[0xFFFA..0xFFFE].copy_from_slice(&[0xF0, 0x00, 0x12, 0x34]);
+    chip.program_counter = 0xFFFA;
+    chip.step().unwrap();
+    assert_eq!(chip.registers.index, 0x1234);
+    assert_eq!(chip.program_counter, 0xFFFE);
+}
+
+#[test]
+fn test_skip_over_long_index() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.memory[0x202] = 0xF0;
+    chip.memory[0x203] = 0x00;
+    chip.run_operation(0x3000).unwrap();
+    assert_eq!(chip.program_counter, 0x206);
+}
+
+#[test]
+fn test_register_range() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.registers.index = 0x400;
+    chip.registers.general_registers[2..5].copy_from_slice(&[7, 8, 9]);
+    chip.run_operation(0x5242).unwrap();
+    assert_eq!(chip.memory[0x400..0x403], [7, 8, 9]);
+    assert_eq!(chip.registers.index, 0x400);
+
+    // reversed ranges count down from X
+    chip.run_operation(0x5422).unwrap();
+    assert_eq!(chip.memory[0x400..0x403], [9, 8, 7]);
+
+    chip.run_operation(0x5a83).unwrap();
+    assert_eq!(chip.registers.general_registers[0xa], 9);
+    assert_eq!(chip.registers.general_registers[0x9], 8);
+    assert_eq!(chip.registers.general_registers[0x8], 7);
+}
+
+#[test]
+fn test_planes() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.registers.index = 0x300;
+    chip.memory[0x300] = 0x80;
+    chip.memory[0x301] = 0xC0;
+
+    // both planes take consecutive sprite data
+    chip.run_operation(0xF301).unwrap();
+    chip.run_operation(0xD011).unwrap();
+    assert_eq!(chip.video_buffer[0][0], 0b11);
+    assert_eq!(chip.video_buffer[0][1], 0b10);
+
+    // clearing only touches the selected plane
+    chip.run_operation(0xF101).unwrap();
+    chip.run_operation(0x00E0).unwrap();
+    assert_eq!(chip.video_buffer[0][0], 0b10);
+
+    chip.run_operation(0xF201).unwrap();
+    chip.run_operation(0xD011).unwrap();
+    assert_eq!(chip.registers.general_registers[0xf], 1);
+    assert_eq!(chip.video_buffer[0][0], 0);
+}
+
+#[test]
+fn test_scroll_selected_plane() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.video_buffer[0][0] = 0b11;
+    chip.run_operation(0xF201).unwrap();
+    chip.run_operation(0x00C2).unwrap();
+    assert_eq!(chip.video_buffer[0][0], 0b01);
+    assert_eq!(chip.video_buffer[2][0], 0b10);
+    chip.run_operation(0x00D1).unwrap();
+    assert_eq!(chip.video_buffer[1][0], 0b10);
+}
+
+#[test]
+fn test_audio_pattern_and_pitch() {
+    let mut chip = CPU::new(Quirks::default());
+    assert!(chip.registers.audio_pattern.is_none());
+    chip.registers.index = 0x300;
+    for i in 0..16 {
+        chip.memory[0x300 + i] = i as u8;
+    }
+    chip.run_operation(0xF002).unwrap();
+    assert_eq!(chip.registers.audio_pattern.unwrap()[15], 15);
+
+    chip.registers.general_registers[4] = 112;
+    chip.run_operation(0xF43A).unwrap();
+    assert_eq!(chip.registers.pitch, 112);
+}
+
+#[test]
+fn test_full_memory() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.registers.index = 0xFFFE;
+    chip.registers.general_registers[0] = 0xAB;
+    chip.run_operation(0xF055).unwrap();
+    assert_eq!(chip.memory[0xFFFE], 0xAB);
+}
+
+#[test]
+fn test_scheduler_spreads_instructions() {
+    let mut scheduler = Scheduler::new(700);
+    let frames: Vec<u32> = (0..60).map(|_| scheduler.instructions_for_frame()).collect();
+    assert_eq!(frames.iter().sum::<u32>(), 700);
+    assert!(frames.iter().all(|&count| count == 11 || count == 12));
+}
+
+#[test]
+fn test_run_frame_instruction_count() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.set_speed(600);
+    // 0x7001 adds one to V0 each time it runs
+    for address in (0x200..0x300).step_by(2) {
+        chip.memory[address] = 0x70;
+        chip.memory[address + 1] = 0x01;
+    }
+    chip.run_frame([false; 16]).unwrap();
+    assert_eq!(chip.registers.general_registers[0], 10);
+    assert_eq!(chip.program_counter, 0x200 + 10 * 2);
+    assert_eq!(chip.instruction_count(), 10);
+}
+
+#[test]
+fn test_run_frame_timers() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.registers.delay_timer = 10;
+    chip.registers.sound_timer = 1;
+    // spin in place with 0x1200
+    chip.memory[0x200] = 0x12;
+    chip.memory[0x201] = 0x00;
+
+    // timers tick once a frame no matter how many instructions ran
+    chip.set_speed(1200);
+    let state = chip.run_frame([false; 16]).unwrap();
+    assert!(!state.beep);
+    assert_eq!(chip.registers.delay_timer, 9);
+    chip.set_speed(60);
+    chip.run_frame([false; 16]).unwrap();
+    assert_eq!(chip.registers.delay_timer, 8);
+}
+
+#[test]
+fn test_stack_faults() {
+    let mut chip = CPU::new(Quirks::default());
+    assert_eq!(chip.run_operation(0x00ee), Err(CpuFault::StackUnderflow { pc: 0x200 }));
+
+    for _ in 0..16 {
+        chip.run_operation(0x2200).unwrap();
+    }
+    assert_eq!(chip.run_operation(0x2200), Err(CpuFault::StackOverflow { pc: 0x200 }));
+    assert_eq!(chip.stack.stack_pointer, 16);
+}
+
+#[test]
+fn test_memory_faults() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.registers.index = 0xFFFE;
+    let fault = Err(CpuFault::MemoryOutOfBounds { pc: 0x200, address: 0x10000 });
+    assert_eq!(chip.run_operation(0xF033), fault);
+    assert_eq!(chip.run_operation(0xF255), fault);
+    assert_eq!(chip.run_operation(0xF265), fault);
+    assert_eq!(chip.run_operation(0xD003), fault);
+    assert_eq!(chip.run_operation(0xD002), Ok(StepOutcome::Executed));
+
+    chip.registers.general_registers[0] = 2;
+    assert_eq!(chip.run_operation(0xF01E), Err(CpuFault::MemoryOutOfBounds { pc: 0x202, address: 0x10000 }));
+    assert_eq!(chip.registers.index, 0xFFFE);
+}
+
+#[test]
+fn test_invalid_opcode() {
+    let mut chip = CPU::new(Quirks::default());
+    assert_eq!(chip.run_operation(0xFFFF), Err(CpuFault::InvalidOpcode { pc: 0x200, opcode: 0xFFFF }));
+    assert_eq!(chip.program_counter, 0x200);
+}
+
+#[test]
+fn test_pc_out_of_range() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.program_counter = 0xFFFE;
+    assert_eq!(chip.run_operation(0x6000), Err(CpuFault::PcOutOfRange { pc: 0xFFFE }));
+    chip.program_counter = 0xFFFF;
+    assert_eq!(chip.step(), Err(CpuFault::PcOutOfRange { pc: 0xFFFF }));
+}
+
+#[test]
+fn test_fault_policy_halt() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.memory[0x200] = 0xFF;
+    chip.memory[0x201] = 0xFF;
+    assert!(chip.run_frame([false; 16]).is_err());
+    assert_eq!(chip.step(), Ok(StepOutcome::Halted));
+    assert_eq!(chip.program_counter, 0x200);
+}
+
+#[test]
+fn test_fault_policy_ignore() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.set_fault_policy(FaultPolicy { invalid_opcode: FaultAction::Ignore, ..FaultPolicy::default() });
+    chip.memory[0x200] = 0xFF;
+    chip.memory[0x201] = 0xFF;
+    chip.memory[0x202] = 0x60;
+    chip.memory[0x203] = 0x07;
+    assert_eq!(chip.step(), Ok(StepOutcome::Executed));
+    assert_eq!(chip.step(), Ok(StepOutcome::Executed));
+    assert_eq!(chip.registers.general_registers[0], 7);
+
+    // other kinds of fault still halt
+    chip.memory[0x204] = 0x00;
+    chip.memory[0x205] = 0xee;
+    assert_eq!(chip.step(), Err(CpuFault::StackUnderflow { pc: 0x204 }));
+}
+
+#[test]
+fn test_wait_outcome() {
+    let mut chip = CPU::new(Quirks::default());
+    assert_eq!(chip.run_operation(0xF00A), Ok(StepOutcome::Waiting));
+}
+
+#[test]
+fn test_seeded_random() {
+    let mut first = CPU::with_rng(Quirks::default(), Box::new(XorShift::from_seed(1234)));
+    let mut second = CPU::with_rng(Quirks::default(), Box::new(XorShift::from_seed(1234)));
+
+    // draw a random digit at a random position, over and over
+    let program = [0xC0, 0x3F, 0xC1, 0x1F, 0xC2, 0x0F, 0xF2, 0x29, 0xD0, 0x15, 0x12, 0x00];
+    for chip in [&mut first, &mut second].iter_mut() {
+        chip.memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+        for _ in 0..10 {
+            chip.run_frame([false; 16]).unwrap();
+        }
+    }
+
+    assert!(first.video_buffer.iter().flatten().any(|&pixel| pixel != 0));
+    assert!(first.video_buffer[..] == second.video_buffer[..]);
+    assert_eq!(first.rng.state(), second.rng.state());
+}
+
+#[test]
+fn test_random_masks_result() {
+    let mut chip = CPU::with_rng(Quirks::default(), Box::new(XorShift::from_seed(99)));
+    for _ in 0..100 {
+        chip.run_operation(0xC30A).unwrap();
+        assert_eq!(chip.registers.general_registers[3] & !0x0A, 0);
+    }
+}
+
+#[test]
+fn test_random_source_state() {
+    let mut rng = XorShift::from_seed(7);
+    let state = rng.state();
+    let first: Vec<u8> = (0..8).map(|_| rng.next_byte()).collect();
+    rng.set_state(state);
+    let second: Vec<u8> = (0..8).map(|_| rng.next_byte()).collect();
+    assert_eq!(first, second);
+    assert_ne!(XorShift::from_seed(7).next_byte(), XorShift::from_seed(8).next_byte());
+}
+
+#[test]
+fn test_save_state_resumes_identically() {
+    // same random drawing program as test_seeded_random, with a subroutine call
+    // so the stack is in use when the state is saved
+    let program = [
+        0x22, 0x04, 0x12, 0x00,
+        0xC0, 0x3F, 0xC1, 0x1F, 0xC2, 0x0F, 0xF2, 0x29, 0xD0, 0x15, 0x00, 0xEE,
+    ];
+    let mut original = CPU::with_rng(Quirks::COSMAC_VIP, Box::new(XorShift::from_seed(5)));
+    original.memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+    original.set_speed(601);
+    original.run_operation(0x00FF).unwrap();
+    original.run_operation(0xA000).unwrap();
+    original.run_operation(0xF002).unwrap();
+    original.program_counter = 0x200;
+    for _ in 0..7 {
+        original.run_frame([false; 16]).unwrap();
+    }
+    original.step().unwrap();
+
+    let state = original.save_state();
+
+    let mut restored = CPU::with_rng(Quirks::default(), Box::new(XorShift::from_seed(6)));
+    restored.set_speed(601);
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.save_state(), state);
+    assert_eq!(restored.quirks, Quirks::COSMAC_VIP);
+    assert!(restored.hires);
+    assert_eq!(restored.stack.stack_pointer, original.stack.stack_pointer);
+    assert!(restored.video_changed);
+
+    for _ in 0..7 {
+        let keypad = [true; 16];
+        original.run_frame(keypad).unwrap();
+        restored.run_frame(keypad).unwrap();
+    }
+    assert_eq!(restored.save_state(), original.save_state());
+}
+
+#[test]
+fn test_load_state_rejects_bad_data() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.registers.general_registers[0] = 0x42;
+    let mut state = chip.save_state();
+
+    let mut other = CPU::new(Quirks::default());
+    assert_eq!(other.load_state(&state[..state.len() - 1]), Err(SaveStateError::Truncated));
+
+    state[4] = savestate::VERSION as u8 + 1;
+    assert_eq!(
+        other.load_state(&state),
+        Err(SaveStateError::UnsupportedVersion { found: savestate::VERSION + 1, expected: savestate::VERSION })
+    );
+    assert_eq!(other.load_state(b"not a state"), Err(SaveStateError::NotASaveState));
+
+    // nothing is changed by a state that fails to load
+    assert_eq!(other.registers.general_registers[0], 0);
+}
@@ -0,0 +1,451 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::cpu::{CPU, MEMORY_SIZE};
+use crate::fault::{CpuFault, StepOutcome};
+use crate::headless::{self, DumpFormat};
+use crate::instruction::Instruction;
+use crate::scheduler::Scheduler;
+use crate::watch::{hex, Watchpoint};
+
+// A command line debugger: breakpoints on the program counter, stepping
+// through instructions and subroutines, and looking at or changing registers
+// and memory. The frontend reads lines and passes them to Debugger::command.
+
+pub const HELP: &str = "commands, addresses and values are hex, counts are decimal:
+    break <address>          stop when the program counter gets to address (b)
+    delete <address>         remove a breakpoint
+    breakpoints              list breakpoints
+    step [count]             run one instruction, or count of them (s)
+    next                     run one instruction, running a CALL through to its return (n)
+    finish                   run until the current subroutine returns
+    continue [frames]        run until a breakpoint, at most frames 60hz frames (c, default 3600)
+    registers                show V0-VF, I, timers, stack and PC (r)
+    memory <address> [bytes] hex dump of memory (x, default 64 bytes)
+    set <v0-vf|i|pc|dt|st> <value>
+                             change a register
+    write <address> <byte>...
+                             change memory
+    watch <target> [read|write|rw] [<op> <value>] [pause]
+                             report instructions touching an address, a range like
+                             300-30f, or a register (v0-vf, i, dt, st). op is ==, !=,
+                             <, <=, > or >=, pause stops running on a hit. dt and
+                             st only see instructions, not the timers counting down
+    unwatch <number>         remove a watchpoint
+    watchpoints              list watchpoints
+    keys [key...]            hold these chip-8 keys down from now on, none lets go
+    screen                   show the screen
+    help                     show this list (h)
+    quit                     stop debugging (q)
+an empty line repeats the last step, next, finish or continue";
+
+/// How long continue runs without reaching a breakpoint, a minute of emulated time
+pub const DEFAULT_CONTINUE_FRAMES: u32 = 3600;
+
+// One line typed at the debugger prompt
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Break(u16),
+    Delete(u16),
+    Breakpoints,
+    Step(u32),
+    Next,
+    Finish,
+    Continue(u32),
+    Registers,
+    Memory(u16, usize),
+    Set(Register, u16),
+    Write(u16, Vec<u8>),
+    Watch(Watchpoint),
+    Unwatch(usize),
+    Watchpoints,
+    Keys([bool; 16]),
+    Screen,
+    Help,
+    Quit,
+}
+
+// Registers the set command can change
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    V(u8),
+    Index,
+    ProgramCounter,
+    DelayTimer,
+    SoundTimer,
+}
+
+// Why running stopped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stop {
+    /// the steps asked for ran, or the subroutine returned
+    Done,
+    /// the program counter reached a breakpoint
+    Breakpoint(u16),
+    /// the rom ran 00FD
+    Exited,
+    /// a fault stopped the cpu, None if it was stopped before this run
+    Halted(Option<CpuFault>),
+    /// continue ran for as many frames as it was allowed to
+    FrameLimit,
+    /// a watchpoint that pauses was hit
+    Watchpoint,
+}
+
+impl Command {
+
+    pub fn parse(line: &str) -> Result<Command, String> {
+
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let arguments: Vec<&str> = words.collect();
+        let argument = |i: usize| arguments.get(i).cloned()
+            .ok_or_else(|| format!("{} needs more arguments, see help", name));
+
+        let command = match name {
+            "break" | "b" => Command::Break(hex(argument(0)?)?),
+            "delete" | "d" => Command::Delete(hex(argument(0)?)?),
+            "breakpoints" => Command::Breakpoints,
+            "step" | "s" => Command::Step(match arguments.first() {
+                Some(count) => match decimal(count)? {
+                    0 => return Err(String::from("step needs a count of at least 1")),
+                    count => count,
+                },
+                None => 1,
+            }),
+            "next" | "n" => Command::Next,
+            "finish" => Command::Finish,
+            "continue" | "c" => Command::Continue(match arguments.first() {
+                Some(frames) => decimal(frames)?,
+                None => DEFAULT_CONTINUE_FRAMES,
+            }),
+            "registers" | "r" => Command::Registers,
+            "memory" | "x" => Command::Memory(hex(argument(0)?)?, match arguments.get(1) {
+                Some(length) => decimal(length)? as usize,
+                None => 64,
+            }),
+            "set" => {
+                let register = argument(0)?.to_ascii_lowercase();
+                let register = match register.as_str() {
+                    "i" => Register::Index,
+                    "pc" => Register::ProgramCounter,
+                    "dt" => Register::DelayTimer,
+                    "st" => Register::SoundTimer,
+                    _ => match register.strip_prefix('v').map(|number| u8::from_str_radix(number, 16)) {
+                        Some(Ok(number)) if number < 16 => Register::V(number),
+                        _ => return Err(format!("unknown register '{}'", register)),
+                    },
+                };
+                Command::Set(register, hex(argument(1)?)?)
+            },
+            "write" => {
+                let address = hex(argument(0)?)?;
+                argument(1)?;
+                let bytes = arguments[1..].iter()
+                    .map(|&byte| u8::from_str_radix(byte, 16).map_err(|_| format!("invalid byte '{}'", byte)))
+                    .collect::<Result<Vec<u8>, String>>()?;
+                Command::Write(address, bytes)
+            },
+            "watch" => Command::Watch(Watchpoint::parse(&arguments.join(" "))?),
+            "unwatch" => Command::Unwatch(decimal(argument(0)?)? as usize),
+            "watchpoints" => Command::Watchpoints,
+            "keys" => {
+                let mut keypad = [false; 16];
+                for &key in arguments.iter() {
+                    match u8::from_str_radix(key, 16) {
+                        Ok(key) if key < 16 => keypad[key as usize] = true,
+                        _ => return Err(format!("invalid key '{}'", key)),
+                    }
+                }
+                Command::Keys(keypad)
+            },
+            "screen" => Command::Screen,
+            "help" | "h" => Command::Help,
+            "quit" | "q" => Command::Quit,
+            _ => return Err(format!("unknown command '{}', see help", name)),
+        };
+
+        Ok(command)
+    }
+}
+
+fn decimal(text: &str) -> Result<u32, String> {
+    text.parse().map_err(|_| format!("invalid count '{}'", text))
+}
+
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    /// splits instructions into 60hz frames, so timers run as they would in a window
+    scheduler: Scheduler,
+    /// instructions left in the current frame, a new one starts when none are
+    frame_remaining: u32,
+    /// false until the first frame starts
+    in_frame: bool,
+    /// chip-8 keys held down
+    keypad: [bool; 16],
+    /// the last command that runs the rom, repeated by an empty line
+    last_run: Option<Command>,
+}
+
+impl Debugger {
+
+    pub fn new(instructions_per_second: u32) -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            scheduler: Scheduler::new(instructions_per_second),
+            frame_remaining: 0,
+            in_frame: false,
+            keypad: [false; 16],
+            last_run: None,
+        }
+    }
+
+    // Parses and runs one line typed at the prompt, returning what to print.
+    // None means quit.
+    pub fn command(&mut self, chip: &mut CPU, line: &str) -> Option<String> {
+
+        let command = if line.trim().is_empty() {
+            match self.last_run {
+                Some(ref command) => command.clone(),
+                None => return Some(String::new()),
+            }
+        } else {
+            match Command::parse(line) {
+                Ok(command) => command,
+                Err(message) => return Some(message),
+            }
+        };
+
+        match command {
+            Command::Step(_) | Command::Next | Command::Finish | Command::Continue(_) => {
+                self.last_run = Some(command.clone());
+            },
+            Command::Quit => return None,
+            _ => (),
+        }
+
+        Some(self.execute(chip, command))
+    }
+
+    pub fn execute(&mut self, chip: &mut CPU, command: Command) -> String {
+        match command {
+            Command::Break(address) => {
+                self.breakpoints.insert(address);
+                format!("breakpoint at {:#06x}", address)
+            },
+            Command::Delete(address) => {
+                if self.breakpoints.remove(&address) {
+                    format!("deleted breakpoint at {:#06x}", address)
+                } else {
+                    format!("no breakpoint at {:#06x}", address)
+                }
+            },
+            Command::Breakpoints => {
+                if self.breakpoints.is_empty() {
+                    String::from("no breakpoints")
+                } else {
+                    let addresses: Vec<String> = self.breakpoints.iter().map(|address| format!("{:#06x}", address)).collect();
+                    addresses.join("\n")
+                }
+            },
+            Command::Step(count) => {
+                // every step runs an instruction, so a count always ends and
+                // needs no frame limit
+                let mut steps = 0;
+                let stop = self.run(chip, u32::MAX, |_| {
+                    steps += 1;
+                    steps >= count
+                });
+                self.report(chip, stop)
+            },
+            Command::Next => {
+                let stop = match chip.current_instruction() {
+                    Some(Instruction::Call(_)) => {
+                        let depth = chip.stack().len();
+                        let return_address = chip.program_counter().wrapping_add(2);
+                        self.run(chip, DEFAULT_CONTINUE_FRAMES, |chip| {
+                            chip.stack().len() == depth && chip.program_counter() == return_address
+                        })
+                    },
+                    _ => self.run(chip, DEFAULT_CONTINUE_FRAMES, |_| true),
+                };
+                self.report(chip, stop)
+            },
+            Command::Finish => {
+                let depth = chip.stack().len();
+                if depth == 0 {
+                    return String::from("not in a subroutine");
+                }
+                let stop = self.run(chip, DEFAULT_CONTINUE_FRAMES, |chip| chip.stack().len() < depth);
+                self.report(chip, stop)
+            },
+            Command::Continue(frames) => {
+                let stop = self.run(chip, frames, |_| false);
+                self.report(chip, stop)
+            },
+            Command::Registers => Debugger::registers(chip),
+            Command::Memory(address, length) => Debugger::memory(chip, address as usize, length),
+            Command::Set(register, value) => {
+                match register {
+                    Register::V(number) => chip.set_register(number as usize, value as u8),
+                    Register::Index => chip.set_index(value),
+                    Register::ProgramCounter => chip.set_program_counter(value),
+                    Register::DelayTimer => chip.set_delay_timer(value as u8),
+                    Register::SoundTimer => chip.set_sound_timer(value as u8),
+                }
+                Debugger::registers(chip)
+            },
+            Command::Write(address, bytes) => {
+                let address = address as usize;
+                if address + bytes.len() > MEMORY_SIZE {
+                    return String::from("write goes past the end of memory");
+                }
+                chip.memory[address..address + bytes.len()].copy_from_slice(&bytes);
+                Debugger::memory(chip, address, bytes.len())
+            },
+            Command::Watch(watchpoint) => {
+                let id = chip.add_watchpoint(watchpoint);
+                format!("watchpoint {}: {}", id, watchpoint)
+            },
+            Command::Unwatch(id) => {
+                if chip.remove_watchpoint(id) {
+                    format!("deleted watchpoint {}", id)
+                } else {
+                    format!("no watchpoint {}", id)
+                }
+            },
+            Command::Watchpoints => {
+                if chip.watchpoints().is_empty() {
+                    String::from("no watchpoints")
+                } else {
+                    let watchpoints: Vec<String> = chip.watchpoints().iter()
+                        .map(|(id, watchpoint)| format!("watchpoint {}: {}", id, watchpoint))
+                        .collect();
+                    watchpoints.join("\n")
+                }
+            },
+            Command::Keys(keypad) => {
+                self.keypad = keypad;
+                let held: Vec<String> = (0..16).filter(|&key| keypad[key]).map(|key| format!("{:X}", key)).collect();
+                if held.is_empty() { String::from("no keys held") } else { format!("holding {}", held.join(" ")) }
+            },
+            Command::Screen => headless::dump(&chip.state(), DumpFormat::Ascii).trim_end().to_string(),
+            Command::Help => String::from(HELP),
+            Command::Quit => String::new(),
+        }
+    }
+
+    // Runs instructions until done says so after one, a breakpoint is reached,
+    // or the rom stops. The first instruction always runs, so running again
+    // from a breakpoint gets past it.
+    pub fn run<F: FnMut(&CPU) -> bool>(&mut self, chip: &mut CPU, max_frames: u32, mut done: F) -> Stop {
+
+        if chip.state().exited {
+            return Stop::Exited;
+        }
+        if chip.is_halted() {
+            return Stop::Halted(None);
+        }
+        // running again carries on past the watchpoint that paused
+        chip.resume();
+
+        let mut frames = 0;
+
+        loop {
+            // at low speeds some frames have no instructions at all
+            while self.frame_remaining == 0 {
+                if frames == max_frames {
+                    return Stop::FrameLimit;
+                }
+                if self.in_frame {
+                    chip.end_frame();
+                }
+                chip.begin_frame(self.keypad);
+                self.in_frame = true;
+                self.frame_remaining = self.scheduler.instructions_for_frame();
+                frames += 1;
+            }
+            self.frame_remaining -= 1;
+
+            match chip.step() {
+                Ok(StepOutcome::Executed) | Ok(StepOutcome::Waiting) => (),
+                Ok(StepOutcome::Exited) => return Stop::Exited,
+                Ok(StepOutcome::Halted) => return Stop::Halted(None),
+                Ok(StepOutcome::Paused) => return Stop::Watchpoint,
+                Err(fault) => return Stop::Halted(Some(fault)),
+            }
+            if chip.state().exited {
+                return Stop::Exited;
+            }
+
+            if done(chip) {
+                return Stop::Done;
+            }
+            if self.breakpoints.contains(&chip.program_counter()) {
+                return Stop::Breakpoint(chip.program_counter());
+            }
+        }
+    }
+
+    // Watchpoints hit along the way, why running stopped, then where it stopped
+    fn report(&self, chip: &mut CPU, stop: Stop) -> String {
+        let mut hits = String::new();
+        for hit in chip.take_watch_hits() {
+            let _ = writeln!(hits, "{}", hit);
+        }
+        let reason = match stop {
+            Stop::Done => String::new(),
+            Stop::Breakpoint(address) => format!("breakpoint at {:#06x}\n", address),
+            Stop::Exited => String::from("the rom has exited\n"),
+            Stop::Halted(Some(fault)) => format!("cpu halted: {}\n", fault),
+            Stop::Halted(None) => String::from("the cpu is halted\n"),
+            Stop::FrameLimit => String::from("stopped after running out of frames\n"),
+            Stop::Watchpoint => String::from("paused at a watchpoint\n"),
+        };
+        hits + &reason + &Debugger::location(chip)
+    }
+
+    // The program counter and the instruction there
+    pub fn location(chip: &CPU) -> String {
+        let pc = chip.program_counter() as usize;
+        let opcode = (chip.memory[pc] as u16) << 8 | chip.memory[(pc + 1) % MEMORY_SIZE] as u16;
+        match chip.current_instruction() {
+            Some(instruction) => format!("{:#06x}: {:04x}  {}", pc, opcode, instruction),
+            None => format!("{:#06x}: {:04x}  (invalid)", pc, opcode),
+        }
+    }
+
+    fn registers(chip: &CPU) -> String {
+        let mut output = String::new();
+        for (row, values) in chip.registers().chunks(8).enumerate() {
+            let cells: Vec<String> = values.iter().enumerate()
+                .map(|(i, value)| format!("V{:X}={:02x}", row * 8 + i, value))
+                .collect();
+            let _ = writeln!(output, "{}", cells.join(" "));
+        }
+        let _ = writeln!(output, "I={:#06x} DT={:02x} ST={:02x} PC={:#06x}",
+                         chip.index(), chip.delay_timer(), chip.sound_timer(), chip.program_counter());
+        let stack: Vec<String> = chip.stack().iter().map(|address| format!("{:#06x}", address)).collect();
+        let _ = write!(output, "stack: {}", if stack.is_empty() { String::from("empty") } else { stack.join(" ") });
+        output
+    }
+
+    // 16 bytes a line, with printable ascii alongside
+    fn memory(chip: &CPU, start: usize, length: usize) -> String {
+        let end = (start + length).min(MEMORY_SIZE);
+        let mut lines = Vec::new();
+        for line_start in (start..end).step_by(16) {
+            let bytes = &chip.memory[line_start..(line_start + 16).min(end)];
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let text: String = bytes.iter()
+                .map(|&byte| if (0x20..0x7F).contains(&byte) { byte as char } else { '.' })
+                .collect();
+            lines.push(format!("{:#06x}: {:<47}  {}", line_start, hex.join(" "), text));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+#[path = "./debugger_test.rs"]
+mod debugger_test;
@@ -0,0 +1,172 @@
+use super::*;
+use crate::quirks::Quirks;
+
+// A main loop that calls a subroutine at 0x300 which adds to V1 and returns
+fn debug_chip() -> CPU {
+    let mut chip = CPU::new(Quirks::default());
+    let program = [
+        0x60, 0x05, // 0x200: LD V0, 5
+        0x23, 0x00, // 0x202: CALL 0x300
+        0x70, 0x01, // 0x204: ADD V0, 1
+        0x12, 0x02, // 0x206: JP 0x202
+    ];
+    chip.memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+    chip.memory[0x300..0x306].copy_from_slice(&[0x71, 0x02, 0x71, 0x03, 0x00, 0xEE]);
+    chip
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(Command::parse("b 0x204"), Ok(Command::Break(0x204)));
+    assert_eq!(Command::parse("break 300"), Ok(Command::Break(0x300)));
+    assert_eq!(Command::parse("s"), Ok(Command::Step(1)));
+    assert_eq!(Command::parse("step 10"), Ok(Command::Step(10)));
+    assert_eq!(Command::parse("c"), Ok(Command::Continue(DEFAULT_CONTINUE_FRAMES)));
+    assert_eq!(Command::parse("x 200 32"), Ok(Command::Memory(0x200, 32)));
+    assert_eq!(Command::parse("set vA ff"), Ok(Command::Set(Register::V(0xA), 0xFF)));
+    assert_eq!(Command::parse("set PC 0x202"), Ok(Command::Set(Register::ProgramCounter, 0x202)));
+    assert_eq!(Command::parse("write 300 01 ff"), Ok(Command::Write(0x300, vec![0x01, 0xFF])));
+    let mut keypad = [false; 16];
+    keypad[0x5] = true;
+    keypad[0xF] = true;
+    assert_eq!(Command::parse("keys 5 f"), Ok(Command::Keys(keypad)));
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(Command::parse("jump"), Err(String::from("unknown command 'jump', see help")));
+    assert_eq!(Command::parse("break"), Err(String::from("break needs more arguments, see help")));
+    assert_eq!(Command::parse("break zz"), Err(String::from("invalid hex number 'zz'")));
+    assert_eq!(Command::parse("set vg 1"), Err(String::from("unknown register 'vg'")));
+    assert_eq!(Command::parse("write 300"), Err(String::from("write needs more arguments, see help")));
+    assert_eq!(Command::parse("keys 10"), Err(String::from("invalid key '10'")));
+    assert_eq!(Command::parse("step 0"), Err(String::from("step needs a count of at least 1")));
+}
+
+#[test]
+fn test_step_and_next() {
+    let mut chip = debug_chip();
+    let mut debugger = Debugger::new(700);
+
+    assert_eq!(debugger.command(&mut chip, "step"), Some(String::from("0x0202: 2300  CALL 0x300")));
+    // next runs the whole subroutine
+    assert_eq!(debugger.command(&mut chip, "next"), Some(String::from("0x0204: 7001  ADD V0, 0x01")));
+    assert_eq!(chip.registers()[1], 5);
+    // an empty line repeats it
+    debugger.command(&mut chip, "");
+    assert_eq!(chip.program_counter(), 0x206);
+    assert_eq!(chip.registers()[0], 6);
+}
+
+#[test]
+fn test_step_past_the_continue_limit() {
+    let mut chip = debug_chip();
+    let mut debugger = Debugger::new(700);
+
+    // more instructions than continue runs in a minute of emulated time
+    assert_eq!(debugger.command(&mut chip, "step 50000"), Some(String::from("0x0300: 7102  ADD V1, 0x02")));
+    assert_eq!(chip.instruction_count(), 50000);
+}
+
+#[test]
+fn test_step_into_and_finish() {
+    let mut chip = debug_chip();
+    let mut debugger = Debugger::new(700);
+
+    debugger.command(&mut chip, "step 2");
+    assert_eq!(chip.program_counter(), 0x300);
+    assert_eq!(chip.stack(), [0x204]);
+
+    assert_eq!(debugger.command(&mut chip, "finish"), Some(String::from("0x0204: 7001  ADD V0, 0x01")));
+    assert!(chip.stack().is_empty());
+    assert_eq!(debugger.command(&mut chip, "finish"), Some(String::from("not in a subroutine")));
+}
+
+#[test]
+fn test_breakpoints() {
+    let mut chip = debug_chip();
+    let mut debugger = Debugger::new(700);
+
+    debugger.command(&mut chip, "break 302");
+    assert_eq!(debugger.command(&mut chip, "c"), Some(String::from("breakpoint at 0x0302\n0x0302: 7103  ADD V1, 0x03")));
+    assert_eq!(chip.registers()[1], 2);
+
+    // continuing from a breakpoint goes round the loop back to it
+    debugger.command(&mut chip, "c");
+    assert_eq!(chip.program_counter(), 0x302);
+    assert_eq!(chip.registers()[1], 7);
+
+    // next stops at a breakpoint inside the call
+    debugger.command(&mut chip, "delete 302");
+    debugger.command(&mut chip, "b 300");
+    debugger.command(&mut chip, "finish");
+    debugger.command(&mut chip, "step 2");
+    assert_eq!(debugger.command(&mut chip, "next"), Some(String::from("breakpoint at 0x0300\n0x0300: 7102  ADD V1, 0x02")));
+    assert_eq!(debugger.command(&mut chip, "breakpoints"), Some(String::from("0x0300")));
+}
+
+#[test]
+fn test_watchpoints() {
+    let mut chip = debug_chip();
+    let mut debugger = Debugger::new(700);
+
+    assert_eq!(debugger.command(&mut chip, "watch v1 == 5 pause"), Some(String::from("watchpoint 1: V1 write == 0x5 pause")));
+    assert_eq!(debugger.command(&mut chip, "c"), Some(String::from(
+        "watchpoint 1: V1 changed at 0x0302, 0x02 -> 0x05\npaused at a watchpoint\n0x0304: 00ee  RET")));
+
+    // removing the only watchpoint leaves none
+    debugger.command(&mut chip, "unwatch 1");
+    assert_eq!(debugger.command(&mut chip, "watchpoints"), Some(String::from("no watchpoints")));
+    assert_eq!(debugger.command(&mut chip, "unwatch 1"), Some(String::from("no watchpoint 1")));
+    assert!(Command::parse("watch v1 read").is_err());
+}
+
+#[test]
+fn test_continue_stops() {
+    let mut chip = debug_chip();
+    let mut debugger = Debugger::new(600);
+    assert_eq!(debugger.run(&mut chip, 2, |_| false), Stop::FrameLimit);
+    assert_eq!(chip.instruction_count(), 20);
+
+    // the timers count down once a frame
+    chip.set_delay_timer(10);
+    debugger.run(&mut chip, 4, |_| false);
+    assert_eq!(chip.delay_timer(), 6);
+
+    let mut chip = CPU::new(Quirks::default());
+    let mut debugger = Debugger::new(700);
+    assert_eq!(debugger.run(&mut chip, 10, |_| false), Stop::Halted(Some(CpuFault::InvalidOpcode { pc: 0x200, opcode: 0 })));
+    assert_eq!(debugger.run(&mut chip, 10, |_| false), Stop::Halted(None));
+
+    chip = CPU::new(Quirks::default());
+    chip.memory[0x200..0x202].copy_from_slice(&[0x00, 0xFD]);
+    assert_eq!(debugger.run(&mut chip, 10, |_| false), Stop::Exited);
+}
+
+#[test]
+fn test_registers_and_memory() {
+    let mut chip = debug_chip();
+    let mut debugger = Debugger::new(700);
+
+    let registers = debugger.command(&mut chip, "set v3 2a").unwrap();
+    assert!(registers.starts_with("V0=00 V1=00 V2=00 V3=2a "));
+    assert!(registers.ends_with("I=0x0000 DT=00 ST=00 PC=0x0200\nstack: empty"));
+
+    debugger.command(&mut chip, "set i 300");
+    assert_eq!(chip.index(), 0x300);
+    debugger.command(&mut chip, "set dt 3c");
+    assert_eq!(chip.delay_timer(), 0x3C);
+
+    assert_eq!(
+        debugger.command(&mut chip, "write 310 48 69 00"),
+        Some(format!("0x0310: {:<47}  Hi.", "48 69 00"))
+    );
+    let dump = debugger.command(&mut chip, "x 0x300 20").unwrap();
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("0x0300: 71 02 71 03 00 ee 00"));
+    assert!(lines[1].starts_with("0x0310: 48 69 00 00  "));
+
+    assert_eq!(debugger.command(&mut chip, "write ffff 01 02"), Some(String::from("write goes past the end of memory")));
+    assert_eq!(debugger.command(&mut chip, "quit"), None);
+}
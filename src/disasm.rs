@@ -0,0 +1,421 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::cpu::PROGRAM_START;
+use crate::instruction::Instruction;
+
+// Turns a rom back into assembly. Code is found by following control flow from
+// the start of the program: jumps, calls, both sides of every skip, and the
+// jump tables BNNN indexes into. Anything never reached is data. Addresses an
+// instruction can reach get labels, and data that's loaded into I and then
+// drawn is shown as a picture of the sprite.
+//
+// Instructions can start at odd addresses, the walk doesn't assume they're
+// aligned. When two instructions found this way overlap, the first one is
+// written out as data so every label still falls on something that's emitted.
+
+// How the assembly is written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Syntax {
+    /// Cowgod's mnemonics with the address and opcode of every instruction in
+    /// a comment, which the assembler module reads back
+    Plain,
+    /// Octo's syntax, which assembles back into the same rom
+    Octo,
+}
+
+// What an address is the target of, which decides its label
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    /// loaded into I
+    Data,
+    /// jumped to or skipped to
+    Code,
+    /// called
+    Subroutine,
+}
+
+// Where an instruction can go next
+struct Flow {
+    /// addresses it can carry on at
+    next: Vec<usize>,
+    /// addresses it refers to, with what they are
+    targets: Vec<(usize, Target)>,
+}
+
+pub struct Disassembly<'a> {
+    rom: &'a [u8],
+    /// instructions found by the walk, by address
+    code: BTreeMap<usize, (Instruction, usize)>,
+    labels: BTreeMap<usize, Target>,
+    /// sprites drawn from data, by address: rows, and bytes per row
+    sprites: BTreeMap<usize, (usize, usize)>,
+}
+
+// Disassembles a rom loaded at PROGRAM_START
+pub fn disassemble(rom: &[u8], syntax: Syntax) -> String {
+    Disassembly::new(rom).write(syntax)
+}
+
+impl<'a> Disassembly<'a> {
+
+    pub fn new(rom: &'a [u8]) -> Disassembly<'a> {
+
+        let mut disassembly = Disassembly {
+            rom: rom,
+            code: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            sprites: BTreeMap::new(),
+        };
+
+        let mut pending = vec![PROGRAM_START];
+        while let Some(address) = pending.pop() {
+            if disassembly.code.contains_key(&address) {
+                continue;
+            }
+            let (instruction, size) = match disassembly.instruction_at(address) {
+                Some(found) => found,
+                None => continue,
+            };
+            disassembly.code.insert(address, (instruction, size));
+
+            let flow = disassembly.flow(address, instruction, size);
+            for (target, kind) in flow.targets {
+                let label = disassembly.labels.entry(target).or_insert(kind);
+                *label = (*label).max(kind);
+            }
+            pending.extend(flow.next);
+        }
+
+        disassembly.find_sprites();
+        disassembly
+    }
+
+    /// Addresses that hold instructions the program can reach
+    pub fn code_addresses(&self) -> impl Iterator<Item = usize> + '_ {
+        self.code.keys().cloned()
+    }
+
+    // The instruction at an address, with its size in bytes, if the address is
+    // inside the rom and holds a valid opcode
+    fn instruction_at(&self, address: usize) -> Option<(Instruction, usize)> {
+        let word = |address: usize| {
+            let offset = address.checked_sub(PROGRAM_START)?;
+            match (self.rom.get(offset), self.rom.get(offset + 1)) {
+                (Some(&high), Some(&low)) => Some((high as u16) << 8 | low as u16),
+                _ => None,
+            }
+        };
+
+        let opcode = word(address)?;
+        let instruction = Instruction::decode(opcode).filter(|instruction| instruction.encode() == opcode)?;
+        match instruction {
+            // the address is the word that follows
+            Instruction::LoadLongIndex => word(address + 2).map(|_| (instruction, 4)),
+            _ => Some((instruction, 2)),
+        }
+    }
+
+    fn flow(&self, address: usize, instruction: Instruction, size: usize) -> Flow {
+
+        let after = address + size;
+        let mut flow = Flow { next: Vec::new(), targets: Vec::new() };
+
+        match instruction {
+            Instruction::Jump(nnn) => {
+                flow.next.push(nnn as usize);
+                flow.targets.push((nnn as usize, Target::Code));
+            },
+            Instruction::Call(nnn) => {
+                flow.next.push(nnn as usize);
+                flow.next.push(after);
+                flow.targets.push((nnn as usize, Target::Subroutine));
+            },
+            Instruction::JumpOffset(nnn) => {
+                // usually a table of jumps, follow every entry in it
+                let mut entry = nnn as usize;
+                flow.targets.push((entry, Target::Code));
+                flow.next.push(entry);
+                while let Some((Instruction::Jump(_), _)) = self.instruction_at(entry) {
+                    flow.next.push(entry);
+                    entry += 2;
+                }
+            },
+            Instruction::Return | Instruction::Exit => (),
+            Instruction::SkipEqualByte(..) | Instruction::SkipNotEqualByte(..) | Instruction::SkipEqual(..) |
+            Instruction::SkipNotEqual(..) | Instruction::SkipKey(_) | Instruction::SkipNotKey(_) => {
+                // a skip goes over all four bytes of a long load
+                let skipped = self.instruction_at(after).map_or(2, |(_, size)| size);
+                flow.next.push(after);
+                flow.next.push(after + skipped);
+            },
+            Instruction::LoadIndex(nnn) => {
+                flow.targets.push((nnn as usize, Target::Data));
+                flow.next.push(after);
+            },
+            Instruction::LoadLongIndex => {
+                let offset = address + 2 - PROGRAM_START;
+                let nnnn = (self.rom[offset] as usize) << 8 | self.rom[offset + 1] as usize;
+                flow.targets.push((nnnn, Target::Data));
+                flow.next.push(after);
+            },
+            _ => flow.next.push(after),
+        }
+
+        flow
+    }
+
+    // Data pointed to by LD I that's drawn by the next few instructions. Looking
+    // further would mostly find draws using some other I.
+    fn find_sprites(&mut self) {
+
+        let mut sprites = BTreeMap::new();
+
+        for (&address, &(instruction, size)) in self.code.iter() {
+            let target = match instruction {
+                Instruction::LoadIndex(nnn) => nnn as usize,
+                _ => continue,
+            };
+
+            let mut next = address + size;
+            for _ in 0..8 {
+                let (following, size) = match self.code.get(&next) {
+                    Some(&found) => found,
+                    None => break,
+                };
+                match following {
+                    Instruction::Draw(_, _, 0) => {
+                        sprites.insert(target, (16, 2));
+                        break;
+                    },
+                    Instruction::Draw(_, _, n) => {
+                        let rows = sprites.get(&target).map_or(0, |&(rows, _)| rows);
+                        sprites.insert(target, (rows.max(n as usize), 1));
+                        break;
+                    },
+                    Instruction::Jump(_) | Instruction::JumpOffset(_) | Instruction::Call(_) |
+                    Instruction::Return | Instruction::Exit | Instruction::LoadIndex(_) |
+                    Instruction::LoadLongIndex | Instruction::AddIndex(_) | Instruction::LoadFont(_) |
+                    Instruction::LoadLargeFont(_) | Instruction::StoreRegisters(_) |
+                    Instruction::LoadRegisters(_) => break,
+                    _ => next += size,
+                }
+            }
+        }
+
+        // only sprites inside the rom, clear of any code
+        self.sprites = sprites.into_iter()
+            .filter(|&(address, (rows, width))| {
+                address >= PROGRAM_START
+                    && address + rows * width <= PROGRAM_START + self.rom.len()
+                    && (address..address + rows * width).all(|byte| !self.is_code(byte))
+            })
+            .collect();
+    }
+
+    // Whether a byte belongs to an instruction
+    fn is_code(&self, address: usize) -> bool {
+        match self.code.range(..=address).next_back() {
+            Some((&start, &(_, size))) => address < start + size,
+            None => false,
+        }
+    }
+
+    // Label for an address, None if nothing refers to it or it isn't in the rom
+    fn label(&self, address: usize) -> Option<String> {
+        if address < PROGRAM_START || address >= PROGRAM_START + self.rom.len() {
+            return None;
+        }
+        self.labels.get(&address).map(|&target| match target {
+            Target::Subroutine => format!("sub_{:03x}", address),
+            Target::Code => format!("label_{:03x}", address),
+            Target::Data if self.sprites.contains_key(&address) => format!("sprite_{:03x}", address),
+            Target::Data => format!("data_{:03x}", address),
+        })
+    }
+
+    // An address operand, by label where there is one
+    fn address(&self, address: u16, syntax: Syntax) -> String {
+        match self.label(address as usize) {
+            Some(label) => label,
+            None if syntax == Syntax::Octo => format!("{:#x}", address),
+            None => format!("{:#05x}", address),
+        }
+    }
+
+    pub fn write(&self, syntax: Syntax) -> String {
+
+        let end = PROGRAM_START + self.rom.len();
+        // addresses that have to start a new line, so their labels can go there
+        let boundaries: BTreeSet<usize> = self.code.keys()
+            .chain(self.sprites.keys())
+            .cloned()
+            .chain(self.labels.keys().cloned().filter(|&address| self.label(address).is_some()))
+            .collect();
+        let boundary_inside = |start: usize, end: usize| boundaries.range(start + 1..end).next().is_some();
+
+        let mut output = String::new();
+        let mut address = PROGRAM_START;
+
+        while address < end {
+
+            // Octo starts running at main, wherever it is
+            if address == PROGRAM_START && syntax == Syntax::Octo {
+                let _ = writeln!(output, ": main");
+            }
+            if let Some(label) = self.label(address) {
+                match syntax {
+                    Syntax::Plain => { let _ = writeln!(output, "{}:", label); },
+                    Syntax::Octo => { let _ = writeln!(output, ": {}", label); },
+                }
+            }
+
+            if let Some(&(instruction, size)) = self.code.get(&address) {
+                if !boundary_inside(address, address + size) {
+                    self.write_instruction(&mut output, address, instruction, size, syntax);
+                    address += size;
+                    continue;
+                }
+            }
+
+            if let Some(&(rows, width)) = self.sprites.get(&address) {
+                if !boundary_inside(address, address + rows * width) {
+                    for row in 0..rows {
+                        self.write_sprite_row(&mut output, address + row * width, width, syntax);
+                    }
+                    address += rows * width;
+                    continue;
+                }
+            }
+
+            // a run of data, up to 8 bytes a line and never past anything with a label
+            let mut run_end = address + 1;
+            while run_end < end && run_end - address < 8 && !boundaries.contains(&run_end) {
+                run_end += 1;
+            }
+            self.write_data(&mut output, address, run_end, syntax);
+            address = run_end;
+        }
+
+        output
+    }
+
+    fn write_instruction(&self, output: &mut String, address: usize, instruction: Instruction, size: usize,
+                         syntax: Syntax) {
+        let offset = address - PROGRAM_START;
+        let bytes = &self.rom[offset..offset + size];
+
+        match syntax {
+            Syntax::Plain => {
+                let text = match instruction {
+                    Instruction::Jump(nnn) => format!("JP {}", self.address(nnn, syntax)),
+                    Instruction::Call(nnn) => format!("CALL {}", self.address(nnn, syntax)),
+                    Instruction::JumpOffset(nnn) => format!("JP V0, {}", self.address(nnn, syntax)),
+                    Instruction::LoadIndex(nnn) => format!("LD I, {}", self.address(nnn, syntax)),
+                    Instruction::LoadLongIndex => {
+                        format!("LD I, LONG {}", self.address((bytes[2] as u16) << 8 | bytes[3] as u16, syntax))
+                    },
+                    _ => instruction.to_string(),
+                };
+                let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                let _ = writeln!(output, "    {:<24}; {:#06x}: {}", text, address, hex.join(""));
+            },
+            Syntax::Octo => {
+                let _ = writeln!(output, "    {}", self.octo(instruction, bytes));
+            },
+        }
+    }
+
+    // The instruction in Octo's syntax. Skips are written as Octo's 'if', which
+    // is the opposite condition: the next instruction runs if the skip doesn't.
+    fn octo(&self, instruction: Instruction, bytes: &[u8]) -> String {
+        let address = |nnn: u16| self.address(nnn, Syntax::Octo);
+        match instruction {
+            Instruction::ScrollDown(n) => format!("scroll-down {}", n),
+            Instruction::ScrollUp(n) => format!("scroll-up {}", n),
+            Instruction::Clear => String::from("clear"),
+            Instruction::Return => String::from("return"),
+            Instruction::ScrollRight => String::from("scroll-right"),
+            Instruction::ScrollLeft => String::from("scroll-left"),
+            Instruction::Exit => String::from("exit"),
+            Instruction::LowRes => String::from("lores"),
+            Instruction::HighRes => String::from("hires"),
+            Instruction::Jump(nnn) => format!("jump {}", address(nnn)),
+            // a subroutine is called by naming it
+            Instruction::Call(nnn) => match self.label(nnn as usize) {
+                Some(label) => label,
+                None => format!(":call {:#x}", nnn),
+            },
+            Instruction::SkipEqualByte(x, kk) => format!("if v{:x} != {:#04x} then", x, kk),
+            Instruction::SkipNotEqualByte(x, kk) => format!("if v{:x} == {:#04x} then", x, kk),
+            Instruction::SkipEqual(x, y) => format!("if v{:x} != v{:x} then", x, y),
+            Instruction::SaveRange(x, y) => format!("save v{:x} - v{:x}", x, y),
+            Instruction::LoadRange(x, y) => format!("load v{:x} - v{:x}", x, y),
+            Instruction::LoadByte(x, kk) => format!("v{:x} := {:#04x}", x, kk),
+            Instruction::AddByte(x, kk) => format!("v{:x} += {:#04x}", x, kk),
+            Instruction::Load(x, y) => format!("v{:x} := v{:x}", x, y),
+            Instruction::Or(x, y) => format!("v{:x} |= v{:x}", x, y),
+            Instruction::And(x, y) => format!("v{:x} &= v{:x}", x, y),
+            Instruction::Xor(x, y) => format!("v{:x} ^= v{:x}", x, y),
+            Instruction::Add(x, y) => format!("v{:x} += v{:x}", x, y),
+            Instruction::Subtract(x, y) => format!("v{:x} -= v{:x}", x, y),
+            Instruction::ShiftRight(x, y) => format!("v{:x} >>= v{:x}", x, y),
+            Instruction::SubtractNegated(x, y) => format!("v{:x} =- v{:x}", x, y),
+            Instruction::ShiftLeft(x, y) => format!("v{:x} <<= v{:x}", x, y),
+            Instruction::SkipNotEqual(x, y) => format!("if v{:x} == v{:x} then", x, y),
+            Instruction::LoadIndex(nnn) => format!("i := {}", address(nnn)),
+            Instruction::JumpOffset(nnn) => format!("jump0 {}", address(nnn)),
+            Instruction::Random(x, kk) => format!("v{:x} := random {:#04x}", x, kk),
+            Instruction::Draw(x, y, n) => format!("sprite v{:x} v{:x} {}", x, y, n),
+            Instruction::SkipKey(x) => format!("if v{:x} -key then", x),
+            Instruction::SkipNotKey(x) => format!("if v{:x} key then", x),
+            Instruction::LoadLongIndex => format!("i := long {}", address((bytes[2] as u16) << 8 | bytes[3] as u16)),
+            Instruction::SelectPlanes(n) => format!("plane {}", n),
+            Instruction::LoadAudio => String::from("audio"),
+            Instruction::LoadDelay(x) => format!("v{:x} := delay", x),
+            Instruction::WaitKey(x) => format!("v{:x} := key", x),
+            Instruction::SetDelay(x) => format!("delay := v{:x}", x),
+            Instruction::SetSound(x) => format!("buzzer := v{:x}", x),
+            Instruction::AddIndex(x) => format!("i += v{:x}", x),
+            Instruction::LoadFont(x) => format!("i := hex v{:x}", x),
+            Instruction::LoadLargeFont(x) => format!("i := bighex v{:x}", x),
+            Instruction::StoreBcd(x) => format!("bcd v{:x}", x),
+            Instruction::SetPitch(x) => format!("pitch := v{:x}", x),
+            Instruction::StoreRegisters(x) => format!("save v{:x}", x),
+            Instruction::LoadRegisters(x) => format!("load v{:x}", x),
+            Instruction::StoreFlags(x) => format!("saveflags v{:x}", x),
+            Instruction::LoadFlags(x) => format!("loadflags v{:x}", x),
+        }
+    }
+
+    // One row of a sprite, with a picture of it in a comment
+    fn write_sprite_row(&self, output: &mut String, address: usize, width: usize, syntax: Syntax) {
+        let offset = address - PROGRAM_START;
+        let bytes = &self.rom[offset..offset + width];
+        let picture: String = bytes.iter()
+            .flat_map(|&byte| (0..8).rev().map(move |bit| if byte & (1 << bit) != 0 { '#' } else { '.' }))
+            .collect();
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:#04x}", byte)).collect();
+
+        match syntax {
+            Syntax::Plain => {
+                let _ = writeln!(output, "    {:<24}; {:#06x}: {}", format!("db {}", hex.join(", ")), address, picture);
+            },
+            Syntax::Octo => { let _ = writeln!(output, "    {:<9}  # {}", hex.join(" "), picture); },
+        }
+    }
+
+    fn write_data(&self, output: &mut String, start: usize, end: usize, syntax: Syntax) {
+        let bytes = &self.rom[start - PROGRAM_START..end - PROGRAM_START];
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:#04x}", byte)).collect();
+
+        match syntax {
+            Syntax::Plain => { let _ = writeln!(output, "    {:<24}; {:#06x}", format!("db {}", hex.join(", ")), start); },
+            Syntax::Octo => { let _ = writeln!(output, "    {}", hex.join(" ")); },
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./disasm_test.rs"]
+mod disasm_test;
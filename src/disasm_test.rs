@@ -0,0 +1,101 @@
+use super::*;
+
+// Clears the screen, draws a three row sprite and loops on a skip, with a byte
+// of data nothing refers to after the sprite
+const PROGRAM: [u8; 18] = [
+    0x00, 0xE0, // 0x200: CLS
+    0xA2, 0x0E, // 0x202: LD I, 0x20E
+    0xD0, 0x13, // 0x204: DRW V0, V1, 3
+    0x22, 0x0C, // 0x206: CALL 0x20C
+    0x30, 0x00, // 0x208: SE V0, 0x00
+    0x12, 0x08, // 0x20A: JP 0x208
+    0x00, 0xEE, // 0x20C: RET
+    0x3C, 0x42, 0xFF, // 0x20E: sprite
+    0xAB,
+];
+
+#[test]
+fn test_octo() {
+    let expected = "\
+: main
+    clear
+    i := sprite_20e
+    sprite v0 v1 3
+    sub_20c
+: label_208
+    if v0 != 0x00 then
+    jump label_208
+: sub_20c
+    return
+: sprite_20e
+    0x3c       # ..####..
+    0x42       # .#....#.
+    0xff       # ########
+    0xab
+";
+    assert_eq!(disassemble(&PROGRAM, Syntax::Octo), expected);
+}
+
+#[test]
+fn test_plain() {
+    let text = disassemble(&PROGRAM, Syntax::Plain);
+    let lines: Vec<&str> = text.lines().collect();
+
+    assert_eq!(lines[0], "    CLS                     ; 0x0200: 00e0");
+    assert_eq!(lines[1], "    LD I, sprite_20e        ; 0x0202: a20e");
+    assert_eq!(lines[3], "    CALL sub_20c            ; 0x0206: 220c");
+    assert_eq!(lines[4], "label_208:");
+    assert!(lines.contains(&"    db 0x3c                 ; 0x020e: ..####.."));
+    assert_eq!(lines.last(), Some(&"    db 0xab                 ; 0x0211"));
+}
+
+#[test]
+fn test_odd_addresses() {
+    // a jump over one byte of data to an odd address
+    let rom = [0x12, 0x03, 0xFF, 0x00, 0xE0, 0x12, 0x03];
+    let disassembly = Disassembly::new(&rom);
+    assert_eq!(disassembly.code_addresses().collect::<Vec<_>>(), [0x200, 0x203, 0x205]);
+    assert_eq!(disassembly.write(Syntax::Octo), ": main\n    jump label_203\n    0xff\n: label_203\n    clear\n    jump label_203\n");
+}
+
+#[test]
+fn test_overlapping_instructions() {
+    // jumping into the middle of LD V0, 0x12 finds JP 0x212, which overlaps
+    // the jump after it, so both of those are written as data
+    let rom = [0x60, 0x12, 0x12, 0x01];
+    let disassembly = Disassembly::new(&rom);
+    assert_eq!(disassembly.code_addresses().collect::<Vec<_>>(), [0x200, 0x201, 0x202]);
+    assert_eq!(disassembly.write(Syntax::Octo), ": main\n    0x60\n: label_201\n    0x12\n    jump label_201\n");
+}
+
+#[test]
+fn test_jump_table() {
+    let rom = [
+        0x60, 0x02, // 0x200: LD V0, 2
+        0xB2, 0x04, // 0x202: JP V0, 0x204
+        0x12, 0x08, // 0x204: JP 0x208
+        0x12, 0x0A, // 0x206: JP 0x20A
+        0x00, 0xE0, // 0x208: CLS
+        0x00, 0xFD, // 0x20A: EXIT
+        0x01, 0x02, // 0x20C: never reached
+    ];
+    let disassembly = Disassembly::new(&rom);
+    assert_eq!(disassembly.code_addresses().collect::<Vec<_>>(), [0x200, 0x202, 0x204, 0x206, 0x208, 0x20A]);
+    let text = disassembly.write(Syntax::Octo);
+    assert!(text.contains("    jump0 label_204\n: label_204\n    jump label_208\n    jump label_20a\n"));
+    assert!(text.ends_with("    exit\n    0x01 0x02\n"));
+}
+
+#[test]
+fn test_skip_over_long_load() {
+    let rom = [
+        0x30, 0x01, // 0x200: SE V0, 0x01
+        0xF0, 0x00, 0x02, 0x0A, // 0x202: LD I, LONG 0x20A
+        0x00, 0xFD, // 0x206: EXIT
+        0x00, 0x00, // 0x208: never reached
+        0x81, // 0x20A
+    ];
+    let disassembly = Disassembly::new(&rom);
+    assert_eq!(disassembly.code_addresses().collect::<Vec<_>>(), [0x200, 0x202, 0x206]);
+    assert!(disassembly.write(Syntax::Octo).contains("    i := long data_20a\n    exit\n    0x00 0x00\n: data_20a\n    0x81\n"));
+}
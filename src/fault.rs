@@ -0,0 +1,126 @@
+use std::fmt;
+
+// Everything a rom can do that the cpu can't carry on from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CpuFault {
+    /// CALL with all 16 stack entries in use
+    StackOverflow { pc: u16 },
+    /// RET with nothing on the stack
+    StackUnderflow { pc: u16 },
+    /// an instruction reached past the end of memory
+    MemoryOutOfBounds { pc: u16, address: usize },
+    /// an opcode this interpreter doesn't know
+    InvalidOpcode { pc: u16, opcode: u16 },
+    /// the program counter can't fetch a full instruction
+    PcOutOfRange { pc: u16 },
+}
+
+// Faults are grouped so a policy can be picked for each group
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    Stack,
+    Memory,
+    InvalidOpcode,
+    ProgramCounter,
+}
+
+// What the cpu does when it hits a fault
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultAction {
+    /// stop executing and report the fault
+    Halt,
+    /// skip the faulting instruction and carry on
+    Ignore,
+    /// print the fault to stderr, then carry on as with Ignore
+    Log,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FaultPolicy {
+    pub stack: FaultAction,
+    pub memory: FaultAction,
+    pub invalid_opcode: FaultAction,
+    pub program_counter: FaultAction,
+}
+
+// What happened during a step that didn't fault
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepOutcome {
+    /// an instruction ran
+    Executed,
+    /// the instruction is waiting on something (a key, vertical blank) and will run again
+    Waiting,
+    /// the program has run 00FD
+    Exited,
+    /// an earlier fault halted the cpu
+    Halted,
+    /// a watchpoint that pauses was hit, nothing more runs until the cpu is resumed
+    Paused,
+}
+
+impl CpuFault {
+
+    pub fn kind(&self) -> FaultKind {
+        match self {
+            CpuFault::StackOverflow { .. } | CpuFault::StackUnderflow { .. } => FaultKind::Stack,
+            CpuFault::MemoryOutOfBounds { .. } => FaultKind::Memory,
+            CpuFault::InvalidOpcode { .. } => FaultKind::InvalidOpcode,
+            CpuFault::PcOutOfRange { .. } => FaultKind::ProgramCounter,
+        }
+    }
+}
+
+impl fmt::Display for CpuFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CpuFault::StackOverflow { pc } => write!(f, "stack overflow at {:#06x}", pc),
+            CpuFault::StackUnderflow { pc } => write!(f, "stack underflow at {:#06x}", pc),
+            CpuFault::MemoryOutOfBounds { pc, address } => {
+                write!(f, "memory access out of bounds at {:#06x} (address {:#x})", pc, address)
+            },
+            CpuFault::InvalidOpcode { pc, opcode } => write!(f, "invalid opcode {:04x} at {:#06x}", opcode, pc),
+            CpuFault::PcOutOfRange { pc } => write!(f, "program counter out of range at {:#06x}", pc),
+        }
+    }
+}
+
+impl FaultPolicy {
+
+    /// Use the same action for every kind of fault
+    pub fn all(action: FaultAction) -> FaultPolicy {
+        FaultPolicy {
+            stack: action,
+            memory: action,
+            invalid_opcode: action,
+            program_counter: action,
+        }
+    }
+
+    pub fn action(&self, kind: FaultKind) -> FaultAction {
+        match kind {
+            FaultKind::Stack => self.stack,
+            FaultKind::Memory => self.memory,
+            FaultKind::InvalidOpcode => self.invalid_opcode,
+            FaultKind::ProgramCounter => self.program_counter,
+        }
+    }
+}
+
+impl Default for FaultPolicy {
+    fn default() -> FaultPolicy {
+        FaultPolicy::all(FaultAction::Halt)
+    }
+}
+
+impl FaultAction {
+
+    /// Looks up an action by name, used for the command line
+    pub fn from_name(name: &str) -> Option<FaultAction> {
+        match name.to_ascii_lowercase().as_str() {
+            "halt" => Some(FaultAction::Halt),
+            "ignore" => Some(FaultAction::Ignore),
+            "log" => Some(FaultAction::Log),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::fmt::Write;
+
+use crate::cpu::{CPU, State};
+use crate::fault::{CpuFault, StepOutcome};
+use crate::scheduler::Scheduler;
+use crate::SCREEN_HEIGHT;
+use crate::SCREEN_WIDTH;
+use crate::HIRES_SCREEN_HEIGHT;
+use crate::HIRES_SCREEN_WIDTH;
+
+// Runs a rom without a window, for scripted tests on machines with no display.
+
+// How long a headless run lasts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunLength {
+    Frames(u32),
+    Instructions(u32),
+}
+
+// How the final screen is written out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DumpFormat {
+    /// one character per pixel
+    Ascii,
+    /// plain (P1) portable bitmap, any lit plane is black
+    Pbm,
+    /// FNV-1a hash of the screen, for comparing against a known good run
+    Hash,
+}
+
+// Keys held down over time. A script is one step per line: the frame the step
+// starts on followed by the hex keys held from then on, for example
+//
+//     # wait a second, then hold 5 and 6 for half a second
+//     60 5 6
+//     90
+//
+// Blank lines and anything after a '#' are ignored.
+#[derive(Debug, Default, PartialEq)]
+pub struct KeyScript {
+    steps: Vec<(u32, [bool; 16])>,
+}
+
+impl KeyScript {
+
+    pub fn parse(text: &str) -> Result<KeyScript, String> {
+
+        let mut steps = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut words = line.split_whitespace();
+
+            let frame = match words.next() {
+                Some(word) => word.parse::<u32>()
+                    .map_err(|_| format!("line {}: invalid frame '{}'", number + 1, word))?,
+                None => continue,
+            };
+
+            let mut keypad = [false; 16];
+            for word in words {
+                match u8::from_str_radix(word, 16) {
+                    Ok(key) if key < 16 => keypad[key as usize] = true,
+                    _ => return Err(format!("line {}: invalid key '{}'", number + 1, word)),
+                }
+            }
+
+            steps.push((frame, keypad));
+        }
+
+        steps.sort_by_key(|&(frame, _)| frame);
+
+        Ok(KeyScript { steps: steps })
+    }
+
+    /// Keys held during the given frame
+    pub fn keys_at(&self, frame: u32) -> [bool; 16] {
+        self.steps.iter()
+            .take_while(|&&(start, _)| start <= frame)
+            .last()
+            .map_or([false; 16], |&(_, keypad)| keypad)
+    }
+}
+
+// Runs the cpu for the given length, stopping early if the rom exits, faults
+// or is paused by a watchpoint
+pub fn run(chip: &mut CPU, length: RunLength, speed: u32, script: &KeyScript) -> Result<(), CpuFault> {
+    match length {
+        RunLength::Frames(frames) => {
+            for frame in 0..frames {
+                let exited = chip.run_frame(script.keys_at(frame))?.exited;
+                if exited || chip.is_paused() {
+                    break;
+                }
+            }
+        },
+        RunLength::Instructions(count) => {
+            // step by hand so the run can end part way through a frame
+            let mut scheduler = Scheduler::new(speed);
+            let mut remaining = count;
+            let mut frame = 0;
+
+            while remaining > 0 {
+                chip.begin_frame(script.keys_at(frame));
+                let frame_length = scheduler.instructions_for_frame();
+
+                for _ in 0..frame_length.min(remaining) {
+                    match chip.step()? {
+                        StepOutcome::Exited | StepOutcome::Halted | StepOutcome::Paused => return Ok(()),
+                        StepOutcome::Executed | StepOutcome::Waiting => (),
+                    }
+                }
+
+                if remaining < frame_length {
+                    break;
+                }
+                remaining -= frame_length;
+                chip.end_frame();
+                frame += 1;
+            }
+        },
+    }
+
+    Ok(())
+}
+
+// Writes the screen out in the chosen format
+pub fn dump(state: &State, format: DumpFormat) -> String {
+
+    let (width, height) = if state.hires {
+        (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT)
+    } else {
+        (SCREEN_WIDTH, SCREEN_HEIGHT)
+    };
+    let rows = state.video_buffer.iter().take(height).map(|row| &row[..width]);
+
+    let mut output = String::new();
+
+    match format {
+        DumpFormat::Ascii => {
+            // one character for each combination of XO-CHIP planes
+            let characters = ['.', '#', '+', '@'];
+            for row in rows {
+                output.extend(row.iter().map(|&pixel| characters[pixel as usize & 0b11]));
+                output.push('\n');
+            }
+        },
+        DumpFormat::Pbm => {
+            let _ = writeln!(output, "P1\n{} {}", width, height);
+            for row in rows {
+                let bits: Vec<&str> = row.iter().map(|&pixel| if pixel == 0 { "0" } else { "1" }).collect();
+                let _ = writeln!(output, "{}", bits.join(" "));
+            }
+        },
+        DumpFormat::Hash => {
+            // https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function
+            let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+            for &byte in [width as u8, height as u8].iter().chain(rows.flatten()) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+            let _ = writeln!(output, "{:016x}", hash);
+        },
+    }
+
+    output
+}
+
+#[cfg(test)]
+#[path = "./headless_test.rs"]
+mod headless_test;
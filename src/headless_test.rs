@@ -0,0 +1,56 @@
+use super::*;
+use crate::quirks::Quirks;
+
+#[test]
+fn test_key_script() {
+    let script = KeyScript::parse("# comment\n10 5 a\n\n30   # let go\n20 f\n").unwrap();
+    assert_eq!(script.keys_at(0), [false; 16]);
+    assert!(script.keys_at(10)[0x5] && script.keys_at(19)[0xa]);
+    assert!(script.keys_at(25)[0xf] && !script.keys_at(25)[0x5]);
+    assert_eq!(script.keys_at(1000), [false; 16]);
+}
+
+#[test]
+fn test_key_script_errors() {
+    assert_eq!(KeyScript::parse("ten 5"), Err(String::from("line 1: invalid frame 'ten'")));
+    assert_eq!(KeyScript::parse("0\n10 g"), Err(String::from("line 2: invalid key 'g'")));
+}
+
+#[test]
+fn test_run_instructions() {
+    let mut chip = CPU::new(Quirks::default());
+    // count up in V0 and store it at 0x300, four instructions a loop
+    let program = [0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00];
+    chip.memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+
+    // stops part way through the seventh loop, after adding but before storing
+    run(&mut chip, RunLength::Instructions(25), 600, &KeyScript::default()).unwrap();
+    assert_eq!(chip.memory[0x300], 6);
+    run(&mut chip, RunLength::Instructions(2), 600, &KeyScript::default()).unwrap();
+    assert_eq!(chip.memory[0x300], 7);
+}
+
+#[test]
+fn test_run_stops_on_fault() {
+    let mut chip = CPU::new(Quirks::default());
+    let result = run(&mut chip, RunLength::Frames(10), 700, &KeyScript::default());
+    assert_eq!(result, Err(CpuFault::InvalidOpcode { pc: 0x200, opcode: 0x0000 }));
+}
+
+#[test]
+fn test_dump_formats() {
+    let mut chip = CPU::new(Quirks::default());
+    chip.video_buffer[0][1] = 1;
+    let state = chip.state();
+
+    let ascii = dump(&state, DumpFormat::Ascii);
+    assert_eq!(ascii.lines().count(), SCREEN_HEIGHT);
+    assert!(ascii.starts_with(".#.."));
+
+    let pbm = dump(&state, DumpFormat::Pbm);
+    assert!(pbm.starts_with("P1\n64 32\n0 1 0"));
+
+    let hash = dump(&state, DumpFormat::Hash);
+    assert_eq!(hash.trim().len(), 16);
+    assert_ne!(hash, dump(&CPU::new(Quirks::default()).state(), DumpFormat::Hash));
+}
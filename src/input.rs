@@ -1,59 +1,251 @@
 use sdl2;
+use sdl2::controller::{Axis, Button, GameController};
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+use sdl2::GameControllerSubsystem;
+
+use crate::cpu::KeyEvent;
+use crate::keymap::{KeyKind, Keymap};
+
+// Keys for the emulator itself rather than the chip-8 keypad
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hotkey {
+    /// F1-F4 save to slots 1-4
+    SaveState(u8),
+    /// Shift+F1-F4 load from slots 1-4
+    LoadState(u8),
+    /// F5 switches to the next palette
+    NextPalette,
+    /// Alt+Enter switches between a window and fullscreen
+    ToggleFullscreen,
+    /// F8 pauses and unpauses
+    TogglePause,
+    /// F6 runs a single frame while paused
+    AdvanceFrame,
+}
+
+// A keymap binding on a game controller
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PadInput {
+    Button(Button),
+    /// a stick or trigger pushed past the deadzone, towards the positive end
+    /// of the axis or the negative one
+    Axis(Axis, bool),
+}
+
+// Everything read from the keyboard and controllers during one frame
+pub struct InputState {
+    /// chip-8 keys held down
+    pub keypad: [bool; 16],
+    /// chip-8 keys pressed and released since the last poll, in order, so
+    /// taps shorter than a frame aren't lost
+    pub key_events: Vec<KeyEvent>,
+    /// hotkeys pressed since the last poll, in order
+    pub hotkeys: Vec<Hotkey>,
+    /// F7 is held down to run faster than real time
+    pub fast_forward: bool,
+}
 
 pub struct Input {
     events: sdl2::EventPump,
+    /// keymap bindings resolved to the keys' positions, keycodes are looked up
+    /// in the keyboard layout when the input is created
+    bindings: Vec<(Scancode, u8)>,
+    pad_bindings: Vec<(PadInput, u8)>,
+    /// how far an axis has to move before it counts, in SDL's -32768 to 32767
+    deadzone: i16,
+    /// None if SDL couldn't start its controller support, the keyboard still works
+    controller_subsystem: Option<GameControllerSubsystem>,
+    /// controllers plugged in now, opened as they're added and dropped as
+    /// they're removed
+    controllers: Vec<GameController>,
 }
 
 impl Input {
-    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
-        Input { events: sdl_context.event_pump().unwrap() }
+    pub fn new(sdl_context: &sdl2::Sdl, keymap: &Keymap) -> Result<Input, String> {
+
+        let mut bindings = Vec::new();
+        let mut pad_bindings = Vec::new();
+        for binding in keymap.bindings.iter() {
+            let scancode = match binding.kind {
+                KeyKind::Scancode => Scancode::from_name(&binding.name),
+                KeyKind::Keycode => Keycode::from_name(&binding.name).and_then(Scancode::from_keycode),
+                KeyKind::Pad => match Input::pad_input(&binding.name) {
+                    Some(input) => {
+                        pad_bindings.push((input, binding.key));
+                        continue;
+                    },
+                    None => return Err(format!("unknown controller input '{}' bound to chip-8 key {:X}",
+                                               binding.name, binding.key)),
+                },
+            };
+            match scancode {
+                Some(scancode) => bindings.push((scancode, binding.key)),
+                None => return Err(format!("unknown key '{}' bound to chip-8 key {:X}", binding.name, binding.key)),
+            }
+        }
+
+        let controller_subsystem = match sdl_context.game_controller() {
+            Ok(subsystem) => Some(subsystem),
+            Err(error) => {
+                eprintln!("game controllers unavailable: {}", error);
+                None
+            },
+        };
+
+        // controllers already plugged in are reported as added on the first poll
+        Ok(Input {
+            events: sdl_context.event_pump()?,
+            bindings: bindings,
+            pad_bindings: pad_bindings,
+            deadzone: (keymap.deadzone * i16::MAX as f32) as i16,
+            controller_subsystem: controller_subsystem,
+            controllers: Vec::new(),
+        })
     }
 
+    // "a", "dpup", ... are buttons, "leftx-" and "lefty+" are a stick pushed one
+    // way, and a bare axis name is a trigger
+    fn pad_input(name: &str) -> Option<PadInput> {
+        let name = name.to_ascii_lowercase();
+        if let Some(axis) = name.strip_suffix('+') {
+            return Axis::from_string(axis).map(|axis| PadInput::Axis(axis, true));
+        }
+        if let Some(axis) = name.strip_suffix('-') {
+            return Axis::from_string(axis).map(|axis| PadInput::Axis(axis, false));
+        }
+        Button::from_string(&name).map(PadInput::Button)
+            .or_else(|| Axis::from_string(&name).map(|axis| PadInput::Axis(axis, true)))
+    }
 
-    pub fn poll(&mut self) -> Result<[bool; 16], ()> {
+    fn pad_pressed(&self, controller: &GameController, input: PadInput) -> bool {
+        match input {
+            PadInput::Button(button) => controller.button(button),
+            PadInput::Axis(axis, true) => controller.axis(axis) > self.deadzone,
+            PadInput::Axis(axis, false) => controller.axis(axis) < -self.deadzone,
+        }
+    }
+
+    // The chip-8 key events for a host key or controller button going down or up.
+    // Sticks only move the held state, they don't have events of their own.
+    fn key_events(bindings: &[(Scancode, u8)], pad_bindings: &[(PadInput, u8)], events: &mut Vec<KeyEvent>,
+                  scancode: Option<Scancode>, button: Option<Button>, event: fn(u8) -> KeyEvent) {
+        if let Some(scancode) = scancode {
+            events.extend(bindings.iter()
+                .filter(|&&(bound, _)| bound == scancode)
+                .map(|&(_, key)| event(key)));
+        }
+        if let Some(button) = button {
+            events.extend(pad_bindings.iter()
+                .filter(|&&(input, _)| input == PadInput::Button(button))
+                .map(|&(_, key)| event(key)));
+        }
+    }
+
+    fn add_controller(&mut self, joystick_index: u32) {
+        let subsystem = match self.controller_subsystem {
+            Some(ref subsystem) if subsystem.is_game_controller(joystick_index) => subsystem,
+            _ => return,
+        };
+        match subsystem.open(joystick_index) {
+            Ok(controller) => {
+                let id = controller.instance_id();
+                if !self.controllers.iter().any(|open| open.instance_id() == id) {
+                    self.controllers.push(controller);
+                }
+            },
+            Err(error) => eprintln!("couldn't open game controller {}: {}", joystick_index, error),
+        }
+    }
+
+
+    pub fn poll(&mut self) -> Result<InputState, ()> {
+
+        let mut hotkeys = Vec::new();
+        let mut added = Vec::new();
+        let mut key_events = Vec::new();
 
         for event in self.events.poll_iter() {
-            if let Event::Quit { .. } = event {
-                return Err(());
-            };
+            match event {
+                Event::Quit { .. } => return Err(()),
+                Event::KeyDown { keycode, scancode, keymod, repeat: false, .. } => {
+                    if let Some(hotkey) = keycode.and_then(|keycode| Input::hotkey(keycode, keymod)) {
+                        hotkeys.push(hotkey);
+                    }
+                    Input::key_events(&self.bindings, &self.pad_bindings, &mut key_events, scancode, None, KeyEvent::Down);
+                },
+                Event::KeyUp { scancode, .. } => {
+                    Input::key_events(&self.bindings, &self.pad_bindings, &mut key_events, scancode, None, KeyEvent::Up);
+                },
+                Event::ControllerButtonDown { button, .. } => {
+                    Input::key_events(&self.bindings, &self.pad_bindings, &mut key_events, None, Some(button), KeyEvent::Down);
+                },
+                Event::ControllerButtonUp { button, .. } => {
+                    Input::key_events(&self.bindings, &self.pad_bindings, &mut key_events, None, Some(button), KeyEvent::Up);
+                },
+                Event::ControllerDeviceAdded { which, .. } => added.push(which),
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.controllers.retain(|controller| controller.instance_id() != which);
+                },
+                _ => (),
+            }
+        }
+        for joystick_index in added {
+            self.add_controller(joystick_index);
         }
 
-        let keys: Vec<Keycode> = self.events
-            .keyboard_state()
-            .pressed_scancodes()
-            .filter_map(Keycode::from_scancode)
-            .collect();
+        let keyboard = self.events.keyboard_state();
 
         let mut chip8_keys = [false; 16];
+        for &(scancode, key) in self.bindings.iter() {
+            if keyboard.is_scancode_pressed(scancode) {
+                chip8_keys[key as usize] = true;
+            }
+        }
+        for controller in self.controllers.iter() {
+            for &(input, key) in self.pad_bindings.iter() {
+                if self.pad_pressed(controller, input) {
+                    chip8_keys[key as usize] = true;
+                }
+            }
+        }
 
-        for key in keys {
-            let index = match key {
-                Keycode::Num1 => Some(0x1),
-                Keycode::Num2 => Some(0x2),
-                Keycode::Num3 => Some(0x3),
-                Keycode::Num4 => Some(0xc),
-                Keycode::Q => Some(0x4),
-                Keycode::W => Some(0x5),
-                Keycode::E => Some(0x6),
-                Keycode::R => Some(0xd),
-                Keycode::A => Some(0x7),
-                Keycode::S => Some(0x8),
-                Keycode::D => Some(0x9),
-                Keycode::F => Some(0xe),
-                Keycode::Z => Some(0xa),
-                Keycode::X => Some(0x0),
-                Keycode::C => Some(0xb),
-                Keycode::V => Some(0xf),
-                _ => None,
-            };
+        // a key with several bindings stays down until the last of them is let go
+        key_events.retain(|&event| match event {
+            KeyEvent::Up(key) => !chip8_keys[key as usize],
+            KeyEvent::Down(_) => true,
+        });
 
-            if let Some(i) = index {
-                chip8_keys[i] = true;
-            }
+        let fast_forward = keyboard.is_scancode_pressed(Scancode::F7);
+
+        Ok(InputState {
+            keypad: chip8_keys,
+            key_events: key_events,
+            hotkeys: hotkeys,
+            fast_forward: fast_forward,
+        })
+    }
+
+    fn hotkey(keycode: Keycode, keymod: Mod) -> Option<Hotkey> {
+        if keycode == Keycode::Return && keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) {
+            return Some(Hotkey::ToggleFullscreen);
         }
 
-        Ok(chip8_keys)
+        let slot = match keycode {
+            Keycode::F1 => 1,
+            Keycode::F2 => 2,
+            Keycode::F3 => 3,
+            Keycode::F4 => 4,
+            Keycode::F5 => return Some(Hotkey::NextPalette),
+            Keycode::F6 => return Some(Hotkey::AdvanceFrame),
+            Keycode::F8 => return Some(Hotkey::TogglePause),
+            _ => return None,
+        };
+
+        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+            Some(Hotkey::LoadState(slot))
+        } else {
+            Some(Hotkey::SaveState(slot))
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,311 @@
+use std::fmt;
+
+// Every opcode the cpu understands, decoded into its operands. Register operands
+// are register numbers (0x0-0xF), not values. Mnemonics follow Cowgod's technical
+// reference, with the SUPER-CHIP and XO-CHIP additions named the way most
+// assemblers name them.
+// http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#3.1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    /// 00CN: SCD n
+    ScrollDown(u8),
+    /// 00DN: SCU n (XO-CHIP)
+    ScrollUp(u8),
+    /// 00E0: CLS
+    Clear,
+    /// 00EE: RET
+    Return,
+    /// 00FB: SCR
+    ScrollRight,
+    /// 00FC: SCL
+    ScrollLeft,
+    /// 00FD: EXIT
+    Exit,
+    /// 00FE: LOW
+    LowRes,
+    /// 00FF: HIGH
+    HighRes,
+    /// 1NNN: JP addr
+    Jump(u16),
+    /// 2NNN: CALL addr
+    Call(u16),
+    /// 3XNN: SE Vx, byte
+    SkipEqualByte(u8, u8),
+    /// 4XNN: SNE Vx, byte
+    SkipNotEqualByte(u8, u8),
+    /// 5XY0: SE Vx, Vy
+    SkipEqual(u8, u8),
+    /// 5XY2: SAVE Vx, Vy (XO-CHIP)
+    SaveRange(u8, u8),
+    /// 5XY3: LOAD Vx, Vy (XO-CHIP)
+    LoadRange(u8, u8),
+    /// 6XNN: LD Vx, byte
+    LoadByte(u8, u8),
+    /// 7XNN: ADD Vx, byte
+    AddByte(u8, u8),
+    /// 8XY0: LD Vx, Vy
+    Load(u8, u8),
+    /// 8XY1: OR Vx, Vy
+    Or(u8, u8),
+    /// 8XY2: AND Vx, Vy
+    And(u8, u8),
+    /// 8XY3: XOR Vx, Vy
+    Xor(u8, u8),
+    /// 8XY4: ADD Vx, Vy
+    Add(u8, u8),
+    /// 8XY5: SUB Vx, Vy
+    Subtract(u8, u8),
+    /// 8XY6: SHR Vx, Vy
+    ShiftRight(u8, u8),
+    /// 8XY7: SUBN Vx, Vy
+    SubtractNegated(u8, u8),
+    /// 8XYE: SHL Vx, Vy
+    ShiftLeft(u8, u8),
+    /// 9XY0: SNE Vx, Vy
+    SkipNotEqual(u8, u8),
+    /// ANNN: LD I, addr
+    LoadIndex(u16),
+    /// BNNN: JP V0, addr
+    JumpOffset(u16),
+    /// CXNN: RND Vx, byte
+    Random(u8, u8),
+    /// DXYN: DRW Vx, Vy, n
+    Draw(u8, u8, u8),
+    /// EX9E: SKP Vx
+    SkipKey(u8),
+    /// EXA1: SKNP Vx
+    SkipNotKey(u8),
+    /// F000 NNNN: LD I, LONG (XO-CHIP), the address is the word that follows
+    LoadLongIndex,
+    /// FN01: PLANE n (XO-CHIP)
+    SelectPlanes(u8),
+    /// F002: AUDIO (XO-CHIP)
+    LoadAudio,
+    /// FX07: LD Vx, DT
+    LoadDelay(u8),
+    /// FX0A: LD Vx, K
+    WaitKey(u8),
+    /// FX15: LD DT, Vx
+    SetDelay(u8),
+    /// FX18: LD ST, Vx
+    SetSound(u8),
+    /// FX1E: ADD I, Vx
+    AddIndex(u8),
+    /// FX29: LD F, Vx
+    LoadFont(u8),
+    /// FX30: LD HF, Vx
+    LoadLargeFont(u8),
+    /// FX33: LD B, Vx
+    StoreBcd(u8),
+    /// FX3A: LD PITCH, Vx (XO-CHIP)
+    SetPitch(u8),
+    /// FX55: LD [I], Vx
+    StoreRegisters(u8),
+    /// FX65: LD Vx, [I]
+    LoadRegisters(u8),
+    /// FX75: LD R, Vx
+    StoreFlags(u8),
+    /// FX85: LD Vx, R
+    LoadFlags(u8),
+}
+
+impl Instruction {
+
+    /// Decodes a single opcode, None if no interpreter defines it
+    pub fn decode(opcode: u16) -> Option<Instruction> {
+        // mask out 4 nibbles
+        let nibbles = (
+            ((opcode & 0xF000) >> 12) as u8,
+            ((opcode & 0x0F00) >> 8) as u8,
+            ((opcode & 0x00F0) >> 4) as u8,
+            (opcode & 0x000F) as u8,
+        );
+
+        // mask out last three nibbles for some operations
+        let nnn = opcode & 0x0FFF;
+        // mask out 2 nibbles
+        let kk = (opcode & 0x00FF) as u8;
+        // get individual reference to last three nibbles
+        let x = nibbles.1;
+        let y = nibbles.2;
+        let n = nibbles.3;
+
+        let instruction = match nibbles {
+            (0x00, 0x00, 0x0c, _) => Instruction::ScrollDown(n),
+            (0x00, 0x00, 0x0d, _) => Instruction::ScrollUp(n),
+            (0x00, 0x00, 0x0e, 0x00) => Instruction::Clear,
+            (0x00, 0x00, 0x0e, 0x0e) => Instruction::Return,
+            (0x00, 0x00, 0x0f, 0x0b) => Instruction::ScrollRight,
+            (0x00, 0x00, 0x0f, 0x0c) => Instruction::ScrollLeft,
+            (0x00, 0x00, 0x0f, 0x0d) => Instruction::Exit,
+            (0x00, 0x00, 0x0f, 0x0e) => Instruction::LowRes,
+            (0x00, 0x00, 0x0f, 0x0f) => Instruction::HighRes,
+            (0x01, _, _, _) => Instruction::Jump(nnn),
+            (0x02, _, _, _) => Instruction::Call(nnn),
+            (0x03, _, _, _) => Instruction::SkipEqualByte(x, kk),
+            (0x04, _, _, _) => Instruction::SkipNotEqualByte(x, kk),
+            (0x05, _, _, 0x00) => Instruction::SkipEqual(x, y),
+            (0x05, _, _, 0x02) => Instruction::SaveRange(x, y),
+            (0x05, _, _, 0x03) => Instruction::LoadRange(x, y),
+            (0x06, _, _, _) => Instruction::LoadByte(x, kk),
+            (0x07, _, _, _) => Instruction::AddByte(x, kk),
+            (0x08, _, _, 0x00) => Instruction::Load(x, y),
+            (0x08, _, _, 0x01) => Instruction::Or(x, y),
+            (0x08, _, _, 0x02) => Instruction::And(x, y),
+            (0x08, _, _, 0x03) => Instruction::Xor(x, y),
+            (0x08, _, _, 0x04) => Instruction::Add(x, y),
+            (0x08, _, _, 0x05) => Instruction::Subtract(x, y),
+            (0x08, _, _, 0x06) => Instruction::ShiftRight(x, y),
+            (0x08, _, _, 0x07) => Instruction::SubtractNegated(x, y),
+            (0x08, _, _, 0x0e) => Instruction::ShiftLeft(x, y),
+            (0x09, _, _, 0x00) => Instruction::SkipNotEqual(x, y),
+            (0x0a, _, _, _) => Instruction::LoadIndex(nnn),
+            (0x0b, _, _, _) => Instruction::JumpOffset(nnn),
+            (0x0c, _, _, _) => Instruction::Random(x, kk),
+            (0x0d, _, _, _) => Instruction::Draw(x, y, n),
+            (0x0e, _, 0x09, 0x0e) => Instruction::SkipKey(x),
+            (0x0e, _, 0x0a, 0x01) => Instruction::SkipNotKey(x),
+            (0x0f, 0x00, 0x00, 0x00) => Instruction::LoadLongIndex,
+            (0x0f, _, 0x00, 0x01) => Instruction::SelectPlanes(x),
+            (0x0f, 0x00, 0x00, 0x02) => Instruction::LoadAudio,
+            (0x0f, _, 0x00, 0x07) => Instruction::LoadDelay(x),
+            (0x0f, _, 0x00, 0x0a) => Instruction::WaitKey(x),
+            (0x0f, _, 0x01, 0x05) => Instruction::SetDelay(x),
+            (0x0f, _, 0x01, 0x08) => Instruction::SetSound(x),
+            (0x0f, _, 0x01, 0x0e) => Instruction::AddIndex(x),
+            (0x0f, _, 0x02, 0x09) => Instruction::LoadFont(x),
+            (0x0f, _, 0x03, 0x00) => Instruction::LoadLargeFont(x),
+            (0x0f, _, 0x03, 0x03) => Instruction::StoreBcd(x),
+            (0x0f, _, 0x03, 0x0a) => Instruction::SetPitch(x),
+            (0x0f, _, 0x05, 0x05) => Instruction::StoreRegisters(x),
+            (0x0f, _, 0x06, 0x05) => Instruction::LoadRegisters(x),
+            (0x0f, _, 0x07, 0x05) => Instruction::StoreFlags(x),
+            (0x0f, _, 0x08, 0x05) => Instruction::LoadFlags(x),
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+
+    /// Turns the instruction back into its opcode
+    pub fn encode(&self) -> u16 {
+        // put operands back into their nibbles
+        let xy = |prefix: u16, x: u8, y: u8, suffix: u16| prefix << 12 | (x as u16) << 8 | (y as u16) << 4 | suffix;
+        let xkk = |prefix: u16, x: u8, kk: u8| prefix << 12 | (x as u16) << 8 | kk as u16;
+        let fx = |x: u8, suffix: u16| 0xF000 | (x as u16) << 8 | suffix;
+
+        match *self {
+            Instruction::ScrollDown(n) => 0x00C0 | n as u16,
+            Instruction::ScrollUp(n) => 0x00D0 | n as u16,
+            Instruction::Clear => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Jump(nnn) => 0x1000 | nnn,
+            Instruction::Call(nnn) => 0x2000 | nnn,
+            Instruction::SkipEqualByte(x, kk) => xkk(0x3, x, kk),
+            Instruction::SkipNotEqualByte(x, kk) => xkk(0x4, x, kk),
+            Instruction::SkipEqual(x, y) => xy(0x5, x, y, 0x0),
+            Instruction::SaveRange(x, y) => xy(0x5, x, y, 0x2),
+            Instruction::LoadRange(x, y) => xy(0x5, x, y, 0x3),
+            Instruction::LoadByte(x, kk) => xkk(0x6, x, kk),
+            Instruction::AddByte(x, kk) => xkk(0x7, x, kk),
+            Instruction::Load(x, y) => xy(0x8, x, y, 0x0),
+            Instruction::Or(x, y) => xy(0x8, x, y, 0x1),
+            Instruction::And(x, y) => xy(0x8, x, y, 0x2),
+            Instruction::Xor(x, y) => xy(0x8, x, y, 0x3),
+            Instruction::Add(x, y) => xy(0x8, x, y, 0x4),
+            Instruction::Subtract(x, y) => xy(0x8, x, y, 0x5),
+            Instruction::ShiftRight(x, y) => xy(0x8, x, y, 0x6),
+            Instruction::SubtractNegated(x, y) => xy(0x8, x, y, 0x7),
+            Instruction::ShiftLeft(x, y) => xy(0x8, x, y, 0xE),
+            Instruction::SkipNotEqual(x, y) => xy(0x9, x, y, 0x0),
+            Instruction::LoadIndex(nnn) => 0xA000 | nnn,
+            Instruction::JumpOffset(nnn) => 0xB000 | nnn,
+            Instruction::Random(x, kk) => xkk(0xC, x, kk),
+            Instruction::Draw(x, y, n) => xy(0xD, x, y, n as u16),
+            Instruction::SkipKey(x) => xkk(0xE, x, 0x9E),
+            Instruction::SkipNotKey(x) => xkk(0xE, x, 0xA1),
+            Instruction::LoadLongIndex => 0xF000,
+            Instruction::SelectPlanes(n) => fx(n, 0x01),
+            Instruction::LoadAudio => 0xF002,
+            Instruction::LoadDelay(x) => fx(x, 0x07),
+            Instruction::WaitKey(x) => fx(x, 0x0A),
+            Instruction::SetDelay(x) => fx(x, 0x15),
+            Instruction::SetSound(x) => fx(x, 0x18),
+            Instruction::AddIndex(x) => fx(x, 0x1E),
+            Instruction::LoadFont(x) => fx(x, 0x29),
+            Instruction::LoadLargeFont(x) => fx(x, 0x30),
+            Instruction::StoreBcd(x) => fx(x, 0x33),
+            Instruction::SetPitch(x) => fx(x, 0x3A),
+            Instruction::StoreRegisters(x) => fx(x, 0x55),
+            Instruction::LoadRegisters(x) => fx(x, 0x65),
+            Instruction::StoreFlags(x) => fx(x, 0x75),
+            Instruction::LoadFlags(x) => fx(x, 0x85),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::Clear => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Jump(nnn) => write!(f, "JP {:#05x}", nnn),
+            Instruction::Call(nnn) => write!(f, "CALL {:#05x}", nnn),
+            Instruction::SkipEqualByte(x, kk) => write!(f, "SE V{:X}, {:#04x}", x, kk),
+            Instruction::SkipNotEqualByte(x, kk) => write!(f, "SNE V{:X}, {:#04x}", x, kk),
+            Instruction::SkipEqual(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SaveRange(x, y) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRange(x, y) => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Instruction::LoadByte(x, kk) => write!(f, "LD V{:X}, {:#04x}", x, kk),
+            Instruction::AddByte(x, kk) => write!(f, "ADD V{:X}, {:#04x}", x, kk),
+            Instruction::Load(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Subtract(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubtractNegated(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNotEqual(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadIndex(nnn) => write!(f, "LD I, {:#05x}", nnn),
+            Instruction::JumpOffset(nnn) => write!(f, "JP V0, {:#05x}", nnn),
+            Instruction::Random(x, kk) => write!(f, "RND V{:X}, {:#04x}", x, kk),
+            Instruction::Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey(x) => write!(f, "SKP V{:X}", x),
+            Instruction::SkipNotKey(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadLongIndex => write!(f, "LD I, LONG"),
+            Instruction::SelectPlanes(n) => write!(f, "PLANE {}", n),
+            Instruction::LoadAudio => write!(f, "AUDIO"),
+            Instruction::LoadDelay(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIndex(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadFont(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::LoadLargeFont(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::StoreBcd(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::SetPitch(x) => write!(f, "LD PITCH, V{:X}", x),
+            Instruction::StoreRegisters(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegisters(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::StoreFlags(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./instruction_test.rs"]
+mod instruction_test;
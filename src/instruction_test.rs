@@ -0,0 +1,81 @@
+use super::*;
+
+// One opcode for every instruction, with the mnemonic it should print as
+const EXAMPLES: [(u16, &str); 51] = [
+    (0x00C4, "SCD 4"),
+    (0x00D2, "SCU 2"),
+    (0x00E0, "CLS"),
+    (0x00EE, "RET"),
+    (0x00FB, "SCR"),
+    (0x00FC, "SCL"),
+    (0x00FD, "EXIT"),
+    (0x00FE, "LOW"),
+    (0x00FF, "HIGH"),
+    (0x1234, "JP 0x234"),
+    (0x2abc, "CALL 0xabc"),
+    (0x3312, "SE V3, 0x12"),
+    (0x4a0f, "SNE VA, 0x0f"),
+    (0x5120, "SE V1, V2"),
+    (0x5342, "SAVE V3, V4"),
+    (0x5f03, "LOAD VF, V0"),
+    (0x6312, "LD V3, 0x12"),
+    (0x7eff, "ADD VE, 0xff"),
+    (0x8120, "LD V1, V2"),
+    (0x8121, "OR V1, V2"),
+    (0x8122, "AND V1, V2"),
+    (0x8123, "XOR V1, V2"),
+    (0x8124, "ADD V1, V2"),
+    (0x8125, "SUB V1, V2"),
+    (0x8126, "SHR V1, V2"),
+    (0x8127, "SUBN V1, V2"),
+    (0x812e, "SHL V1, V2"),
+    (0x9ab0, "SNE VA, VB"),
+    (0xa050, "LD I, 0x050"),
+    (0xb300, "JP V0, 0x300"),
+    (0xc70f, "RND V7, 0x0f"),
+    (0xd015, "DRW V0, V1, 5"),
+    (0xe59e, "SKP V5"),
+    (0xe5a1, "SKNP V5"),
+    (0xf000, "LD I, LONG"),
+    (0xf301, "PLANE 3"),
+    (0xf002, "AUDIO"),
+    (0xf207, "LD V2, DT"),
+    (0xf20a, "LD V2, K"),
+    (0xf215, "LD DT, V2"),
+    (0xf218, "LD ST, V2"),
+    (0xf21e, "ADD I, V2"),
+    (0xf229, "LD F, V2"),
+    (0xf230, "LD HF, V2"),
+    (0xf233, "LD B, V2"),
+    (0xf23a, "LD PITCH, V2"),
+    (0xf255, "LD [I], V2"),
+    (0xf265, "LD V2, [I]"),
+    (0xf275, "LD R, V2"),
+    (0xf285, "LD V2, R"),
+    (0x00C0, "SCD 0"),
+];
+
+#[test]
+fn test_examples_round_trip() {
+    for &(opcode, mnemonic) in EXAMPLES.iter() {
+        let instruction = Instruction::decode(opcode).unwrap();
+        assert_eq!(instruction.encode(), opcode);
+        assert_eq!(instruction.to_string(), mnemonic);
+    }
+}
+
+#[test]
+fn test_every_opcode_round_trips() {
+    for opcode in 0..=0xFFFF {
+        if let Some(instruction) = Instruction::decode(opcode) {
+            assert_eq!(instruction.encode(), opcode, "{:04x} decoded as {}", opcode, instruction);
+        }
+    }
+}
+
+#[test]
+fn test_unknown_opcodes() {
+    for &opcode in [0x0000, 0x0123, 0x00E1, 0x5121, 0x800F, 0x9121, 0xE000, 0xF100, 0xF302, 0xFFFF].iter() {
+        assert_eq!(Instruction::decode(opcode), None);
+    }
+}
@@ -0,0 +1,250 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// Which host keys and game controller buttons press which chip-8 keys. Keys
+// are named the way SDL names them ("Q", "Keypad 8", "Up"), and are matched
+// either by keycode, the character the key types in the current keyboard
+// layout, or by scancode, the key's position on the keyboard whatever the
+// layout. Controller buttons use SDL's game controller names with a "pad:"
+// prefix ("pad:a", "pad:dpup", "pad:leftshoulder"), and analog sticks are an
+// axis and a direction ("pad:leftx-", "pad:lefty+"). A keymap file has one
+// binding per line:
+//
+//     # start from a preset instead of the current bindings
+//     preset = azerty
+//     # the arrow keys as well, by keycode
+//     2 = Up
+//     8 = Down
+//     # several keys at once, by scancode and on a controller
+//     4 = Left, scancode:A, pad:x
+//     # how far a stick has to move before it counts, 0.0 to 1.0
+//     deadzone = 0.4
+//
+// Blank lines and lines starting with '#' are ignored. Bindings are added to
+// the ones already there; 'clear = <chip-8 key>' removes a key's bindings
+// first. The window's hotkeys can't be bound, since pressing them would do
+// both.
+
+/// The chip-8 keypad as it's laid out on the COSMAC VIP
+pub const KEYPAD_LAYOUT: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC,
+    0x4, 0x5, 0x6, 0xD,
+    0x7, 0x8, 0x9, 0xE,
+    0xA, 0x0, 0xB, 0xF,
+];
+
+/// Built in keymaps, one host key per chip-8 key in KEYPAD_LAYOUT order
+pub const PRESETS: [(&str, [&str; 16]); 4] = [
+    ("qwerty", [
+        "1", "2", "3", "4",
+        "Q", "W", "E", "R",
+        "A", "S", "D", "F",
+        "Z", "X", "C", "V",
+    ]),
+    // the number row types symbols on AZERTY, so it's matched by position
+    ("azerty", [
+        "scancode:1", "scancode:2", "scancode:3", "scancode:4",
+        "A", "Z", "E", "R",
+        "Q", "S", "D", "F",
+        "W", "X", "C", "V",
+    ]),
+    ("dvorak", [
+        "1", "2", "3", "4",
+        "'", ",", ".", "P",
+        "A", "O", "E", "U",
+        ";", "Q", "J", "K",
+    ]),
+    ("numpad", [
+        "Keypad 7", "Keypad 8", "Keypad 9", "Keypad /",
+        "Keypad 4", "Keypad 5", "Keypad 6", "Keypad *",
+        "Keypad 1", "Keypad 2", "Keypad 3", "Keypad -",
+        "Keypad 0", "Keypad .", "Keypad Enter", "Keypad +",
+    ]),
+];
+
+/// Controller bindings every preset starts with: the d-pad and left stick on
+/// 2/4/6/8, the usual directions, and the buttons on the keys games most often
+/// use for actions
+pub const PAD_BINDINGS: [(&str, u8); 16] = [
+    ("dpup", 0x2), ("dpdown", 0x8), ("dpleft", 0x4), ("dpright", 0x6),
+    ("lefty-", 0x2), ("lefty+", 0x8), ("leftx-", 0x4), ("leftx+", 0x6),
+    ("a", 0x5), ("b", 0xA), ("x", 0x7), ("y", 0x9),
+    ("leftshoulder", 0x1), ("rightshoulder", 0x3), ("back", 0xB), ("start", 0xF),
+];
+
+/// Keys the window keeps for itself: save states, palettes, frame advance,
+/// fast forward and pause
+pub const HOTKEYS: [&str; 8] = ["F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8"];
+
+/// How far a stick has to move, as a fraction of its range, before it presses a key
+pub const DEFAULT_DEADZONE: f32 = 0.25;
+
+// How a host key is matched
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyKind {
+    /// by the character it types in the current keyboard layout
+    Keycode,
+    /// by its position on the keyboard
+    Scancode,
+    /// a game controller button, or a stick pushed one way
+    Pad,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binding {
+    pub kind: KeyKind,
+    /// SDL's name for the key
+    pub name: String,
+    /// chip-8 key it presses, 0x0-0xF
+    pub key: u8,
+}
+
+impl Binding {
+
+    /// Whether this is a keyboard key the window uses as a hotkey
+    pub fn is_hotkey(&self) -> bool {
+        self.kind != KeyKind::Pad && HOTKEYS.iter().any(|hotkey| hotkey.eq_ignore_ascii_case(&self.name))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Keymap {
+    pub bindings: Vec<Binding>,
+    /// how far a stick has to move, as a fraction of its range, before it presses a key
+    pub deadzone: f32,
+}
+
+impl Keymap {
+
+    /// Looks up a built in keymap by name
+    pub fn preset(name: &str) -> Option<Keymap> {
+        let name = name.to_ascii_lowercase();
+        PRESETS.iter()
+            .find(|&&(preset, _)| preset == name)
+            .map(|&(_, keys)| Keymap {
+                bindings: KEYPAD_LAYOUT.iter()
+                    .zip(keys.iter())
+                    .map(|(&key, &host_key)| Keymap::binding(host_key, key))
+                    .chain(PAD_BINDINGS.iter().map(|&(button, key)| Binding {
+                        kind: KeyKind::Pad,
+                        name: String::from(button),
+                        key: key,
+                    }))
+                    .collect(),
+                deadzone: DEFAULT_DEADZONE,
+            })
+    }
+
+    // Applies a keymap file on top of the current bindings, see the top of this
+    // file for the format. Nothing changes if the file has an error.
+    pub fn apply(&mut self, text: &str) -> Result<(), String> {
+
+        let mut keymap = self.clone();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let value = parts.next()
+                .map(str::trim)
+                .ok_or_else(|| error(String::from("expected '<key> = <host keys>'")))?;
+
+            match name {
+                "preset" => {
+                    keymap = Keymap::preset(value)
+                        .ok_or_else(|| error(format!("unknown keymap preset '{}'", value)))?;
+                },
+                "deadzone" => {
+                    keymap.deadzone = match value.parse() {
+                        Ok(deadzone) if (0.0..1.0).contains(&deadzone) => deadzone,
+                        _ => return Err(error(format!("invalid deadzone '{}'", value))),
+                    };
+                },
+                "clear" => {
+                    let key = Keymap::chip8_key(value).map_err(error)?;
+                    keymap.bindings.retain(|binding| binding.key != key);
+                },
+                _ => {
+                    let key = Keymap::chip8_key(name).map_err(error)?;
+                    for host_key in value.split(',').map(str::trim) {
+                        if host_key.is_empty() {
+                            return Err(error(format!("missing host key for chip-8 key {:X}", key)));
+                        }
+                        let binding = Keymap::binding(host_key, key);
+                        if binding.is_hotkey() {
+                            return Err(error(format!("{} is a hotkey and can't be bound", binding.name)));
+                        }
+                        keymap.bindings.push(binding);
+                    }
+                },
+            }
+        }
+
+        *self = keymap;
+        Ok(())
+    }
+
+    /// Bindings for one chip-8 key
+    pub fn keys_for(&self, key: u8) -> impl Iterator<Item = &Binding> {
+        self.bindings.iter().filter(move |binding| binding.key == key)
+    }
+
+    // "scancode:Name" is matched by position, "pad:name" is on a controller,
+    // anything else is matched by keycode
+    fn binding(host_key: &str, key: u8) -> Binding {
+        if let Some(name) = host_key.strip_prefix("scancode:") {
+            Binding { kind: KeyKind::Scancode, name: String::from(name.trim()), key: key }
+        } else if let Some(name) = host_key.strip_prefix("pad:") {
+            Binding { kind: KeyKind::Pad, name: String::from(name.trim()), key: key }
+        } else {
+            Binding { kind: KeyKind::Keycode, name: String::from(host_key), key: key }
+        }
+    }
+
+    fn chip8_key(text: &str) -> Result<u8, String> {
+        match u8::from_str_radix(text, 16) {
+            Ok(key) if key < 16 => Ok(key),
+            _ => Err(format!("invalid chip-8 key '{}'", text)),
+        }
+    }
+}
+
+// Per-rom overrides live next to the rom, game.ch8 reads game.keymap
+pub fn rom_keymap_path(rom: &Path) -> PathBuf {
+    rom.with_extension("keymap")
+}
+
+// The keypad grid with the host keys bound to each chip-8 key, for --print-keymap
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in KEYPAD_LAYOUT.chunks(4) {
+            let cells: Vec<String> = row.iter()
+                .map(|&key| {
+                    let names: Vec<String> = self.keys_for(key)
+                        .map(|binding| match binding.kind {
+                            KeyKind::Keycode => binding.name.clone(),
+                            KeyKind::Scancode => format!("scancode:{}", binding.name),
+                            KeyKind::Pad => format!("pad:{}", binding.name),
+                        })
+                        .collect();
+                    let names = if names.is_empty() { String::from("-") } else { names.join(", ") };
+                    format!("{:X}: {:<18}", key, names)
+                })
+                .collect();
+            writeln!(f, "{}", cells.join(" ").trim_end())?;
+        }
+        if self.bindings.iter().any(|binding| binding.kind == KeyKind::Pad) {
+            writeln!(f, "stick deadzone: {}", self.deadzone)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[path = "./keymap_test.rs"]
+mod keymap_test;
@@ -0,0 +1,109 @@
+use super::*;
+
+fn names(keymap: &Keymap, key: u8) -> Vec<String> {
+    keymap.keys_for(key).map(|binding| binding.name.clone()).collect()
+}
+
+#[test]
+fn test_presets() {
+    let qwerty = Keymap::preset("qwerty").unwrap();
+    assert_eq!(qwerty.bindings.len(), 16 + PAD_BINDINGS.len());
+    assert_eq!(names(&qwerty, 0x1), ["1", "leftshoulder"]);
+    assert_eq!(names(&qwerty, 0x0), ["X"]);
+    assert_eq!(names(&qwerty, 0xF), ["V", "start"]);
+
+    let azerty = Keymap::preset("AZERTY").unwrap();
+    assert_eq!(azerty.keys_for(0xC).next().unwrap().kind, KeyKind::Scancode);
+    assert_eq!(names(&azerty, 0xD), ["R"]);
+
+    let numpad = Keymap::preset("numpad").unwrap();
+    assert_eq!(names(&numpad, 0x2), ["Keypad 8", "dpup", "lefty-"]);
+    assert_eq!(names(&numpad, 0xE), ["Keypad -"]);
+
+    assert!(Keymap::preset("colemak").is_none());
+}
+
+#[test]
+fn test_every_preset_binds_every_key_once() {
+    for &(name, _) in PRESETS.iter() {
+        let keymap = Keymap::preset(name).unwrap();
+        for key in 0..16 {
+            let keys = keymap.keys_for(key).filter(|binding| binding.kind != KeyKind::Pad);
+            assert_eq!(keys.count(), 1, "{} key {:X}", name, key);
+        }
+    }
+}
+
+#[test]
+fn test_apply_adds_bindings() {
+    let mut keymap = Keymap::preset("qwerty").unwrap();
+    keymap.apply("# arrows too\n2 = Up\n8 = Down\n4 = Left, scancode:Keypad 4\n\n").unwrap();
+
+    assert_eq!(names(&keymap, 0x2), ["2", "dpup", "lefty-", "Up"]);
+    assert_eq!(names(&keymap, 0x8), ["S", "dpdown", "lefty+", "Down"]);
+    let left: Vec<&Binding> = keymap.keys_for(0x4).collect();
+    assert_eq!(left.len(), 5);
+    assert_eq!(*left[4], Binding { kind: KeyKind::Scancode, name: String::from("Keypad 4"), key: 0x4 });
+}
+
+#[test]
+fn test_apply_preset_and_clear() {
+    let mut keymap = Keymap::preset("qwerty").unwrap();
+    keymap.apply("preset = dvorak\nclear = 5\n5 = Space").unwrap();
+
+    assert_eq!(names(&keymap, 0x4), ["'", "dpleft", "leftx-"]);
+    assert_eq!(names(&keymap, 0x5), ["Space"]);
+}
+
+#[test]
+fn test_apply_errors_leave_keymap_alone() {
+    let mut keymap = Keymap::preset("qwerty").unwrap();
+    let original = keymap.clone();
+
+    assert_eq!(keymap.apply("2 = Up\nG = H"), Err(String::from("line 2: invalid chip-8 key 'G'")));
+    assert_eq!(keymap.apply("preset = colemak"), Err(String::from("line 1: unknown keymap preset 'colemak'")));
+    assert_eq!(keymap.apply("Up"), Err(String::from("line 1: expected '<key> = <host keys>'")));
+    assert_eq!(keymap.apply("3 = A,"), Err(String::from("line 1: missing host key for chip-8 key 3")));
+    assert_eq!(keymap.apply("deadzone = 1.5"), Err(String::from("line 1: invalid deadzone '1.5'")));
+    assert_eq!(keymap.apply("1 = Q\n5 = f7"), Err(String::from("line 2: f7 is a hotkey and can't be bound")));
+    assert_eq!(keymap.apply("5 = scancode:F8"), Err(String::from("line 1: F8 is a hotkey and can't be bound")));
+    assert_eq!(keymap, original);
+}
+
+#[test]
+fn test_presets_leave_hotkeys_free() {
+    for &(name, _) in PRESETS.iter() {
+        let keymap = Keymap::preset(name).unwrap();
+        assert!(keymap.bindings.iter().all(|binding| !binding.is_hotkey()), "{}", name);
+    }
+}
+
+#[test]
+fn test_display() {
+    let mut keymap = Keymap::preset("qwerty").unwrap();
+    keymap.apply("clear = F\nclear = 2\n2 = Up, pad:y").unwrap();
+    let text = keymap.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].starts_with("1: 1, pad:leftshoulder "));
+    assert!(lines[0].contains("2: Up, pad:y "));
+    assert!(lines[3].ends_with("F: -"));
+    assert_eq!(lines[4], "stick deadzone: 0.25");
+}
+
+#[test]
+fn test_pad_bindings() {
+    let mut keymap = Keymap::preset("qwerty").unwrap();
+    keymap.apply("deadzone = 0.5\nclear = 5\n5 = pad:rightx+, pad:righttrigger").unwrap();
+
+    let pad: Vec<&Binding> = keymap.keys_for(0x5).collect();
+    assert_eq!(*pad[0], Binding { kind: KeyKind::Pad, name: String::from("rightx+"), key: 0x5 });
+    assert_eq!(pad[1].name, "righttrigger");
+    assert_eq!(keymap.deadzone, 0.5);
+}
+
+#[test]
+fn test_rom_keymap_path() {
+    assert_eq!(rom_keymap_path(Path::new("roms/pong.ch8")), PathBuf::from("roms/pong.keymap"));
+}
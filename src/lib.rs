@@ -0,0 +1,56 @@
+//! CHIP-8, SUPER-CHIP and XO-CHIP interpreter core.
+//!
+//! The core has no dependencies on a window or audio device, a frontend loads
+//! a `Rom` into a `CPU`, calls `run_frame` sixty times a second with the keys
+//! held down, and draws the `State` it gets back. The SDL window, keyboard and
+//! speaker used by the `chip-8` binary are behind the default `sdl` feature,
+//! and the raw mode terminal behind the default `terminal` feature.
+
+extern crate rand;
+#[cfg(feature = "sdl")]
+extern crate sdl2;
+#[cfg(feature = "terminal")]
+extern crate libc;
+
+pub mod cpu;
+pub mod rom;
+pub mod quirks;
+pub mod scheduler;
+pub mod fault;
+pub mod instruction;
+pub mod random;
+pub mod headless;
+pub mod savestate;
+pub mod audio;
+pub mod palette;
+pub mod phosphor;
+pub mod pacing;
+pub mod keymap;
+pub mod terminal;
+pub mod debugger;
+pub mod disasm;
+pub mod assembler;
+pub mod trace;
+pub mod watch;
+mod font;
+
+#[cfg(feature = "sdl")]
+pub mod video;
+#[cfg(feature = "sdl")]
+pub mod input;
+#[cfg(feature = "sdl")]
+pub mod speaker;
+#[cfg(all(feature = "terminal", unix))]
+pub mod tty;
+
+pub use cpu::{CPU, KeyEvent, State};
+pub use fault::{CpuFault, FaultAction, FaultPolicy, StepOutcome};
+pub use quirks::Quirks;
+pub use rom::Rom;
+
+/// Size of the standard CHIP-8 screen
+pub const SCREEN_HEIGHT: usize = 32;
+pub const SCREEN_WIDTH: usize = 64;
+/// Size of the SUPER-CHIP high resolution screen, also the size of `State::video_buffer`
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+pub const HIRES_SCREEN_WIDTH: usize = 128;
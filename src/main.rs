@@ -1,58 +1,573 @@
-extern crate rand;
-extern crate sdl2;
+extern crate chip_8;
 
 use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
 
-mod cpu;
-mod rom;
-mod font;
-mod video;
-mod input;
+mod options;
 
-use std::thread;
-use std::time::Duration;
-use cpu::CPU;
-use rom::Rom;
-use video::VideoWindow;
-use input::Input;
+use chip_8::CPU;
+use chip_8::Rom;
+use chip_8::assembler;
+use chip_8::disasm::{self, Syntax};
+use chip_8::headless::{self, KeyScript};
+use chip_8::keymap::{self, Keymap};
+use chip_8::random::XorShift;
+use chip_8::trace::Tracer;
+use options::Options;
 
+fn main() {
 
-const SCREEN_HEIGHT: usize = 32;
-const SCREEN_WIDTH: usize = 64;
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n{}", message, options::USAGE);
+            process::exit(2);
+        }
+    };
 
-fn main() {
+    if options.print_keymap {
+        match load_keymap(&options) {
+            Ok(keymap) => print!("{}", keymap),
+            Err(message) => {
+                eprintln!("{}", message);
+                process::exit(2);
+            }
+        }
+        return;
+    }
+
+    if options.disassemble {
+        process::exit(run_disassembler(&options));
+    }
+
+    if options.assemble {
+        process::exit(run_assembler(&options));
+    }
+
+    let mut chip = match options.seed {
+        Some(seed) => CPU::with_rng(options.quirks, Box::new(XorShift::from_seed(seed))),
+        None => CPU::new(options.quirks),
+    };
+    chip.set_speed(options.speed);
+    chip.set_fault_policy(options.fault_policy);
+    let current_rom = match Rom::new(&options.rom) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("{}: {}", options.rom, error);
+            process::exit(2);
+        }
+    };
+
+    chip.load_rom(current_rom);
+
+    if let Some(ref path) = options.trace {
+        match fs::File::create(path) {
+            Ok(file) => {
+                let output = Box::new(io::BufWriter::new(file));
+                chip.set_tracer(Tracer::new(options.trace_format, options.trace_filter.clone(), output));
+            },
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                process::exit(2);
+            }
+        }
+    }
+
+    for &watchpoint in options.watchpoints.iter() {
+        chip.add_watchpoint(watchpoint);
+    }
+
+    if options.headless {
+        let status = run_headless(&options, &mut chip);
+        finish_trace(&mut chip, &options);
+        process::exit(status);
+    }
+
+    if options.debug {
+        run_debugger(&mut chip, &options);
+    } else if options.terminal {
+        run_terminal(&mut chip, &options);
+    } else {
+        run_window(&mut chip, &options);
+    }
+    finish_trace(&mut chip, &options);
+}
+
+// Writes out the end of the trace, if there is one
+fn finish_trace(chip: &mut CPU, options: &Options) {
+    if let Some(mut tracer) = chip.take_tracer() {
+        if let Err(error) = tracer.finish() {
+            eprintln!("{}: {}", options.trace.as_deref().unwrap_or("trace"), error);
+        }
+    }
+}
+
+// Runs the loaded rom in an SDL window until it exits or the window is closed
+#[cfg(feature = "sdl")]
+fn run_window(chip: &mut CPU, options: &Options) {
+
+    use std::time::Instant;
+    use chip_8::audio::{AudioBackend, NullAudio};
+    use chip_8::input::{Hotkey, Input};
+    use chip_8::pacing::{FramePacer, RateMeter};
+    use chip_8::palette::PRESETS;
+    use chip_8::savestate;
+    use chip_8::speaker::Speaker;
+    use chip_8::video::VideoWindow;
 
     let sdl_context = sdl2::init().unwrap();
-    let sleep_duration = Duration::from_millis(1);
 
-    let mut display = VideoWindow::new(&sdl_context);
-    let mut input = Input::new(&sdl_context);
+    let rom_name = Path::new(&options.rom)
+        .file_name()
+        .map_or(options.rom.clone(), |name| name.to_string_lossy().into_owned());
+    let title = format!("chip-8 - {}", rom_name);
+    let mut display = VideoWindow::new(&sdl_context, &title, options.scale, options.integer_scale, options.palette);
+    display.set_persistence(options.phosphor);
+    // the palette hotkey cycles through the presets, starting from a custom palette if one was given
+    let mut palettes: Vec<_> = PRESETS.iter().map(|&(_, palette)| palette).collect();
+    if !palettes.contains(&options.palette) {
+        palettes.insert(0, options.palette);
+    }
+    let mut palette_index = palettes.iter().position(|&palette| palette == options.palette).unwrap_or(0);
+    let mut input = match load_keymap(options).and_then(|keymap| Input::new(&sdl_context, &keymap)) {
+        Ok(input) => input,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+    // carry on without sound rather than not at all
+    let mut audio: Box<dyn AudioBackend> = match Speaker::new(&sdl_context, options.tone) {
+        Ok(speaker) => Box::new(speaker),
+        Err(message) => {
+            eprintln!("no audio: {}", message);
+            Box::new(NullAudio)
+        }
+    };
+    let rom = &options.rom;
 
-    let mut chip = CPU::new();
-    let filename = env::args().nth(1);
+    let mut pacer = FramePacer::new();
+    let mut rate_meter = RateMeter::new(Instant::now(), chip.instruction_count());
+    let mut paused = false;
+    let mut exited = false;
 
-    let current_rom = match filename {
-        Some(filename) => {
-            Rom::new(&filename)
-        },
+    while let Ok(input_state) = input.poll() {
+
+        let mut redraw = false;
+        let mut advance = false;
+
+        for hotkey in input_state.hotkeys {
+            match hotkey {
+                Hotkey::SaveState(slot) => {
+                    let path = savestate::slot_path(Path::new(rom), slot);
+                    match fs::write(&path, chip.save_state()) {
+                        Ok(()) => eprintln!("saved state to {}", path.display()),
+                        Err(error) => eprintln!("{}: {}", path.display(), error),
+                    }
+                },
+                Hotkey::LoadState(slot) => {
+                    let path = savestate::slot_path(Path::new(rom), slot);
+                    let loaded = fs::read(&path)
+                        .map_err(|error| error.to_string())
+                        .and_then(|data| chip.load_state(&data).map_err(|error| error.to_string()));
+                    match loaded {
+                        Ok(()) => {
+                            eprintln!("loaded state from {}", path.display());
+                            redraw = true;
+                        },
+                        Err(message) => eprintln!("{}: {}", path.display(), message),
+                    }
+                },
+                Hotkey::NextPalette => {
+                    palette_index = (palette_index + 1) % palettes.len();
+                    display.set_palette(palettes[palette_index]);
+                    redraw = true;
+                },
+                Hotkey::ToggleFullscreen => display.toggle_fullscreen(),
+                Hotkey::TogglePause => {
+                    paused = !paused;
+                    if paused {
+                        display.set_title(&format!("{} - paused", title));
+                    }
+                },
+                Hotkey::AdvanceFrame => {
+                    paused = true;
+                    advance = true;
+                },
+            }
+        }
+
+        // emulated frames to run during this host frame
+        let frames = match (paused, input_state.fast_forward) {
+            (true, _) => advance as u32,
+            (false, true) => options.fast_forward,
+            (false, false) => 1,
+        };
+
+        // key events go to the first frame run, while paused they're dropped
+        if frames > 0 {
+            for &event in input_state.key_events.iter() {
+                chip.key_event(event);
+            }
+        }
+
+        // A halted cpu leaves the last frame on screen until the window is closed
+        for _ in 0..frames {
+            match chip.run_frame(input_state.keypad) {
+                Ok(state) => {
+                    if state.exited {
+                        exited = true;
+                        break;
+                    }
+
+                    audio.play_frame(&state);
+                    redraw |= state.video_changed;
+                },
+                Err(fault) => eprintln!("cpu halted: {}", fault),
+            }
+        }
+        if exited {
+            break;
+        }
+
+        // a watchpoint that pauses pauses the window, unpausing carries on past it
+        for hit in chip.take_watch_hits() {
+            eprintln!("{}", hit);
+        }
+        if chip.is_paused() {
+            chip.resume();
+            paused = true;
+            display.set_title(&format!("{} - paused at a watchpoint", title));
+        }
+
+        // the screen is uploaded at most once a frame however often the rom drew,
+        // and presented every frame
+        if redraw || display.is_fading() {
+            let state = chip.state();
+            display.update(state.video_buffer, state.hires);
+        }
+        display.present();
+
+        if let Some((fps, ips)) = rate_meter.frame(Instant::now(), chip.instruction_count()) {
+            if !paused {
+                display.set_title(&format!("{} - {:.0} fps, {:.0} instructions/s", title, fps, ips));
+            }
+        }
+
+        pacer.wait();
+    }
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_window(_chip: &mut CPU, _options: &Options) {
+    eprintln!("built without the sdl feature, only --headless runs are available");
+    process::exit(2);
+}
+
+// Runs the loaded rom in the terminal until it exits or escape is pressed
+#[cfg(all(feature = "terminal", unix))]
+fn run_terminal(chip: &mut CPU, options: &Options) {
+
+    use std::time::{Duration, Instant};
+    use chip_8::pacing::{FramePacer, RateMeter};
+    use chip_8::terminal::{self, KeyHold, TermKey};
+    use chip_8::tty::Tty;
+
+    let bindings = match load_keymap(options) {
+        Ok(keymap) => terminal::terminal_bindings(&keymap),
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+
+    let rom_name = Path::new(&options.rom)
+        .file_name()
+        .map_or(options.rom.clone(), |name| name.to_string_lossy().into_owned());
+
+    let mut tty = match Tty::open() {
+        Ok(tty) => tty,
+        Err(error) => {
+            eprintln!("can't draw in the terminal: {}", error);
+            process::exit(2);
+        }
+    };
+
+    let mut keys = KeyHold::new(Duration::from_millis(options.key_hold as u64));
+    let mut pacer = FramePacer::new();
+    let mut rate_meter = RateMeter::new(Instant::now(), chip.instruction_count());
+    let mut rate = String::from("escape quits");
+    let mut halted = None;
+    let mut last_hit = None;
+    let mut layout = None;
+    let mut redraw = true;
+
+    while let Ok(input) = tty.read() {
+        let now = Instant::now();
+
+        let pressed = terminal::parse_keys(&input);
+        if pressed.iter().any(|&key| key == TermKey::Escape || key == TermKey::Interrupt) {
+            break;
+        }
+        for &(host_key, key) in bindings.iter() {
+            if pressed.contains(&host_key) {
+                keys.press(key, now);
+            }
+        }
+
+        // printing would scroll the screen away, so the last watchpoint hit goes
+        // in the status line. Any key carries on after one that pauses.
+        if chip.is_paused() && !pressed.is_empty() {
+            chip.resume();
+            redraw = true;
+        }
+
+        // a fault is only reported once, the halted cpu keeps its last frame on screen
+        if !chip.is_paused() {
+            match chip.run_frame(keys.keypad(now)) {
+                Ok(state) => {
+                    if state.exited {
+                        break;
+                    }
+                    redraw |= state.video_changed;
+                },
+                Err(fault) => {
+                    halted = Some(fault);
+                    redraw = true;
+                },
+            }
+        }
+        if let Some(hit) = chip.take_watch_hits().pop() {
+            last_hit = Some(hit);
+            redraw = true;
+        }
+
+        // the screen is centred above a status line, and drawn again from
+        // scratch whenever the terminal is resized or the resolution changes
+        let state = chip.state();
+        let (columns, rows) = tty.size().unwrap_or((80, 24));
+        let screen_rows = rows.saturating_sub(1);
+        let glyphs = match options.glyphs {
+            Some(glyphs) => Some(glyphs).filter(|&glyphs| {
+                let (width, height) = terminal::screen_size(state.hires, glyphs);
+                width <= columns && height <= screen_rows
+            }),
+            None => terminal::fitting_glyphs(state.hires, columns, screen_rows),
+        };
+        if layout != Some((columns, rows, state.hires, glyphs)) {
+            layout = Some((columns, rows, state.hires, glyphs));
+            redraw = true;
+            if tty.write("\x1b[0m\x1b[2J").is_err() {
+                break;
+            }
+        }
+
+        if let Some((fps, _)) = rate_meter.frame(Instant::now(), chip.instruction_count()) {
+            rate = format!("{:.0} fps - escape quits", fps);
+            redraw = true;
+        }
+
+        if redraw {
+            let mut frame = match glyphs {
+                Some(glyphs) => {
+                    let (width, height) = terminal::screen_size(state.hires, glyphs);
+                    let column = (columns - width) / 2 + 1;
+                    let row = (screen_rows - height) / 2 + 1;
+                    terminal::render(state.video_buffer, state.hires, &options.palette, glyphs, column, row)
+                },
+                None => {
+                    let (width, height) = terminal::screen_size(state.hires, terminal::Glyphs::Braille);
+                    format!("\x1b[1;1Hterminal too small, needs {}x{}", width, height + 1)
+                },
+            };
+            let status = match (halted, last_hit) {
+                (Some(ref fault), _) => format!("{} - cpu halted: {}", rom_name, fault),
+                (None, Some(hit)) if chip.is_paused() => format!("{} - {} - any key carries on", rom_name, hit),
+                (None, Some(hit)) => format!("{} - {} - {}", rom_name, hit, rate),
+                (None, None) => format!("{} - {}", rom_name, rate),
+            };
+            frame.push_str(&format!("\x1b[{};1H\x1b[2K{}", rows, status));
+            if tty.write(&frame).is_err() {
+                break;
+            }
+            redraw = false;
+        }
+
+        pacer.wait();
+    }
+}
+
+#[cfg(not(all(feature = "terminal", unix)))]
+fn run_terminal(_chip: &mut CPU, _options: &Options) {
+    eprintln!("built without the terminal feature, or not on unix");
+    process::exit(2);
+}
+
+// The keymap from --keymap, either a preset or a file applied over the qwerty
+// preset, plus the rom's own keymap file if there is one
+fn load_keymap(options: &Options) -> Result<Keymap, String> {
+
+    let mut keymap = match Keymap::preset(&options.keymap) {
+        Some(keymap) => keymap,
         None => {
-            panic!("Uh Oh: File not read");
+            let text = fs::read_to_string(&options.keymap)
+                .map_err(|error| format!("{}: {}", options.keymap, error))?;
+            let mut keymap = Keymap::preset("qwerty").unwrap_or_default();
+            keymap.apply(&text).map_err(|message| format!("{}: {}", options.keymap, message))?;
+            keymap
+        },
+    };
+
+    if !options.rom.is_empty() {
+        let path = keymap::rom_keymap_path(Path::new(&options.rom));
+        if let Ok(text) = fs::read_to_string(&path) {
+            keymap.apply(&text).map_err(|message| format!("{}: {}", path.display(), message))?;
+        }
+    }
+
+    Ok(keymap)
+}
+
+// Reads debugger commands from stdin until quit or the end of input
+fn run_debugger(chip: &mut CPU, options: &Options) {
+
+    use std::io::{BufRead, Write};
+    use chip_8::debugger::Debugger;
+
+    let mut debugger = Debugger::new(options.speed);
+    println!("{}, type 'help' for commands", options.rom);
+    println!("{}", Debugger::location(chip));
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(chip-8) ");
+        let _ = io::stdout().flush();
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        match debugger.command(chip, &line) {
+            Some(output) if output.is_empty() => (),
+            Some(output) => println!("{}", output),
+            None => break,
+        }
+    }
+}
+
+// Prints the rom as assembly. Returns the exit code, 2 when a file can't be
+// read or written.
+fn run_disassembler(options: &Options) -> i32 {
+
+    let rom = match fs::read(&options.rom) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("{}: {}", options.rom, error);
+            return 2;
         }
     };
 
-    chip.load_rom(current_rom);
+    let syntax = if options.octo { Syntax::Octo } else { Syntax::Plain };
+    let text = disasm::disassemble(&rom, syntax);
+    match options.output {
+        Some(ref path) => {
+            if let Err(error) = fs::write(path, text) {
+                eprintln!("{}: {}", path, error);
+                return 2;
+            }
+        },
+        None => print!("{}", text),
+    }
 
-    while let Ok(keypad) = input.poll() {
+    0
+}
 
-        let state = chip.tick(keypad);
+// Assembles the source into a rom, next to the source unless --output says
+// otherwise. Returns the exit code, 1 when the source has errors and 2 when a
+// file can't be written.
+fn run_assembler(options: &Options) -> i32 {
 
-        if state.video_changed {
-            display.draw(state.video_buffer);
+    let program = match assembler::assemble_file(&options.rom) {
+        Ok(program) => program,
+        Err(message) => {
+            eprintln!("{}", message);
+            return 1;
         }
+    };
 
-        thread::sleep(sleep_duration);
+    let output = match options.output {
+        Some(ref path) => path.clone(),
+        None => Path::new(&options.rom).with_extension("ch8").to_string_lossy().into_owned(),
+    };
+    if output == options.rom {
+        eprintln!("{}: the rom would overwrite the source, use --output", output);
+        return 2;
+    }
 
+    let mut files = vec![(output, program.bytes.clone())];
+    if let Some(ref path) = options.symbols {
+        files.push((path.clone(), program.symbol_file().into_bytes()));
     }
-    
-    
+    for (path, contents) in files {
+        if let Err(error) = fs::write(&path, contents) {
+            eprintln!("{}: {}", path, error);
+            return 2;
+        }
+    }
+
+    0
+}
+
+// Runs the loaded rom without a window and dumps the final screen. Returns the
+// exit code: 0 when the run finishes, 1 on a cpu fault, 2 when a file can't be
+// read or written.
+fn run_headless(options: &Options, chip: &mut CPU) -> i32 {
+
+    let script = match options.key_script {
+        Some(ref path) => {
+            let script = fs::read_to_string(path)
+                .map_err(|error| error.to_string())
+                .and_then(|text| KeyScript::parse(&text));
+            match script {
+                Ok(script) => script,
+                Err(message) => {
+                    eprintln!("{}: {}", path, message);
+                    return 2;
+                }
+            }
+        },
+        None => KeyScript::default(),
+    };
+
+    let status = match headless::run(chip, options.run_length, options.speed, &script) {
+        Ok(()) => 0,
+        Err(fault) => {
+            eprintln!("cpu halted: {}", fault);
+            1
+        }
+    };
+    for hit in chip.take_watch_hits() {
+        eprintln!("{}", hit);
+    }
+    if chip.is_paused() {
+        eprintln!("stopped at a watchpoint");
+    }
+
+    // the screen is dumped even after a fault, it's often the best clue to what went wrong
+    let dump = headless::dump(&chip.state(), options.dump);
+    match options.output {
+        Some(ref path) => {
+            if let Err(error) = fs::write(path, dump) {
+                eprintln!("{}: {}", path, error);
+                return 2;
+            }
+        },
+        None => print!("{}", dump),
+    }
+
+    status
 }
@@ -0,0 +1,472 @@
+use std::fs;
+
+use chip_8::audio::{ToneSettings, Waveform};
+use chip_8::fault::{FaultAction, FaultPolicy};
+use chip_8::headless::{DumpFormat, RunLength};
+use chip_8::palette::Palette;
+use chip_8::phosphor::Phosphor;
+use chip_8::quirks::Quirks;
+use chip_8::scheduler::DEFAULT_INSTRUCTIONS_PER_SECOND;
+use chip_8::terminal::Glyphs;
+use chip_8::trace::{TraceFilter, TraceFormat};
+use chip_8::watch::Watchpoint;
+
+/// Size of a low resolution pixel in the window that opens, 640x320 fits most screens
+pub const DEFAULT_SCALE: u32 = 10;
+
+pub const USAGE: &str = "usage: chip-8 [options] <rom_filename>
+       chip-8 disasm [--octo] [--output <file>] <rom_filename>
+       chip-8 asm [--output <file>] [--symbols <file>] <source_filename>
+
+options:
+    --config <file>                         read options from a file, one 'option = value' per line
+    --keymap <qwerty|azerty|dvorak|numpad|file>
+                                            keyboard layout, or a keymap file. A <rom>.keymap
+                                            file next to the rom adds to it
+    --print-keymap                          show the keymap for the rom and exit
+    --quirks <default|vip|chip-48|schip|xo-chip>
+    --speed <instructions per second>
+    --seed <number>                         repeatable random numbers
+    --palette <name | #rrggbb,#rrggbb[,#rrggbb,#rrggbb]>
+                                            classic, amber, white, lcd or inverted, or custom
+                                            colors for background, plane 1, plane 2, both planes
+    --fast-forward <multiplier>             speed while F7 is held down (default 4)
+    --phosphor <off|low|medium|high|frames> fade pixels out over a few frames to hide
+                                            flicker (default off)
+    --scale <pixels>                        starting window size, in pixels per chip-8 pixel (default 10)
+    --scaling <integer|stretch>             scale the screen by whole numbers only, or fill
+                                            as much of the window as fits (default integer)
+    --tone <hz>                             pitch of the buzzer (default 440)
+    --volume <0-100>                        loudness of the buzzer (default 25)
+    --waveform <square|sine>                shape of the buzzer's tone (default square)
+    --on-fault <action | kind=action,...>   action is halt, ignore or log,
+                                            kind is stack, memory, opcode or pc
+
+tracing:
+    --trace <file>                          log every instruction run, with the registers it changed
+    --trace-format <text|json>              one line of text or one JSON object per instruction
+                                            (default text)
+    --trace-range <start-end>               only instructions at these hex addresses
+    --trace-opcodes <classes>               only opcodes starting with these hex digits, like 8,d
+    --trace-first <count>                   only the first this many instructions
+    --trace-last <count>                    only the last this many, written when the rom stops
+
+watchpoints:
+    --watch <target [read|write|rw] [op value] [pause]>
+                                            report instructions touching an address, a hex
+                                            range like 300-30f, or v0-vf, i, dt or st. op is
+                                            ==, !=, <, <=, > or >=. pause stops the rom on a hit.
+                                            dt and st only see instructions writing them, not
+                                            the timers counting down.
+                                            Can be given more than once
+
+terminal options:
+    --terminal                              draw in the terminal instead of opening a window
+    --glyphs <auto|half-block|braille>      characters the screen is drawn with (default auto)
+    --key-hold <milliseconds>               how long a key stays down after the terminal
+                                            sends it (default 150)
+
+debugger:
+    --debug                                 step through the rom at a command prompt, without
+                                            a window. 'help' at the prompt lists the commands
+
+disassembler:
+    disasm                                  print the rom as assembly instead of running it
+    --octo                                  write Octo syntax, which assembles back into the rom
+
+assembler:
+    asm                                     assemble the source into a rom instead of running it,
+                                            written next to the source as .ch8 unless --output is given
+    --symbols <file>                        also write the address of every label to a file
+
+headless options:
+    --headless                              run without a window and dump the final screen
+    --frames <count>                        run for this many 60hz frames (default 600)
+    --instructions <count>                  run for this many instructions instead
+    --keys <file>                           key script, one '<frame> <hex keys...>' per line
+    --dump <ascii|pbm|hash>                 format of the final screen (default ascii)
+    --output <file>                         write the dump or disassembly here instead of stdout";
+
+// Command line options, parsed by hand since there are only a few of them
+pub struct Options {
+    /// path to the rom to run, or the source to assemble, only empty with print_keymap
+    pub rom: String,
+    /// keymap preset name or keymap file
+    pub keymap: String,
+    /// show the keymap instead of running the rom
+    pub print_keymap: bool,
+    /// quirk profile the cpu is built with
+    pub quirks: Quirks,
+    /// instructions run per second of emulated time
+    pub speed: u32,
+    /// what the cpu does with each kind of fault
+    pub fault_policy: FaultPolicy,
+    /// seed for CXNN's random numbers, None picks one from the operating system
+    pub seed: Option<u64>,
+    /// colors the screen starts out in
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub palette: Palette,
+    /// emulated frames run per frame while fast forwarding
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub fast_forward: u32,
+    /// frames a pixel takes to fade out after switching off
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub phosphor: u8,
+    /// starting window size, in window pixels per low resolution pixel
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub scale: u32,
+    /// only scale the screen by whole numbers, otherwise it fills as much of the window as fits
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub integer_scale: bool,
+    /// how the buzzer sounds, there's no buzzer without the sdl feature
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub tone: ToneSettings,
+    /// draw in the terminal instead of a window
+    pub terminal: bool,
+    /// characters the terminal screen is drawn with, None picks whichever fits
+    #[cfg_attr(not(feature = "terminal"), allow(dead_code))]
+    pub glyphs: Option<Glyphs>,
+    /// milliseconds a key stays down after the terminal sends it
+    #[cfg_attr(not(feature = "terminal"), allow(dead_code))]
+    pub key_hold: u32,
+    /// file every instruction run is logged to, see the trace module
+    pub trace: Option<String>,
+    pub trace_format: TraceFormat,
+    /// which instructions are logged
+    pub trace_filter: TraceFilter,
+    /// watchpoints the cpu starts with, see the watch module
+    pub watchpoints: Vec<Watchpoint>,
+    /// run at the debugger prompt, see the debugger module
+    pub debug: bool,
+    /// print the rom as assembly, see the disasm module
+    pub disassemble: bool,
+    /// disassemble to Octo syntax instead of plain mnemonics
+    pub octo: bool,
+    /// assemble the file named by rom, see the assembler module
+    pub assemble: bool,
+    /// file the assembler writes label addresses to
+    pub symbols: Option<String>,
+    /// run without a window, see the headless module
+    pub headless: bool,
+    /// how long a headless run lasts
+    pub run_length: RunLength,
+    /// key script file for a headless run
+    pub key_script: Option<String>,
+    /// format the screen is dumped in after a headless run
+    pub dump: DumpFormat,
+    /// file the dump, disassembly or assembled rom is written to, None writes
+    /// the dump and disassembly to stdout
+    pub output: Option<String>,
+}
+
+impl Options {
+
+    /// Parses the arguments following the program name
+    pub fn parse<I: Iterator<Item = String>>(args: I) -> Result<Options, String> {
+
+        let mut args = Options::expand_config_files(args)?.into_iter();
+
+        let mut rom = None;
+        let mut keymap = String::from("qwerty");
+        let mut print_keymap = false;
+        let mut quirks = Quirks::default();
+        let mut speed = DEFAULT_INSTRUCTIONS_PER_SECOND;
+        let mut fault_policy = FaultPolicy::default();
+        let mut seed = None;
+        let mut palette = Palette::default();
+        let mut fast_forward = 4;
+        let mut phosphor = 0;
+        let mut scale = DEFAULT_SCALE;
+        let mut integer_scale = true;
+        let mut tone = ToneSettings::default();
+        let mut terminal = false;
+        let mut glyphs = None;
+        let mut key_hold = 150;
+        let mut trace = None;
+        let mut trace_format = TraceFormat::Text;
+        let mut trace_filter = TraceFilter::default();
+        let mut watchpoints = Vec::new();
+        let mut debug = false;
+        let mut disassemble = false;
+        let mut octo = false;
+        let mut assemble = false;
+        let mut symbols = None;
+        let mut headless = false;
+        let mut run_length = RunLength::Frames(600);
+        let mut key_script = None;
+        let mut dump = DumpFormat::Ascii;
+        let mut output = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--keymap" => keymap = Options::value(&mut args, &arg)?,
+                "--print-keymap" => print_keymap = true,
+                "--quirks" => {
+                    let name = Options::value(&mut args, &arg)?;
+                    quirks = Quirks::from_name(&name)
+                        .ok_or_else(|| format!("unknown quirk profile '{}'", name))?;
+                },
+                "--speed" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    speed = match value.parse() {
+                        Ok(speed) if speed > 0 => speed,
+                        _ => return Err(format!("invalid speed '{}'", value)),
+                    };
+                },
+                "--on-fault" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    fault_policy = Options::fault_policy(&value, fault_policy)?;
+                },
+                "--seed" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    seed = Some(value.parse().map_err(|_| format!("invalid seed '{}'", value))?);
+                },
+                "--palette" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    palette = Palette::parse(&value)?;
+                },
+                "--fast-forward" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    fast_forward = match value.parse() {
+                        Ok(multiplier) if multiplier > 0 && multiplier <= 100 => multiplier,
+                        _ => return Err(format!("invalid fast forward multiplier '{}'", value)),
+                    };
+                },
+                "--phosphor" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    phosphor = Phosphor::decay_from_name(&value)
+                        .ok_or_else(|| format!("invalid phosphor decay '{}'", value))?;
+                },
+                "--scale" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    scale = match value.parse() {
+                        Ok(scale) if scale > 0 && scale <= 64 => scale,
+                        _ => return Err(format!("invalid scale '{}'", value)),
+                    };
+                },
+                "--scaling" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    integer_scale = match value.as_str() {
+                        "integer" => true,
+                        "stretch" => false,
+                        _ => return Err(format!("unknown scaling '{}'", value)),
+                    };
+                },
+                "--tone" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    tone.frequency = match value.parse() {
+                        Ok(frequency) if frequency > 0.0 && frequency < 20000.0 => frequency,
+                        _ => return Err(format!("invalid tone '{}'", value)),
+                    };
+                },
+                "--volume" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    tone.volume = match value.parse::<u8>() {
+                        Ok(volume) if volume <= 100 => volume as f32 / 100.0,
+                        _ => return Err(format!("invalid volume '{}'", value)),
+                    };
+                },
+                "--waveform" => {
+                    let name = Options::value(&mut args, &arg)?;
+                    tone.waveform = Waveform::from_name(&name)
+                        .ok_or_else(|| format!("unknown waveform '{}'", name))?;
+                },
+                "--terminal" => terminal = true,
+                "--glyphs" => {
+                    let name = Options::value(&mut args, &arg)?;
+                    glyphs = Glyphs::from_name(&name)
+                        .ok_or_else(|| format!("unknown glyphs '{}'", name))?;
+                },
+                "--key-hold" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    key_hold = match value.parse() {
+                        Ok(milliseconds) if milliseconds > 0 && milliseconds <= 2000 => milliseconds,
+                        _ => return Err(format!("invalid key hold '{}'", value)),
+                    };
+                },
+                "--trace" => trace = Some(Options::value(&mut args, &arg)?),
+                "--trace-format" => {
+                    let name = Options::value(&mut args, &arg)?;
+                    trace_format = TraceFormat::from_name(&name)
+                        .ok_or_else(|| format!("unknown trace format '{}'", name))?;
+                },
+                "--trace-range" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    trace_filter.range = Some(TraceFilter::parse_range(&value)?);
+                },
+                "--trace-opcodes" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    trace_filter.classes = TraceFilter::parse_classes(&value)?;
+                },
+                "--trace-first" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    trace_filter.first = Some(Options::count(&value)? as u64);
+                },
+                "--trace-last" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    trace_filter.last = Some(Options::count(&value)? as usize);
+                },
+                "--watch" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    watchpoints.push(Watchpoint::parse(&value)?);
+                },
+                "--debug" => debug = true,
+                "disasm" if rom.is_none() && !disassemble && !assemble => disassemble = true,
+                "--octo" => octo = true,
+                "asm" if rom.is_none() && !disassemble && !assemble => assemble = true,
+                "--symbols" => symbols = Some(Options::value(&mut args, &arg)?),
+                "--headless" => headless = true,
+                "--frames" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    run_length = RunLength::Frames(Options::count(&value)?);
+                },
+                "--instructions" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    run_length = RunLength::Instructions(Options::count(&value)?);
+                },
+                "--keys" => key_script = Some(Options::value(&mut args, &arg)?),
+                "--dump" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    dump = match value.as_str() {
+                        "ascii" => DumpFormat::Ascii,
+                        "pbm" => DumpFormat::Pbm,
+                        "hash" => DumpFormat::Hash,
+                        _ => return Err(format!("unknown dump format '{}'", value)),
+                    };
+                },
+                "--output" => output = Some(Options::value(&mut args, &arg)?),
+                _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+                _ => rom = Some(arg),
+            }
+        }
+
+        Ok(Options {
+            rom: match rom {
+                Some(rom) => rom,
+                None if print_keymap => String::new(),
+                None => return Err(String::from("no rom given")),
+            },
+            keymap: keymap,
+            print_keymap: print_keymap,
+            quirks: quirks,
+            speed: speed,
+            fault_policy: fault_policy,
+            seed: seed,
+            palette: palette,
+            fast_forward: fast_forward,
+            phosphor: phosphor,
+            scale: scale,
+            integer_scale: integer_scale,
+            tone: tone,
+            terminal: terminal,
+            glyphs: glyphs,
+            key_hold: key_hold,
+            trace: trace,
+            trace_format: trace_format,
+            trace_filter: trace_filter,
+            watchpoints: watchpoints,
+            debug: debug,
+            disassemble: disassemble,
+            octo: octo,
+            assemble: assemble,
+            symbols: symbols,
+            headless: headless,
+            run_length: run_length,
+            key_script: key_script,
+            dump: dump,
+            output: output,
+        })
+    }
+
+    // Replaces each --config <file> with the options in the file, so options
+    // after it on the command line override the file. Each line of the file is
+    // 'option = value', with the option named as on the command line without
+    // the leading dashes:
+    //
+    //     # long sessions are easier on the eyes in amber
+    //     palette = amber
+    //     speed = 1000
+    //
+    // 'rom = <file>' names the rom, and flags like headless take true or false.
+    fn expand_config_files<I: Iterator<Item = String>>(mut args: I) -> Result<Vec<String>, String> {
+
+        let mut expanded = Vec::new();
+
+        while let Some(arg) = args.next() {
+            if arg != "--config" {
+                expanded.push(arg);
+                continue;
+            }
+
+            let path = Options::value(&mut args, &arg)?;
+            let text = fs::read_to_string(&path).map_err(|error| format!("{}: {}", path, error))?;
+
+            for (number, line) in text.lines().enumerate() {
+                // only whole line comments, colors start with '#' too
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                let mut parts = line.splitn(2, '=');
+                let key = parts.next().unwrap_or("").trim();
+                let value = parts.next()
+                    .map(str::trim)
+                    .ok_or_else(|| format!("{} line {}: expected 'option = value'", path, number + 1))?;
+
+                match (key, value) {
+                    ("rom", _) => expanded.push(String::from(value)),
+                    ("config", _) => return Err(format!("{} line {}: config files can't include others", path, number + 1)),
+                    (_, "true") => expanded.push(format!("--{}", key)),
+                    (_, "false") => (),
+                    _ => {
+                        expanded.push(format!("--{}", key));
+                        expanded.push(String::from(value));
+                    },
+                }
+            }
+        }
+
+        Ok(expanded)
+    }
+
+    // Either a single action for every fault, or a comma separated list of kind=action
+    fn fault_policy(value: &str, mut policy: FaultPolicy) -> Result<FaultPolicy, String> {
+
+        let action = |name: &str| FaultAction::from_name(name)
+            .ok_or_else(|| format!("unknown fault action '{}'", name));
+
+        if !value.contains('=') {
+            return Ok(FaultPolicy::all(action(value)?));
+        }
+
+        for setting in value.split(',') {
+            let mut parts = setting.splitn(2, '=');
+            let kind = parts.next().unwrap_or("");
+            let kind_action = action(parts.next().unwrap_or(""))?;
+            match kind {
+                "stack" => policy.stack = kind_action,
+                "memory" => policy.memory = kind_action,
+                "opcode" => policy.invalid_opcode = kind_action,
+                "pc" => policy.program_counter = kind_action,
+                _ => return Err(format!("unknown fault kind '{}'", kind)),
+            }
+        }
+
+        Ok(policy)
+    }
+
+    // Frame and instruction counts for headless runs
+    fn count(value: &str) -> Result<u32, String> {
+        value.parse().map_err(|_| format!("invalid count '{}'", value))
+    }
+
+    // Pulls the value that follows an option
+    fn value<I: Iterator<Item = String>>(args: &mut I, option: &str) -> Result<String, String> {
+        args.next().ok_or_else(|| format!("{} needs a value", option))
+    }
+}
+
+#[cfg(test)]
+#[path = "./options_test.rs"]
+mod options_test;
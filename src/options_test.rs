@@ -0,0 +1,129 @@
+use super::*;
+use std::path::PathBuf;
+
+fn parse(args: &[&str]) -> Result<Options, String> {
+    Options::parse(args.iter().map(|&arg| String::from(arg)))
+}
+
+// Writes a config file somewhere no other test run writes to
+fn config_file(name: &str, text: &str) -> String {
+    let path: PathBuf = std::env::temp_dir().join(format!("chip-8-{}-{}.conf", std::process::id(), name));
+    fs::write(&path, text).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn test_defaults() {
+    let options = parse(&["game.ch8"]).unwrap();
+    assert_eq!(options.rom, "game.ch8");
+    assert_eq!(options.keymap, "qwerty");
+    assert_eq!(options.speed, DEFAULT_INSTRUCTIONS_PER_SECOND);
+    assert_eq!(options.fault_policy, FaultPolicy::default());
+    assert_eq!(options.run_length, RunLength::Frames(600));
+    assert!(!options.headless && !options.disassemble && !options.assemble);
+}
+
+#[test]
+fn test_values() {
+    let options = parse(&["--speed", "1000", "--headless", "--frames", "10", "--quirks", "vip", "game.ch8"]).unwrap();
+    assert_eq!(options.speed, 1000);
+    assert!(options.headless);
+    assert_eq!(options.run_length, RunLength::Frames(10));
+    assert_eq!(options.quirks, Quirks::from_name("vip").unwrap());
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(parse(&[]).err(), Some(String::from("no rom given")));
+    assert_eq!(parse(&["--speed"]).err(), Some(String::from("--speed needs a value")));
+    assert_eq!(parse(&["--speed", "0", "game.ch8"]).err(), Some(String::from("invalid speed '0'")));
+    assert_eq!(parse(&["--fast", "game.ch8"]).err(), Some(String::from("unknown option '--fast'")));
+    // only printing the keymap needs no rom
+    assert_eq!(parse(&["--print-keymap"]).unwrap().rom, "");
+}
+
+#[test]
+fn test_fault_policy() {
+    let options = parse(&["--on-fault", "ignore", "game.ch8"]).unwrap();
+    assert_eq!(options.fault_policy, FaultPolicy::all(FaultAction::Ignore));
+
+    // kinds not named keep what they had, so repeating the option adds to it
+    let options = parse(&["--on-fault", "log", "--on-fault", "stack=halt,opcode=ignore", "game.ch8"]).unwrap();
+    assert_eq!(options.fault_policy, FaultPolicy {
+        stack: FaultAction::Halt,
+        memory: FaultAction::Log,
+        invalid_opcode: FaultAction::Ignore,
+        program_counter: FaultAction::Log,
+    });
+
+    assert_eq!(parse(&["--on-fault", "explode", "game.ch8"]).err(), Some(String::from("unknown fault action 'explode'")));
+    assert_eq!(parse(&["--on-fault", "disk=log", "game.ch8"]).err(), Some(String::from("unknown fault kind 'disk'")));
+    assert_eq!(parse(&["--on-fault", "stack=", "game.ch8"]).err(), Some(String::from("unknown fault action ''")));
+}
+
+#[test]
+fn test_disassembler_and_assembler() {
+    let options = parse(&["disasm", "--octo", "game.ch8"]).unwrap();
+    assert!(options.disassemble && options.octo && !options.assemble);
+    assert_eq!(options.rom, "game.ch8");
+
+    let options = parse(&["asm", "--symbols", "game.sym", "game.8o"]).unwrap();
+    assert!(options.assemble && !options.disassemble);
+    assert_eq!(options.symbols, Some(String::from("game.sym")));
+    assert_eq!(options.rom, "game.8o");
+
+    // only the first word picks a mode, a rom can still be called disasm
+    let options = parse(&["asm", "disasm"]).unwrap();
+    assert!(options.assemble && !options.disassemble);
+    assert_eq!(options.rom, "disasm");
+    assert_eq!(parse(&["disasm"]).err(), Some(String::from("no rom given")));
+}
+
+#[test]
+fn test_config_file() {
+    let path = config_file("settings", "\
+        # long sessions are easier on the eyes in amber\n\
+        \n\
+        palette = #000000,#ffb000\n\
+        speed = 1000\n\
+        headless = true\n\
+        terminal = false\n\
+        rom = game.ch8\n");
+
+    let options = parse(&["--config", &path]).unwrap();
+    assert_eq!(options.palette, Palette::parse("#000000,#ffb000").unwrap());
+    assert_eq!(options.speed, 1000);
+    assert!(options.headless);
+    assert!(!options.terminal);
+    assert_eq!(options.rom, "game.ch8");
+
+    // options after the file override it, ones before are overridden by it
+    let options = parse(&["--speed", "2000", "--config", &path, "--frames", "5", "other.ch8"]).unwrap();
+    assert_eq!(options.speed, 1000);
+    assert_eq!(options.run_length, RunLength::Frames(5));
+    assert_eq!(options.rom, "other.ch8");
+    let options = parse(&["--config", &path, "--speed", "500"]).unwrap();
+    assert_eq!(options.speed, 500);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_config_file_errors() {
+    let including = config_file("including", "speed = 1000\nconfig = other.conf\n");
+    assert_eq!(parse(&["--config", &including]).err(),
+               Some(format!("{} line 2: config files can't include others", including)));
+
+    let missing_value = config_file("missing-value", "# fine\nheadless\n");
+    assert_eq!(parse(&["--config", &missing_value]).err(),
+               Some(format!("{} line 2: expected 'option = value'", missing_value)));
+
+    let unknown = config_file("unknown", "fast = true\n");
+    assert_eq!(parse(&["--config", &unknown, "game.ch8"]).err(), Some(String::from("unknown option '--fast'")));
+
+    assert!(parse(&["--config", "/nonexistent/chip-8.conf"]).err().unwrap().starts_with("/nonexistent/chip-8.conf: "));
+
+    for path in [including, missing_value, unknown] {
+        fs::remove_file(&path).unwrap();
+    }
+}
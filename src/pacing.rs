@@ -0,0 +1,95 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::scheduler::TIMER_HZ;
+
+/// Sleeping is only accurate to a millisecond or so, the last stretch before a
+/// deadline is spent yielding instead
+const SPIN_TIME: Duration = Duration::from_millis(2);
+
+// Keeps a frontend running at 60 frames a second of wall clock time. Each frame
+// is scheduled from the last deadline rather than from when the frame finished,
+// so small delays don't add up. If the host falls more than a frame behind, it
+// starts again from now instead of rushing to catch up.
+pub struct FramePacer {
+    frame_duration: Duration,
+    next_frame: Instant,
+}
+
+impl FramePacer {
+
+    pub fn new() -> FramePacer {
+        FramePacer {
+            frame_duration: Duration::from_secs(1) / TIMER_HZ,
+            next_frame: Instant::now(),
+        }
+    }
+
+    // Waits until it's time for the next frame
+    pub fn wait(&mut self) {
+        self.next_frame += self.frame_duration;
+
+        let now = Instant::now();
+        if self.next_frame <= now {
+            self.next_frame = now;
+            return;
+        }
+
+        let remaining = self.next_frame - now;
+        if remaining > SPIN_TIME {
+            thread::sleep(remaining - SPIN_TIME);
+        }
+        while Instant::now() < self.next_frame {
+            thread::yield_now();
+        }
+    }
+}
+
+impl Default for FramePacer {
+    fn default() -> FramePacer {
+        FramePacer::new()
+    }
+}
+
+// Measures frames and instructions per second of wall clock time, for showing
+// how fast the emulator is really running
+pub struct RateMeter {
+    start: Instant,
+    frames: u32,
+    instructions: u64,
+}
+
+impl RateMeter {
+
+    pub fn new(now: Instant, instructions: u64) -> RateMeter {
+        RateMeter {
+            start: now,
+            frames: 0,
+            instructions: instructions,
+        }
+    }
+
+    // Counts a displayed frame. Once a second has passed, returns the frames and
+    // instructions per second since the last reading and starts over. Takes the
+    // cpu's running instruction count, so frames that ran several emulated
+    // frames (fast forward) or none (paused) are counted right.
+    pub fn frame(&mut self, now: Instant, instructions: u64) -> Option<(f64, f64)> {
+        self.frames += 1;
+
+        let elapsed = now.duration_since(self.start).as_secs_f64();
+        if elapsed < 1.0 {
+            return None;
+        }
+
+        let rates = (
+            self.frames as f64 / elapsed,
+            instructions.saturating_sub(self.instructions) as f64 / elapsed,
+        );
+        *self = RateMeter::new(now, instructions);
+        Some(rates)
+    }
+}
+
+#[cfg(test)]
+#[path = "./pacing_test.rs"]
+mod pacing_test;
@@ -0,0 +1,35 @@
+use super::*;
+
+#[test]
+fn test_rate_meter() {
+    let start = Instant::now();
+    let mut meter = RateMeter::new(start, 1000);
+    let frame = Duration::from_millis(20);
+
+    for i in 1..50 {
+        assert_eq!(meter.frame(start + frame * i, 1000 + 10 * i as u64), None);
+    }
+    let (fps, ips) = meter.frame(start + frame * 50, 1500).unwrap();
+    assert!((fps - 50.0).abs() < 0.001);
+    assert!((ips - 500.0).abs() < 0.001);
+
+    // the next reading only covers the second after the first
+    let later = start + frame * 50;
+    for i in 1..25 {
+        assert_eq!(meter.frame(later + frame * 2 * i, 1500), None);
+    }
+    let (fps, ips) = meter.frame(later + frame * 50, 1500).unwrap();
+    assert!((fps - 25.0).abs() < 0.001);
+    assert_eq!(ips, 0.0);
+}
+
+#[test]
+fn test_frame_pacer_waits_a_frame() {
+    let mut pacer = FramePacer::new();
+    let start = Instant::now();
+    for _ in 0..3 {
+        pacer.wait();
+    }
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(49), "{:?}", elapsed);
+}
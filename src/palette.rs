@@ -0,0 +1,103 @@
+// Colors the screen is drawn in. Each pixel holds one bit per XO-CHIP plane, so
+// a palette has a color for every combination of planes:
+//
+//     0  background
+//     1  plane 1 only, the only lit color for CHIP-8 and SUPER-CHIP roms
+//     2  plane 2 only
+//     3  both planes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub colors: [(u8, u8, u8); 4],
+}
+
+/// Built in palettes, in the order the palette hotkey cycles through them
+pub const PRESETS: [(&str, Palette); 5] = [
+    ("classic", Palette { colors: [(0, 0, 0), (0, 250, 0), (0, 120, 60), (180, 250, 180)] }),
+    ("amber", Palette { colors: [(20, 12, 0), (255, 176, 0), (150, 90, 0), (255, 220, 130)] }),
+    ("white", Palette { colors: [(0, 0, 0), (255, 255, 255), (110, 110, 110), (190, 190, 190)] }),
+    ("lcd", Palette { colors: [(170, 178, 160), (40, 44, 36), (110, 116, 100), (75, 80, 68)] }),
+    ("inverted", Palette { colors: [(255, 255, 255), (0, 0, 0), (140, 140, 140), (70, 70, 70)] }),
+];
+
+impl Palette {
+
+    /// Looks up a built in palette by name, used for the command line
+    pub fn from_name(name: &str) -> Option<Palette> {
+        let lowercase = name.to_ascii_lowercase();
+        let name = match lowercase.as_str() {
+            "green" => "classic",
+            "white-on-black" => "white",
+            "lcd-gray" | "lcd-grey" => "lcd",
+            name => name,
+        };
+        PRESETS.iter()
+            .find(|&&(preset, _)| preset == name)
+            .map(|&(_, palette)| palette)
+    }
+
+    // Either the name of a built in palette, or a comma separated list of hex
+    // colors. Two colors give the background and foreground, with the XO-CHIP
+    // plane colors blended from them; four give every combination of planes.
+    pub fn parse(value: &str) -> Result<Palette, String> {
+
+        if !value.contains(',') {
+            return Palette::from_name(value).ok_or_else(|| format!("unknown palette '{}'", value));
+        }
+
+        let colors = value.split(',')
+            .map(|color| Palette::hex_color(color.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match colors.len() {
+            2 => {
+                let blend = |amount: u16| {
+                    let mix = |background: u8, foreground: u8| {
+                        ((background as u16 * (4 - amount) + foreground as u16 * amount) / 4) as u8
+                    };
+                    let ((r0, g0, b0), (r1, g1, b1)) = (colors[0], colors[1]);
+                    (mix(r0, r1), mix(g0, g1), mix(b0, b1))
+                };
+                Ok(Palette { colors: [colors[0], colors[1], blend(2), blend(3)] })
+            },
+            4 => Ok(Palette { colors: [colors[0], colors[1], colors[2], colors[3]] }),
+            _ => Err(format!("palette '{}' needs 2 or 4 colors", value)),
+        }
+    }
+
+    /// Color for a pixel value from the video buffer
+    pub fn color(&self, pixel: u8) -> (u8, u8, u8) {
+        self.colors[(pixel & 0b11) as usize]
+    }
+
+    // Color for a pixel part way through fading out to the background, used for
+    // phosphor persistence. Brightness 1.0 is the pixel's own color, 0.0 the background.
+    pub fn fade(&self, pixel: u8, brightness: f32) -> (u8, u8, u8) {
+        let (r0, g0, b0) = self.color(0);
+        let (r1, g1, b1) = self.color(pixel);
+        let brightness = brightness.clamp(0.0, 1.0);
+        let mix = |background: u8, foreground: u8| {
+            (background as f32 + (foreground as f32 - background as f32) * brightness).round() as u8
+        };
+        (mix(r0, r1), mix(g0, g1), mix(b0, b1))
+    }
+
+    // rrggbb, with or without a leading '#'
+    fn hex_color(text: &str) -> Result<(u8, u8, u8), String> {
+        let hex = text.trim_start_matches('#');
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid color '{}'", text));
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+        Ok((channel(0), channel(2), channel(4)))
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        PRESETS[0].1
+    }
+}
+
+#[cfg(test)]
+#[path = "./palette_test.rs"]
+mod palette_test;
@@ -0,0 +1,61 @@
+use super::*;
+
+#[test]
+fn test_default_is_classic_green() {
+    let palette = Palette::default();
+    assert_eq!(palette.color(0), (0, 0, 0));
+    assert_eq!(palette.color(1), (0, 250, 0));
+}
+
+#[test]
+fn test_from_name() {
+    for &(name, palette) in PRESETS.iter() {
+        assert_eq!(Palette::from_name(name), Some(palette));
+    }
+    assert_eq!(Palette::from_name("Amber"), Some(PRESETS[1].1));
+    assert_eq!(Palette::from_name("green"), Some(PRESETS[0].1));
+    assert_eq!(Palette::from_name("white-on-black"), Some(PRESETS[2].1));
+    assert_eq!(Palette::from_name("lcd-gray"), Some(PRESETS[3].1));
+    assert_eq!(Palette::from_name("purple"), None);
+}
+
+#[test]
+fn test_parse_custom_colors() {
+    let palette = Palette::parse("#000000, #FFB000").unwrap();
+    assert_eq!(palette.colors[0], (0, 0, 0));
+    assert_eq!(palette.colors[1], (255, 176, 0));
+    assert_eq!(palette.colors[2], (127, 88, 0));
+    assert_eq!(palette.colors[3], (191, 132, 0));
+
+    let palette = Palette::parse("101010,202020,303030,404040").unwrap();
+    assert_eq!(palette.colors, [(16, 16, 16), (32, 32, 32), (48, 48, 48), (64, 64, 64)]);
+
+    assert_eq!(Palette::parse("lcd"), Ok(PRESETS[3].1));
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(Palette::parse("purple"), Err(String::from("unknown palette 'purple'")));
+    assert_eq!(Palette::parse("#000000,#fff"), Err(String::from("invalid color '#fff'")));
+    assert_eq!(Palette::parse("#000000,#00zz00"), Err(String::from("invalid color '#00zz00'")));
+    assert!(Palette::parse("#000000,#111111,#222222").is_err());
+}
+
+#[test]
+fn test_color_masks_pixel() {
+    let palette = PRESETS[4].1;
+    assert_eq!(palette.color(3), palette.color(0xFF));
+}
+
+#[test]
+fn test_fade() {
+    let palette = Palette::parse("#000000,#ffffff,#ff0000,#00ff00").unwrap();
+    assert_eq!(palette.fade(1, 1.0), (255, 255, 255));
+    assert_eq!(palette.fade(1, 0.5), (128, 128, 128));
+    assert_eq!(palette.fade(2, 0.25), (64, 0, 0));
+    assert_eq!(palette.fade(3, 0.0), (0, 0, 0));
+
+    // fading towards a light background
+    let inverted = PRESETS[4].1;
+    assert_eq!(inverted.fade(1, 0.5), (128, 128, 128));
+}
@@ -0,0 +1,64 @@
+use crate::HIRES_SCREEN_HEIGHT;
+use crate::HIRES_SCREEN_WIDTH;
+
+// Persistence between the video buffer and the screen. Games erase and redraw
+// sprites with XOR, so a moving sprite is off for part of every frame and
+// flickers. Like the phosphor on the original displays, a pixel that switches
+// off here fades out over a few frames instead of going dark at once.
+pub struct Phosphor {
+    /// frames a pixel takes to fade out after switching off, 0 turns the effect off
+    decay_frames: u8,
+    /// for each pixel, the last value it was lit with and how many frames of
+    /// glow it has left. A lit pixel has decay_frames + 1.
+    glow: [[(u8, u8); HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT],
+}
+
+impl Phosphor {
+
+    pub fn new(decay_frames: u8) -> Phosphor {
+        Phosphor {
+            decay_frames: decay_frames,
+            glow: [[(0, 0); HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT],
+        }
+    }
+
+    /// Looks up a decay strength by name, or takes a number of frames, used for the command line
+    pub fn decay_from_name(name: &str) -> Option<u8> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" => Some(0),
+            "low" => Some(2),
+            "medium" => Some(4),
+            "high" => Some(8),
+            frames => frames.parse().ok().filter(|&frames| frames <= 60),
+        }
+    }
+
+    // Takes in the next frame. Call once per displayed frame, whether or not the
+    // video buffer changed, so fading pixels keep fading.
+    pub fn apply(&mut self, pixels: &[[u8; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT]) {
+        for (glow_row, row) in self.glow.iter_mut().zip(pixels.iter()) {
+            for (glow, &pixel) in glow_row.iter_mut().zip(row.iter()) {
+                if pixel != 0 {
+                    *glow = (pixel, self.decay_frames + 1);
+                } else if glow.1 > 0 {
+                    glow.1 -= 1;
+                }
+            }
+        }
+    }
+
+    /// The value a pixel shows and its brightness, 1.0 while lit down to 0.0 once faded
+    pub fn pixel(&self, x: usize, y: usize) -> (u8, f32) {
+        let (value, frames_left) = self.glow[y][x];
+        (value, frames_left as f32 / (self.decay_frames as f32 + 1.0))
+    }
+
+    /// Whether any pixel is still fading, and so the screen has to be redrawn next frame
+    pub fn is_fading(&self) -> bool {
+        self.glow.iter().flatten().any(|&(_, frames_left)| frames_left > 0 && frames_left <= self.decay_frames)
+    }
+}
+
+#[cfg(test)]
+#[path = "./phosphor_test.rs"]
+mod phosphor_test;
@@ -0,0 +1,67 @@
+use super::*;
+
+fn screen_with(x: usize, y: usize, value: u8) -> [[u8; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT] {
+    let mut pixels = [[0; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT];
+    pixels[y][x] = value;
+    pixels
+}
+
+#[test]
+fn test_lit_pixels_are_full_brightness() {
+    let mut phosphor = Phosphor::new(4);
+    phosphor.apply(&screen_with(3, 2, 1));
+    assert_eq!(phosphor.pixel(3, 2), (1, 1.0));
+    assert_eq!(phosphor.pixel(0, 0).1, 0.0);
+    assert!(!phosphor.is_fading());
+}
+
+#[test]
+fn test_pixels_fade_out() {
+    let mut phosphor = Phosphor::new(3);
+    phosphor.apply(&screen_with(5, 5, 2));
+
+    let blank = [[0; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT];
+    let mut brightness = Vec::new();
+    for _ in 0..4 {
+        phosphor.apply(&blank);
+        brightness.push(phosphor.pixel(5, 5));
+    }
+
+    assert_eq!(brightness, [(2, 0.75), (2, 0.5), (2, 0.25), (2, 0.0)]);
+    assert!(!phosphor.is_fading());
+}
+
+#[test]
+fn test_redrawn_pixel_stays_lit() {
+    // a sprite erased and redrawn in alternate frames never drops below the first step of the fade
+    let mut phosphor = Phosphor::new(4);
+    let lit = screen_with(1, 1, 1);
+    let blank = [[0; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT];
+
+    for _ in 0..10 {
+        phosphor.apply(&lit);
+        assert_eq!(phosphor.pixel(1, 1).1, 1.0);
+        phosphor.apply(&blank);
+        assert_eq!(phosphor.pixel(1, 1).1, 0.8);
+        assert!(phosphor.is_fading());
+    }
+}
+
+#[test]
+fn test_off_switches_instantly() {
+    let mut phosphor = Phosphor::new(0);
+    phosphor.apply(&screen_with(0, 0, 3));
+    assert_eq!(phosphor.pixel(0, 0), (3, 1.0));
+    phosphor.apply(&[[0; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT]);
+    assert_eq!(phosphor.pixel(0, 0).1, 0.0);
+    assert!(!phosphor.is_fading());
+}
+
+#[test]
+fn test_decay_from_name() {
+    assert_eq!(Phosphor::decay_from_name("off"), Some(0));
+    assert_eq!(Phosphor::decay_from_name("Medium"), Some(4));
+    assert_eq!(Phosphor::decay_from_name("12"), Some(12));
+    assert_eq!(Phosphor::decay_from_name("61"), None);
+    assert_eq!(Phosphor::decay_from_name("bright"), None);
+}
@@ -0,0 +1,79 @@
+// Chip-8 has a handful of instructions whose behavior changed between
+// interpreters. Reference:
+// https://github.com/Timendus/chip8-test-suite#quirks-test
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY into VX instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I pointing past the last register stored or loaded
+    pub load_store_increments_index: bool,
+    /// BNNN becomes BXNN, jumping to XNN plus the value of VX instead of V0
+    pub jump_uses_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0
+    pub logic_resets_vf: bool,
+    /// Sprites are clipped at the edge of the screen instead of wrapping around
+    pub clip_sprites: bool,
+    /// DXYN waits for the next vertical blank before drawing
+    pub display_wait: bool,
+    /// FX0A finishes when the key is released rather than when it's pressed
+    pub wait_key_release: bool,
+}
+
+impl Quirks {
+
+    /// The original interpreter on the COSMAC VIP
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_index: true,
+        jump_uses_vx: false,
+        logic_resets_vf: true,
+        clip_sprites: true,
+        display_wait: true,
+        wait_key_release: true,
+    };
+
+    /// CHIP-48 on the HP-48 calculators
+    pub const CHIP_48: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_index: false,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true,
+        display_wait: false,
+        wait_key_release: true,
+    };
+
+    /// SUPER-CHIP 1.1, built on top of CHIP-48
+    pub const SCHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_index: false,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true,
+        display_wait: false,
+        wait_key_release: true,
+    };
+
+    /// XO-CHIP as implemented by Octo
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_index: true,
+        jump_uses_vx: false,
+        logic_resets_vf: false,
+        clip_sprites: false,
+        display_wait: false,
+        wait_key_release: true,
+    };
+
+    /// Looks up a preset by name, used for picking a profile from the command line
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name.to_ascii_lowercase().as_str() {
+            "default" => Some(Quirks::default()),
+            "vip" | "cosmac-vip" => Some(Quirks::COSMAC_VIP),
+            "chip-48" | "chip48" => Some(Quirks::CHIP_48),
+            "schip" | "super-chip" => Some(Quirks::SCHIP),
+            "xo-chip" | "xochip" => Some(Quirks::XO_CHIP),
+            _ => None,
+        }
+    }
+}
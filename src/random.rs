@@ -0,0 +1,53 @@
+use rand;
+use rand::Rng;
+
+// Where CXNN gets its random numbers from. The whole state has to fit in a u64
+// so it can be saved and restored along with the rest of the cpu.
+pub trait RandomSource {
+    fn next_byte(&mut self) -> u8;
+    fn state(&self) -> u64;
+    fn set_state(&mut self, state: u64);
+}
+
+// xorshift64*, tiny and plenty random enough for games. Reference:
+// https://en.wikipedia.org/wiki/Xorshift#xorshift*
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+
+    /// Same seed, same sequence of numbers
+    pub fn from_seed(seed: u64) -> XorShift {
+        // xorshift gets stuck on 0, so spread the seed out with splitmix64 first
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        XorShift { state: if z == 0 { 1 } else { z } }
+    }
+
+    /// Seeded from the operating system, for when runs don't need to be repeatable
+    pub fn from_entropy() -> XorShift {
+        XorShift::from_seed(rand::thread_rng().gen())
+    }
+}
+
+impl RandomSource for XorShift {
+
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = if state == 0 { 1 } else { state };
+    }
+}
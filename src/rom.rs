@@ -1,32 +1,39 @@
-use std::io::Read;
+use std::io::{self, Read};
+
+/// Largest rom that fits between 0x200 and the end of XO-CHIP's 64kb of memory
+pub const MAX_ROM_SIZE: usize = 0x10000 - 0x200;
 
 /// Barrows heavily from https://github.com/starrhorne/chip8-rust/blob/master/src/drivers/cartridge_driver.rs
 /// Some small changes, but hey, this is a learning exercise.
 pub struct Rom {
-    /// memory, size defined as 0xFFF - 0x200
-    pub memory: [u8; 3584],
+    /// memory, size defined as 0xFFFF - 0x200
+    pub memory: [u8; MAX_ROM_SIZE],
     pub size: usize,
 } 
 
 impl Rom {
 
-        /// Loads rom data based on a filename (in this case from main args)
-        pub fn new(filename: &str) -> Rom {
-
-            let mut file = std::fs::File::open(filename).expect("File not found!");
-            //defined as 4096 - 512 bytes.
-            let mut buffer = [0u8; 3584];
-    
-            let rom_size = if let Ok(rom_size) = file.read(&mut buffer) {
-                rom_size
-            } else {
-                0
-            };
-    
+        /// Loads rom data based on a filename (in this case from main args),
+        /// anything past MAX_ROM_SIZE is dropped
+        pub fn new(filename: &str) -> io::Result<Rom> {
+
+            let mut file = std::fs::File::open(filename)?;
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+
+            Ok(Rom::from_bytes(&data))
+        }
+
+        /// Copies rom data already in memory, anything past MAX_ROM_SIZE is dropped
+        pub fn from_bytes(data: &[u8]) -> Rom {
+
+            let size = data.len().min(MAX_ROM_SIZE);
+            let mut buffer = [0u8; MAX_ROM_SIZE];
+            buffer[..size].copy_from_slice(&data[..size]);
+
             Rom {
                 memory: buffer,
-                size: rom_size,
+                size: size,
             }
-    
         }
 }
\ No newline at end of file
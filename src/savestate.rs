@@ -0,0 +1,198 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::quirks::Quirks;
+
+// Save states are a snapshot of everything the cpu needs to carry on running a
+// rom exactly where it left off. The format is a small binary one:
+//
+//     "CH8S"   magic
+//     u16      format version
+//     ...      cpu state, written and read in the same order by CPU::save_state
+//              and CPU::load_state
+//
+// Numbers are little endian. Anything that changes the layout has to bump
+// VERSION, older states are rejected rather than loaded wrong.
+
+pub const MAGIC: &[u8; 4] = b"CH8S";
+pub const VERSION: u16 = 2;
+
+// Why a save state couldn't be loaded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SaveStateError {
+    /// the data doesn't start with the magic header, so isn't a save state
+    NotASaveState,
+    /// the state was written by a different version of the format
+    UnsupportedVersion { found: u16, expected: u16 },
+    /// the data ends part way through the state
+    Truncated,
+    /// a value in the state is out of range, the file is corrupt
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveStateError::NotASaveState => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion { found, expected } => {
+                write!(f, "save state version {} is not supported (expected {})", found, expected)
+            },
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::Corrupt(what) => write!(f, "save state is corrupt: {}", what),
+        }
+    }
+}
+
+// Builds up a save state, starting with the header
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl Default for StateWriter {
+    fn default() -> StateWriter {
+        let mut writer = StateWriter { data: Vec::new() };
+        writer.bytes(MAGIC);
+        writer.u16(VERSION);
+        writer
+    }
+}
+
+impl StateWriter {
+
+    pub fn new() -> StateWriter {
+        StateWriter::default()
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+// Reads a save state back, checking the header first
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+
+    pub fn new(data: &'a [u8]) -> Result<StateReader<'a>, SaveStateError> {
+        if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Err(SaveStateError::NotASaveState);
+        }
+
+        let mut reader = StateReader { data: &data[MAGIC.len()..] };
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(SaveStateError::UnsupportedVersion { found: version, expected: VERSION });
+        }
+
+        Ok(reader)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Corrupt("flag is not 0 or 1")),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SaveStateError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SaveStateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn bytes(&mut self, length: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.data.len() < length {
+            return Err(SaveStateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    // Anything left over means the state was written by something else
+    pub fn finish(self) -> Result<(), SaveStateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(SaveStateError::Corrupt("trailing data"))
+        }
+    }
+}
+
+// Quirks are stored as one bit each, in the order they're declared
+pub fn quirks_to_bits(quirks: &Quirks) -> u8 {
+    [
+        quirks.shift_uses_vy,
+        quirks.load_store_increments_index,
+        quirks.jump_uses_vx,
+        quirks.logic_resets_vf,
+        quirks.clip_sprites,
+        quirks.display_wait,
+        quirks.wait_key_release,
+    ].iter().enumerate().fold(0, |bits, (i, &set)| bits | (set as u8) << i)
+}
+
+pub fn quirks_from_bits(bits: u8) -> Quirks {
+    let set = |i: u8| bits & (1 << i) != 0;
+    Quirks {
+        shift_uses_vy: set(0),
+        load_store_increments_index: set(1),
+        jump_uses_vx: set(2),
+        logic_resets_vf: set(3),
+        clip_sprites: set(4),
+        display_wait: set(5),
+        wait_key_release: set(6),
+    }
+}
+
+// Numbered save slots live next to the rom, game.ch8 saves slot 1 to game.state1
+pub fn slot_path(rom: &Path, slot: u8) -> PathBuf {
+    rom.with_extension(format!("state{}", slot))
+}
+
+#[cfg(test)]
+#[path = "./savestate_test.rs"]
+mod savestate_test;
@@ -0,0 +1,70 @@
+use super::*;
+
+#[test]
+fn test_header() {
+    let data = StateWriter::new().finish();
+    assert_eq!(data, [b'C', b'H', b'8', b'S', VERSION as u8, (VERSION >> 8) as u8]);
+    assert!(StateReader::new(&data).unwrap().finish().is_ok());
+}
+
+#[test]
+fn test_round_trip() {
+    let mut writer = StateWriter::new();
+    writer.u8(0x12);
+    writer.bool(true);
+    writer.u16(0x3456);
+    writer.u32(0x789A_BCDE);
+    writer.u64(0x0123_4567_89AB_CDEF);
+    writer.bytes(&[1, 2, 3]);
+    let data = writer.finish();
+
+    let mut reader = StateReader::new(&data).unwrap();
+    assert_eq!(reader.u8(), Ok(0x12));
+    assert_eq!(reader.bool(), Ok(true));
+    assert_eq!(reader.u16(), Ok(0x3456));
+    assert_eq!(reader.u32(), Ok(0x789A_BCDE));
+    assert_eq!(reader.u64(), Ok(0x0123_4567_89AB_CDEF));
+    assert_eq!(reader.bytes(3), Ok(&[1u8, 2, 3][..]));
+    assert!(reader.finish().is_ok());
+}
+
+#[test]
+fn test_rejects_bad_header() {
+    assert_eq!(StateReader::new(b"").err(), Some(SaveStateError::NotASaveState));
+    assert_eq!(StateReader::new(b"CH8X\x01\x00").err(), Some(SaveStateError::NotASaveState));
+    assert_eq!(StateReader::new(b"CH8S\x01").err(), Some(SaveStateError::Truncated));
+    assert_eq!(
+        StateReader::new(b"CH8S\x63\x00").err(),
+        Some(SaveStateError::UnsupportedVersion { found: 99, expected: VERSION })
+    );
+}
+
+#[test]
+fn test_rejects_truncated_and_trailing_data() {
+    let mut writer = StateWriter::new();
+    writer.u8(1);
+    let data = writer.finish();
+
+    let mut reader = StateReader::new(&data).unwrap();
+    assert_eq!(reader.u16(), Err(SaveStateError::Truncated));
+
+    let reader = StateReader::new(&data).unwrap();
+    assert_eq!(reader.finish(), Err(SaveStateError::Corrupt("trailing data")));
+
+    let mut reader = StateReader::new(b"CH8S\x02\x00\x02").unwrap();
+    assert!(reader.bool().is_err());
+}
+
+#[test]
+fn test_quirk_bits() {
+    for &quirks in [Quirks::default(), Quirks::COSMAC_VIP, Quirks::CHIP_48, Quirks::SCHIP, Quirks::XO_CHIP].iter() {
+        assert_eq!(quirks_from_bits(quirks_to_bits(&quirks)), quirks);
+    }
+    assert_eq!(quirks_to_bits(&Quirks::default()), 0);
+}
+
+#[test]
+fn test_slot_path() {
+    assert_eq!(slot_path(Path::new("roms/pong.ch8"), 1), PathBuf::from("roms/pong.state1"));
+    assert_eq!(slot_path(Path::new("pong"), 4), PathBuf::from("pong.state4"));
+}
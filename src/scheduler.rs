@@ -0,0 +1,45 @@
+/// Delay and sound timers always count down at 60hz
+pub const TIMER_HZ: u32 = 60;
+/// Instructions per second used when nothing else is asked for. Original hardware
+/// varied a lot per instruction, most roms are happy somewhere around here.
+pub const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 700;
+
+// Splits the instruction clock into 60hz frames of emulated time. Instruction
+// rates that don't divide evenly carry the leftover into the next frame, so
+// every second of emulated time runs exactly instructions_per_second instructions.
+pub struct Scheduler {
+    instructions_per_second: u32,
+    /// instructions owed from earlier frames, in 1/60ths of an instruction
+    remainder: u32,
+}
+
+impl Scheduler {
+
+    pub fn new(instructions_per_second: u32) -> Scheduler {
+        Scheduler {
+            instructions_per_second: instructions_per_second,
+            remainder: 0,
+        }
+    }
+
+    pub fn set_instructions_per_second(&mut self, instructions_per_second: u32) {
+        self.instructions_per_second = instructions_per_second;
+        self.remainder = 0;
+    }
+
+    // Leftover from earlier frames, kept in save states so a restored run stays in step
+    pub fn remainder(&self) -> u32 {
+        self.remainder
+    }
+
+    pub fn set_remainder(&mut self, remainder: u32) {
+        self.remainder = remainder % TIMER_HZ;
+    }
+
+    // Number of instructions to run during the next frame
+    pub fn instructions_for_frame(&mut self) -> u32 {
+        let total = self.instructions_per_second + self.remainder;
+        self.remainder = total % TIMER_HZ;
+        total / TIMER_HZ
+    }
+}
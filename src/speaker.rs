@@ -0,0 +1,49 @@
+use std::mem;
+
+use sdl2;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+
+use crate::audio::{AudioBackend, FrameQueue, ToneGenerator, ToneSettings};
+use crate::cpu::State;
+
+const SAMPLE_RATE: i32 = 48000;
+/// Frames of audio allowed to build up in the queue. If the emulator runs ahead
+/// of the sound card, frames are left out instead of the sound lagging behind.
+const MAX_QUEUED_FRAMES: u32 = 4;
+
+// Plays the buzzer through an SDL audio queue
+pub struct Speaker {
+    queue: AudioQueue<f32>,
+    frames: FrameQueue,
+}
+
+impl Speaker {
+
+    pub fn new(sdl_context: &sdl2::Sdl, settings: ToneSettings) -> Result<Speaker, String> {
+        let audio = sdl_context.audio()?;
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let queue: AudioQueue<f32> = audio.open_queue(None, &desired)?;
+        let sample_rate = queue.spec().freq as u32;
+        queue.resume();
+
+        Ok(Speaker {
+            queue: queue,
+            frames: FrameQueue::new(ToneGenerator::new(sample_rate, settings), MAX_QUEUED_FRAMES),
+        })
+    }
+}
+
+impl AudioBackend for Speaker {
+
+    fn play_frame(&mut self, state: &State) {
+        let queued = self.queue.size() as usize / mem::size_of::<f32>();
+        if let Some(samples) = self.frames.next_frame(state, queued) {
+            let _ = self.queue.queue_audio(samples);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::keymap::{KeyKind, Keymap};
+use crate::palette::Palette;
+use crate::SCREEN_HEIGHT;
+use crate::SCREEN_WIDTH;
+use crate::HIRES_SCREEN_HEIGHT;
+use crate::HIRES_SCREEN_WIDTH;
+
+// Drawing the screen with text and reading keys from a terminal, for playing
+// over SSH where there's no SDL. None of this touches the terminal itself, the
+// tty module does that.
+
+// Characters the screen is drawn with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Glyphs {
+    /// '▀' in two colors, each character is one pixel wide and two high
+    HalfBlock,
+    /// braille dots, each character is two pixels wide and four high but only
+    /// has one color
+    Braille,
+}
+
+impl Glyphs {
+
+    /// Looks up glyphs by name, "auto" is None and picks whichever fits
+    pub fn from_name(name: &str) -> Option<Option<Glyphs>> {
+        match name.to_ascii_lowercase().as_str() {
+            "auto" => Some(None),
+            "half-block" | "halfblock" => Some(Some(Glyphs::HalfBlock)),
+            "braille" => Some(Some(Glyphs::Braille)),
+            _ => None,
+        }
+    }
+
+    // Pixels covered by one character
+    fn cell_size(self) -> (usize, usize) {
+        match self {
+            Glyphs::HalfBlock => (1, 2),
+            Glyphs::Braille => (2, 4),
+        }
+    }
+}
+
+// Columns and rows the screen takes up
+pub fn screen_size(hires: bool, glyphs: Glyphs) -> (usize, usize) {
+    let (width, height) = if hires {
+        (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT)
+    } else {
+        (SCREEN_WIDTH, SCREEN_HEIGHT)
+    };
+    let (cell_width, cell_height) = glyphs.cell_size();
+    (width / cell_width, height / cell_height)
+}
+
+// Half blocks look better, braille is used when they don't fit in the terminal
+pub fn fitting_glyphs(hires: bool, columns: usize, rows: usize) -> Option<Glyphs> {
+    [Glyphs::HalfBlock, Glyphs::Braille].iter()
+        .cloned()
+        .find(|&glyphs| {
+            let (width, height) = screen_size(hires, glyphs);
+            width <= columns && height <= rows
+        })
+}
+
+// The screen as text with 24-bit color escapes, each row starting with a cursor
+// move so the top left corner lands at the 1-based column and row given.
+// Colors are only sent when they change, which keeps frames small over a slow
+// connection.
+pub fn render(pixels: &[[u8; HIRES_SCREEN_WIDTH]], hires: bool, palette: &Palette, glyphs: Glyphs,
+              column: usize, row: usize) -> String {
+
+    let (columns, rows) = screen_size(hires, glyphs);
+    let (cell_width, cell_height) = glyphs.cell_size();
+    let mut output = String::new();
+
+    for y in 0..rows {
+        let _ = write!(output, "\x1b[{};{}H", row + y, column);
+        let mut colors = None;
+
+        for x in 0..columns {
+            let cell = |dx: usize, dy: usize| pixels[y * cell_height + dy][x * cell_width + dx] & 0b11;
+
+            let (character, foreground, background) = match glyphs {
+                Glyphs::HalfBlock => ('▀', palette.color(cell(0, 0)), palette.color(cell(0, 1))),
+                Glyphs::Braille => {
+                    // dots are numbered down the left column then the right,
+                    // with the bottom row added later as bits 6 and 7
+                    const DOTS: [(usize, usize); 8] = [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2), (0, 3), (1, 3)];
+                    let mut bits = 0;
+                    let mut counts = [0; 4];
+                    for (bit, &(dx, dy)) in DOTS.iter().enumerate() {
+                        let pixel = cell(dx, dy);
+                        if pixel != 0 {
+                            bits |= 1 << bit;
+                            counts[pixel as usize] += 1;
+                        }
+                    }
+                    // one color per character, the plane with the most dots lit wins
+                    let pixel = (1..4).rev().max_by_key(|&pixel| counts[pixel]).unwrap_or(1);
+                    let character = std::char::from_u32(0x2800 + bits).unwrap_or(' ');
+                    (character, palette.color(pixel as u8), palette.color(0))
+                },
+            };
+
+            if colors != Some((foreground, background)) {
+                let _ = write!(output, "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                               foreground.0, foreground.1, foreground.2,
+                               background.0, background.1, background.2);
+                colors = Some((foreground, background));
+            }
+            output.push(character);
+        }
+    }
+    output.push_str("\x1b[0m");
+
+    output
+}
+
+// A key read from the terminal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TermKey {
+    /// a key that types something, letters are lower case
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    /// escape on its own, not the start of a longer sequence
+    Escape,
+    /// Ctrl+C, which raw mode delivers as a key instead of a signal
+    Interrupt,
+}
+
+// Splits what the terminal sent into keys. Arrow keys arrive as escape
+// sequences, any other sequence (function keys, mouse reports) is dropped.
+pub fn parse_keys(bytes: &[u8]) -> Vec<TermKey> {
+
+    let text = String::from_utf8_lossy(bytes);
+    let mut characters = text.chars().peekable();
+    let mut keys = Vec::new();
+
+    while let Some(character) = characters.next() {
+        let key = match character {
+            '\x03' => TermKey::Interrupt,
+            '\x1b' => match characters.peek() {
+                None => TermKey::Escape,
+                Some(&'[') | Some(&'O') => {
+                    characters.next();
+                    // parameters, then a final character from '@' to '~'
+                    let mut last = None;
+                    for character in characters.by_ref() {
+                        if ('@'..='~').contains(&character) {
+                            last = Some(character);
+                            break;
+                        }
+                    }
+                    match last {
+                        Some('A') => TermKey::Up,
+                        Some('B') => TermKey::Down,
+                        Some('C') => TermKey::Right,
+                        Some('D') => TermKey::Left,
+                        _ => continue,
+                    }
+                },
+                // Alt+key, the key on its own is close enough
+                Some(_) => continue,
+            },
+            '\n' => TermKey::Char('\r'),
+            character => TermKey::Char(character.to_ascii_lowercase()),
+        };
+        keys.push(key);
+    }
+
+    keys
+}
+
+// The keymap's bindings that a terminal can tell apart. Terminals only see what
+// a key types, so keycodes and scancodes are both matched by character, and
+// keys that don't type anything, and controller bindings, are left out.
+pub fn terminal_bindings(keymap: &Keymap) -> Vec<(TermKey, u8)> {
+    keymap.bindings.iter()
+        .filter(|binding| binding.kind != KeyKind::Pad)
+        .filter_map(|binding| {
+            let name = binding.name.strip_prefix("Keypad ").unwrap_or(&binding.name);
+            let key = match name {
+                "Up" => TermKey::Up,
+                "Down" => TermKey::Down,
+                "Left" => TermKey::Left,
+                "Right" => TermKey::Right,
+                "Space" => TermKey::Char(' '),
+                "Return" | "Enter" => TermKey::Char('\r'),
+                "Tab" => TermKey::Char('\t'),
+                _ => {
+                    let mut characters = name.chars();
+                    match (characters.next(), characters.next()) {
+                        (Some(character), None) => TermKey::Char(character.to_ascii_lowercase()),
+                        _ => return None,
+                    }
+                },
+            };
+            Some((key, binding.key))
+        })
+        .collect()
+}
+
+// Terminals only send key presses, never releases, so a key counts as held for
+// a while after each press. Holding a key down keeps it held through the
+// terminal's key repeat, apart from the pause before repeating starts.
+pub struct KeyHold {
+    hold: Duration,
+    /// when each chip-8 key is let go, None if it's up
+    release_at: [Option<Instant>; 16],
+}
+
+impl KeyHold {
+
+    pub fn new(hold: Duration) -> KeyHold {
+        KeyHold {
+            hold: hold,
+            release_at: [None; 16],
+        }
+    }
+
+    pub fn press(&mut self, key: u8, now: Instant) {
+        self.release_at[key as usize & 0x0F] = Some(now + self.hold);
+    }
+
+    /// Keys held down at the given time
+    pub fn keypad(&mut self, now: Instant) -> [bool; 16] {
+        let mut keypad = [false; 16];
+        for (down, release_at) in keypad.iter_mut().zip(self.release_at.iter_mut()) {
+            if let Some(time) = *release_at {
+                if now >= time {
+                    *release_at = None;
+                }
+            }
+            *down = release_at.is_some();
+        }
+        keypad
+    }
+}
+
+#[cfg(test)]
+#[path = "./terminal_test.rs"]
+mod terminal_test;
@@ -0,0 +1,114 @@
+use super::*;
+
+fn screen(lit: &[(usize, usize, u8)]) -> [[u8; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT] {
+    let mut pixels = [[0; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT];
+    for &(x, y, pixel) in lit {
+        pixels[y][x] = pixel;
+    }
+    pixels
+}
+
+// The characters drawn, with the escape sequences taken out
+fn characters(text: &str) -> String {
+    let mut output = String::new();
+    let mut in_escape = false;
+    for character in text.chars() {
+        match character {
+            '\x1b' => in_escape = true,
+            'H' | 'm' if in_escape => in_escape = false,
+            _ if in_escape => (),
+            _ => output.push(character),
+        }
+    }
+    output
+}
+
+#[test]
+fn test_glyphs_from_name() {
+    assert_eq!(Glyphs::from_name("auto"), Some(None));
+    assert_eq!(Glyphs::from_name("Half-Block"), Some(Some(Glyphs::HalfBlock)));
+    assert_eq!(Glyphs::from_name("braille"), Some(Some(Glyphs::Braille)));
+    assert_eq!(Glyphs::from_name("ascii"), None);
+}
+
+#[test]
+fn test_screen_size_and_fit() {
+    assert_eq!(screen_size(false, Glyphs::HalfBlock), (64, 16));
+    assert_eq!(screen_size(true, Glyphs::HalfBlock), (128, 32));
+    assert_eq!(screen_size(true, Glyphs::Braille), (64, 16));
+
+    assert_eq!(fitting_glyphs(false, 80, 23), Some(Glyphs::HalfBlock));
+    assert_eq!(fitting_glyphs(true, 80, 23), Some(Glyphs::Braille));
+    assert_eq!(fitting_glyphs(true, 60, 23), None);
+}
+
+#[test]
+fn test_render_half_blocks() {
+    let pixels = screen(&[(0, 1, 1), (63, 31, 2)]);
+    let palette = Palette::parse("#000000,#ffffff,#ff0000,#00ff00").unwrap();
+    let text = render(&pixels, false, &palette, Glyphs::HalfBlock, 3, 2);
+
+    assert!(text.starts_with("\x1b[2;3H\x1b[38;2;0;0;0;48;2;255;255;255m▀\x1b[38;2;0;0;0;48;2;0;0;0m▀▀"));
+    assert!(text.contains("\x1b[17;3H"));
+    assert!(text.ends_with("\x1b[38;2;0;0;0;48;2;255;0;0m▀\x1b[0m"));
+    assert_eq!(characters(&text).chars().count(), 64 * 16);
+}
+
+#[test]
+fn test_render_braille() {
+    // the top left character has its left column lit, and one dot from plane 2
+    let pixels = screen(&[(0, 0, 1), (0, 1, 1), (0, 2, 1), (0, 3, 1), (1, 3, 2)]);
+    let text = render(&pixels, true, &Palette::default(), Glyphs::Braille, 1, 1);
+
+    let drawn: Vec<char> = characters(&text).chars().collect();
+    assert_eq!(drawn.len(), 64 * 16);
+    assert_eq!(drawn[0], '\u{28C7}');
+    assert_eq!(drawn[1], '\u{2800}');
+    let plane_1 = Palette::default().color(1);
+    assert!(text.starts_with(&format!("\x1b[1;1H\x1b[38;2;{};{};{};", plane_1.0, plane_1.1, plane_1.2)));
+}
+
+#[test]
+fn test_parse_keys() {
+    assert_eq!(parse_keys(b"qW 5"), [TermKey::Char('q'), TermKey::Char('w'), TermKey::Char(' '), TermKey::Char('5')]);
+    assert_eq!(parse_keys(b"\x1b[A\x1bOD\x1b[1;5C"), [TermKey::Up, TermKey::Left, TermKey::Right]);
+    assert_eq!(parse_keys(b"\x1b[15~a"), [TermKey::Char('a')]);
+    assert_eq!(parse_keys(b"\x1b"), [TermKey::Escape]);
+    assert_eq!(parse_keys(b"\x03\r\n"), [TermKey::Interrupt, TermKey::Char('\r'), TermKey::Char('\r')]);
+}
+
+#[test]
+fn test_terminal_bindings() {
+    let mut keymap = Keymap::preset("numpad").unwrap();
+    keymap.apply("5 = Space, Caps Lock\n2 = Up").unwrap();
+    let bindings = terminal_bindings(&keymap);
+
+    assert!(bindings.contains(&(TermKey::Char('8'), 0x2)));
+    assert!(bindings.contains(&(TermKey::Char('\r'), 0xB)));
+    assert!(bindings.contains(&(TermKey::Char(' '), 0x5)));
+    assert!(bindings.contains(&(TermKey::Up, 0x2)));
+    // controller bindings and keys that don't type anything are dropped
+    assert_eq!(bindings.len(), 16 + 2);
+
+    let azerty = terminal_bindings(&Keymap::preset("azerty").unwrap());
+    assert!(azerty.contains(&(TermKey::Char('1'), 0x1)));
+    assert!(azerty.contains(&(TermKey::Char('z'), 0x5)));
+}
+
+#[test]
+fn test_key_hold() {
+    let start = Instant::now();
+    let mut keys = KeyHold::new(Duration::from_millis(100));
+    assert_eq!(keys.keypad(start), [false; 16]);
+
+    keys.press(0xA, start);
+    assert!(keys.keypad(start + Duration::from_millis(99))[0xA]);
+    assert!(!keys.keypad(start + Duration::from_millis(100))[0xA]);
+
+    // key repeat keeps a key held
+    keys.press(0x3, start);
+    keys.press(0x3, start + Duration::from_millis(80));
+    let keypad = keys.keypad(start + Duration::from_millis(150));
+    assert!(keypad[0x3]);
+    assert_eq!(keypad.iter().filter(|&&down| down).count(), 1);
+}
@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::instruction::Instruction;
+
+// A log of every instruction the cpu runs, for finding where two runs of a rom
+// go different ways. The cpu only keeps a tracer when tracing is on, and only
+// looks at registers for instructions the filter lets through, so tracing costs
+// a single check per instruction when it's off.
+
+// How each traced instruction is written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat {
+    /// one aligned line per instruction
+    Text,
+    /// one JSON object per line
+    Json,
+}
+
+impl TraceFormat {
+
+    pub fn from_name(name: &str) -> Option<TraceFormat> {
+        match name {
+            "text" => Some(TraceFormat::Text),
+            "json" => Some(TraceFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+// Which instructions are traced. The range and opcode classes pick
+// instructions, then first and last cut down how many of those are kept.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceFilter {
+    /// lowest and highest address traced, inclusive
+    pub range: Option<(u16, u16)>,
+    /// one bit per first nibble of the opcode, 0x8000 is every 8XYN instruction
+    pub classes: u16,
+    /// only the first this many instructions
+    pub first: Option<u64>,
+    /// only the last this many, written when the trace is finished
+    pub last: Option<usize>,
+}
+
+impl Default for TraceFilter {
+    fn default() -> TraceFilter {
+        TraceFilter {
+            range: None,
+            classes: 0xFFFF,
+            first: None,
+            last: None,
+        }
+    }
+}
+
+impl TraceFilter {
+
+    /// Parses an address range like '200-2ff', in hex
+    pub fn parse_range(text: &str) -> Result<(u16, u16), String> {
+        let invalid = || format!("invalid address range '{}'", text);
+        let mut parts = text.splitn(2, '-');
+        let mut address = || {
+            let part = parts.next().ok_or_else(invalid)?.trim();
+            let part = part.strip_prefix("0x").unwrap_or(part);
+            u16::from_str_radix(part, 16).map_err(|_| invalid())
+        };
+        let (low, high) = (address()?, address()?);
+        if low > high {
+            return Err(invalid());
+        }
+        Ok((low, high))
+    }
+
+    /// Parses opcode classes given as first nibbles, like '8,d,f'
+    pub fn parse_classes(text: &str) -> Result<u16, String> {
+        text.split(',').try_fold(0, |classes, class| {
+            let class = class.trim().trim_end_matches(['x', 'X']);
+            match u8::from_str_radix(class, 16) {
+                Ok(nibble) if nibble < 16 => Ok(classes | 1 << nibble),
+                _ => Err(format!("invalid opcode class '{}'", class)),
+            }
+        })
+    }
+}
+
+// What the cpu looked like on one side of an instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Snapshot {
+    pub registers: [u8; 16],
+    pub index: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+pub struct Tracer {
+    format: TraceFormat,
+    filter: TraceFilter,
+    output: Box<dyn Write>,
+    /// instructions traced so far, counting ones held back for the last N
+    traced: u64,
+    /// held back when only the last N are wanted
+    recent: VecDeque<String>,
+    /// the first write that failed, nothing more is written after it
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+
+    pub fn new(format: TraceFormat, filter: TraceFilter, output: Box<dyn Write>) -> Tracer {
+        Tracer {
+            format: format,
+            filter: filter,
+            output: output,
+            traced: 0,
+            recent: VecDeque::new(),
+            error: None,
+        }
+    }
+
+    /// Whether the instruction at pc is traced, checked before the cpu takes a snapshot
+    pub fn wants(&self, pc: u16, opcode: u16) -> bool {
+        let in_range = match self.filter.range {
+            Some((low, high)) => pc >= low && pc <= high,
+            None => true,
+        };
+        let in_first = match self.filter.first {
+            Some(first) => self.traced < first,
+            None => true,
+        };
+        in_range && in_first && self.filter.classes & (1 << (opcode >> 12)) != 0 && self.error.is_none()
+    }
+
+    /// Logs an instruction. count is the cpu's instruction count, long is the
+    /// word after the opcode, which is part of the instruction for F000.
+    pub fn record(&mut self, count: u64, pc: u16, opcode: u16, long: u16, before: &Snapshot, after: &Snapshot) {
+
+        self.traced += 1;
+
+        let instruction = Instruction::decode(opcode).map(|instruction| match instruction {
+            Instruction::LoadLongIndex => format!("LD I, LONG {:#06x}", long),
+            _ => instruction.to_string(),
+        });
+        let changes = Tracer::changes(before, after);
+
+        let mut line = String::new();
+        match self.format {
+            TraceFormat::Text => {
+                let _ = write!(line, "{:>10} {:#06x}: {:04x}  {:<20}", count, pc, opcode,
+                               instruction.as_deref().unwrap_or("???"));
+                for (name, old, new, width) in changes {
+                    let _ = write!(line, " {} {:0w$x}->{:0w$x}", name, old, new, w = width);
+                }
+                line = String::from(line.trim_end());
+            },
+            TraceFormat::Json => {
+                let _ = write!(line, "{{\"count\":{},\"pc\":{},\"opcode\":{},\"instruction\":", count, pc, opcode);
+                match instruction {
+                    Some(instruction) => { let _ = write!(line, "\"{}\"", instruction); },
+                    None => line.push_str("null"),
+                }
+                line.push_str(",\"changes\":{");
+                for (number, (name, old, new, _)) in changes.into_iter().enumerate() {
+                    let separator = if number == 0 { "" } else { "," };
+                    let _ = write!(line, "{}\"{}\":[{},{}]", separator, name, old, new);
+                }
+                line.push_str("}}");
+            },
+        }
+
+        match self.filter.last {
+            Some(last) => {
+                if self.recent.len() == last {
+                    self.recent.pop_front();
+                }
+                if last > 0 {
+                    self.recent.push_back(line);
+                }
+            },
+            None => self.write_line(&line),
+        }
+    }
+
+    // Registers that differ, each with its old and new value and how many hex
+    // digits it's written with
+    fn changes(before: &Snapshot, after: &Snapshot) -> Vec<(String, u16, u16, usize)> {
+        let mut changes = Vec::new();
+        for (x, (&old, &new)) in before.registers.iter().zip(after.registers.iter()).enumerate() {
+            if old != new {
+                changes.push((format!("V{:X}", x), old as u16, new as u16, 2));
+            }
+        }
+        if before.index != after.index {
+            changes.push((String::from("I"), before.index, after.index, 4));
+        }
+        if before.delay_timer != after.delay_timer {
+            changes.push((String::from("DT"), before.delay_timer as u16, after.delay_timer as u16, 2));
+        }
+        if before.sound_timer != after.sound_timer {
+            changes.push((String::from("ST"), before.sound_timer as u16, after.sound_timer as u16, 2));
+        }
+        changes
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(error) = writeln!(self.output, "{}", line) {
+            self.error = Some(error);
+        }
+    }
+
+    /// Writes out the last N instructions if those were asked for and flushes
+    /// the output. Returns the first error writing the trace hit.
+    pub fn finish(&mut self) -> io::Result<()> {
+        while let Some(line) = self.recent.pop_front() {
+            self.write_line(&line);
+        }
+        if self.error.is_none() {
+            if let Err(error) = self.output.flush() {
+                self.error = Some(error);
+            }
+        }
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./trace_test.rs"]
+mod trace_test;
@@ -0,0 +1,98 @@
+use super::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::cpu::CPU;
+use crate::quirks::Quirks;
+
+// Output the test can still read after handing it to a tracer
+#[derive(Clone, Default)]
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedOutput {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8_lossy(&self.0.borrow()).lines().map(String::from).collect()
+    }
+}
+
+// Runs a short program under a tracer and returns what it wrote
+fn trace(format: TraceFormat, filter: TraceFilter, steps: usize) -> Vec<String> {
+    let mut chip = CPU::new(Quirks::default());
+    let program = [
+        0x60, 0x05, // 0x200: LD V0, 0x05
+        0xA3, 0x00, // 0x202: LD I, 0x300
+        0x80, 0x04, // 0x204: ADD V0, V0
+        0xF0, 0x15, // 0x206: LD DT, V0
+        0xF0, 0x00, 0x12, 0x34, // 0x208: LD I, LONG 0x1234
+        0x12, 0x00, // 0x20C: JP 0x200
+    ];
+    chip.memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+
+    let output = SharedOutput::default();
+    chip.set_tracer(Tracer::new(format, filter, Box::new(output.clone())));
+    for _ in 0..steps {
+        chip.step().unwrap();
+    }
+    chip.take_tracer().unwrap().finish().unwrap();
+    output.lines()
+}
+
+#[test]
+fn test_text() {
+    let lines = trace(TraceFormat::Text, TraceFilter::default(), 6);
+    assert_eq!(lines, [
+        "         1 0x0200: 6005  LD V0, 0x05          V0 00->05",
+        "         2 0x0202: a300  LD I, 0x300          I 0000->0300",
+        "         3 0x0204: 8004  ADD V0, V0           V0 05->0a",
+        "         4 0x0206: f015  LD DT, V0            DT 00->0a",
+        "         5 0x0208: f000  LD I, LONG 0x1234    I 0300->1234",
+        "         6 0x020c: 1200  JP 0x200",
+    ]);
+}
+
+#[test]
+fn test_json() {
+    let lines = trace(TraceFormat::Json, TraceFilter::default(), 3);
+    assert_eq!(lines[0], "{\"count\":1,\"pc\":512,\"opcode\":24581,\"instruction\":\"LD V0, 0x05\",\"changes\":{\"V0\":[0,5]}}");
+    assert_eq!(lines[2], "{\"count\":3,\"pc\":516,\"opcode\":32772,\"instruction\":\"ADD V0, V0\",\"changes\":{\"V0\":[5,10]}}");
+}
+
+#[test]
+fn test_filters() {
+    let only = |filter: TraceFilter| -> Vec<String> {
+        trace(TraceFormat::Text, filter, 12).iter().map(|line| String::from(&line[11..17])).collect()
+    };
+
+    let range = TraceFilter { range: Some((0x204, 0x206)), ..TraceFilter::default() };
+    assert_eq!(only(range), ["0x0204", "0x0206", "0x0204", "0x0206"]);
+
+    let classes = TraceFilter { classes: TraceFilter::parse_classes("6,1").unwrap(), ..TraceFilter::default() };
+    assert_eq!(only(classes), ["0x0200", "0x020c", "0x0200", "0x020c"]);
+
+    let first = TraceFilter { first: Some(2), ..TraceFilter::default() };
+    assert_eq!(only(first), ["0x0200", "0x0202"]);
+
+    let last = TraceFilter { last: Some(3), ..TraceFilter::default() };
+    assert_eq!(only(last), ["0x0206", "0x0208", "0x020c"]);
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(TraceFilter::parse_range("200-2ff"), Ok((0x200, 0x2FF)));
+    assert_eq!(TraceFilter::parse_range("0x300-0x300"), Ok((0x300, 0x300)));
+    assert_eq!(TraceFilter::parse_range("300-200"), Err(String::from("invalid address range '300-200'")));
+    assert_eq!(TraceFilter::parse_range("300"), Err(String::from("invalid address range '300'")));
+    assert_eq!(TraceFilter::parse_classes("8, dx,F"), Ok(1 << 0x8 | 1 << 0xD | 1 << 0xF));
+    assert_eq!(TraceFilter::parse_classes("g"), Err(String::from("invalid opcode class 'g'")));
+}
@@ -0,0 +1,79 @@
+use std::io::{self, Write};
+use std::mem;
+
+use libc;
+
+// The terminal the emulator is running in, switched into raw mode so keys
+// arrive as they're pressed without echoing. The terminal is put back the
+// way it was when this is dropped, including after a panic.
+pub struct Tty {
+    original: libc::termios,
+}
+
+impl Tty {
+
+    // Switches to raw mode and the alternate screen, with the cursor hidden
+    pub fn open() -> io::Result<Tty> {
+
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        // reads return straight away with whatever has been typed, maybe nothing
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut tty = Tty { original: original };
+        tty.write("\x1b[?1049h\x1b[?25l\x1b[2J")?;
+        Ok(tty)
+    }
+
+    /// Columns and rows of the terminal, None if it can't be told
+    pub fn size(&self) -> Option<(usize, usize)> {
+        let mut size: libc::winsize = unsafe { mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_col == 0 {
+            return None;
+        }
+        Some((size.ws_col as usize, size.ws_row as usize))
+    }
+
+    // Everything typed since the last read, without waiting
+    pub fn read(&mut self) -> io::Result<Vec<u8>> {
+        let mut input = Vec::new();
+        let mut buffer = [0u8; 256];
+        loop {
+            let count = unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+            if count < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+            if count == 0 {
+                return Ok(input);
+            }
+            input.extend_from_slice(&buffer[..count as usize]);
+        }
+    }
+
+    pub fn write(&mut self, text: &str) -> io::Result<()> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+impl Drop for Tty {
+    fn drop(&mut self) {
+        let _ = self.write("\x1b[0m\x1b[?25h\x1b[?1049l");
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
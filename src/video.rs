@@ -1,60 +1,167 @@
 use sdl2;
 use sdl2::pixels;
+use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::{FullscreenType, Window};
 
+use crate::palette::Palette;
+use crate::phosphor::Phosphor;
 use crate::SCREEN_HEIGHT;
 use crate::SCREEN_WIDTH;
-const SCALE_FACTOR: u32 = 20;
+use crate::HIRES_SCREEN_HEIGHT;
+use crate::HIRES_SCREEN_WIDTH;
+
+/// Color of the bars around the screen when the window's shape doesn't match it
+const LETTERBOX_COLOR: pixels::Color = pixels::Color::RGB(0, 0, 0);
 
 
 pub struct  VideoWindow {
     canvas: Canvas<Window>,
+    /// the screen, uploaded once per frame and scaled up by the renderer. It's
+    /// sized for hires mode, lowres only uses the top left quarter. SDL frees it
+    /// along with the canvas.
+    texture: Texture,
+    /// RGB bytes waiting to be uploaded to the texture, reused between frames
+    rgb: Vec<u8>,
+    /// SUPER-CHIP 128x64 mode, decides how much of the texture is shown
+    hires: bool,
+    /// colors for each combination of XO-CHIP planes
+    palette: Palette,
+    /// only scale the screen by whole numbers, so every pixel is the same size
+    integer_scale: bool,
+    /// fades pixels out over a few frames to hide XOR flicker
+    phosphor: Phosphor,
 }
 
 impl VideoWindow {
 
-    pub fn new(sdl_context: &sdl2::Sdl) -> VideoWindow {
+    // Opens a resizable window, scale is the size of a low resolution pixel
+    pub fn new(sdl_context: &sdl2::Sdl, title: &str, scale: u32, integer_scale: bool, palette: Palette) -> VideoWindow {
         let video = sdl_context.video().unwrap();
-        let window = video.window("Test", SCREEN_WIDTH as u32  * SCALE_FACTOR, SCREEN_HEIGHT as u32 * SCALE_FACTOR)
+        let window = video.window(title, SCREEN_WIDTH as u32 * scale, SCREEN_HEIGHT as u32 * scale)
             .position_centered()
+            .resizable()
             .opengl()
             .build()
             .unwrap();
 
+        // scale the texture up with nearest neighbour, so pixels stay sharp
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "0");
         let mut canvas = window.into_canvas().build().unwrap();
+        let texture = canvas.texture_creator()
+            .create_texture_streaming(PixelFormatEnum::RGB24, HIRES_SCREEN_WIDTH as u32, HIRES_SCREEN_HEIGHT as u32)
+            .unwrap();
 
-        canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
+        canvas.set_draw_color(LETTERBOX_COLOR);
         canvas.clear();
         canvas.present();
 
         VideoWindow {
             canvas: canvas,
+            texture: texture,
+            rgb: vec![0; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT * 3],
+            hires: false,
+            palette: palette,
+            integer_scale: integer_scale,
+            phosphor: Phosphor::new(0),
+        }
+    }
+
+    /// Takes effect from the next update
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Frames a pixel takes to fade out after switching off, 0 switches it off at once
+    pub fn set_persistence(&mut self, decay_frames: u8) {
+        self.phosphor = Phosphor::new(decay_frames);
+    }
+
+    /// Whether pixels are still fading out, and need updating even if the rom drew nothing
+    pub fn is_fading(&self) -> bool {
+        self.phosphor.is_fading()
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        let _ = self.canvas.window_mut().set_title(title);
+    }
+
+    // Switches between a window and borderless fullscreen at the desktop's resolution
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        if let Err(message) = window.set_fullscreen(fullscreen) {
+            eprintln!("couldn't change fullscreen: {}", message);
         }
     }
 
-    pub fn draw(&mut self, pixels: &[[u8; SCREEN_WIDTH]; SCREEN_HEIGHT]) {
-        for (y, row) in pixels.iter().enumerate() {
-            for (x, &col) in row.iter().enumerate() {
-                let x = (x as u32) * SCALE_FACTOR;
-                let y = (y as u32) * SCALE_FACTOR;
+    // Uploads a new frame to the texture, colored with the palette and faded by
+    // the phosphor. Nothing is shown until the next present.
+    pub fn update(&mut self, pixels: &[[u8; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT], hires: bool) {
+        let (width, height) = VideoWindow::screen_size(hires);
 
-                self.canvas.set_draw_color(VideoWindow::color(col));
-                let _ = self.canvas
-                    .fill_rect(Rect::new(x as i32, y as i32, SCALE_FACTOR, SCALE_FACTOR));
+        self.phosphor.apply(pixels);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (value, brightness) = self.phosphor.pixel(x, y);
+                let (r, g, b) = self.palette.fade(value, brightness);
+                let offset = (y * width + x) * 3;
+                self.rgb[offset..offset + 3].copy_from_slice(&[r, g, b]);
             }
         }
+
+        let area = Rect::new(0, 0, width as u32, height as u32);
+        if let Err(error) = self.texture.update(area, &self.rgb[..width * height * 3], width * 3) {
+            eprintln!("couldn't update the screen: {}", error);
+        }
+        self.hires = hires;
+    }
+
+    // Shows the last frame uploaded, as large as fits in the window while keeping
+    // its shape and centered between bars. Called once per host frame, however
+    // many times the rom drew.
+    pub fn present(&mut self) {
+        let (width, height) = VideoWindow::screen_size(self.hires);
+
+        let (output_width, output_height) = self.canvas.output_size().unwrap_or((0, 0));
+        let screen = VideoWindow::layout(output_width, output_height, self.integer_scale);
+
+        self.canvas.set_draw_color(LETTERBOX_COLOR);
+        self.canvas.clear();
+        let _ = self.canvas.copy(&self.texture, Rect::new(0, 0, width as u32, height as u32), screen);
         self.canvas.present();
     }
 
-    fn color(value: u8) -> pixels::Color {
-        if value == 0 {
-            pixels::Color::RGB(0, 0, 0)
+    // Width and height of the screen in chip-8 pixels
+    fn screen_size(hires: bool) -> (usize, usize) {
+        if hires {
+            (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT)
         } else {
-            pixels::Color::RGB(0, 250, 0)
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
         }
     }
-    
 
-}
\ No newline at end of file
+    // Where the screen goes in a window of the given size. Integer scaling rounds
+    // the size of a hires pixel down to a whole number, never below 1.
+    fn layout(output_width: u32, output_height: u32, integer_scale: bool) -> Rect {
+        let fit = (output_width as f32 / HIRES_SCREEN_WIDTH as f32)
+            .min(output_height as f32 / HIRES_SCREEN_HEIGHT as f32);
+        let unit = if integer_scale { fit.floor().max(1.0) } else { fit };
+
+        let used_width = (unit * HIRES_SCREEN_WIDTH as f32) as u32;
+        let used_height = (unit * HIRES_SCREEN_HEIGHT as f32) as u32;
+
+        Rect::new(
+            (output_width.saturating_sub(used_width) / 2) as i32,
+            (output_height.saturating_sub(used_height) / 2) as i32,
+            used_width,
+            used_height,
+        )
+    }
+
+}
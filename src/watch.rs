@@ -0,0 +1,268 @@
+use std::fmt;
+
+// Watchpoints, for finding what changed a byte of memory or a register. The
+// cpu checks memory watchpoints on every byte an instruction reads or writes
+// through I, and register watchpoints on every register an instruction writes,
+// even when it writes the value already there. Timers counting down at the end
+// of a frame don't count, only instructions do. With no watchpoints set
+// neither check is made.
+//
+// A watchpoint is written as a target, then any of an access, a condition on
+// the value and 'pause', for example
+//
+//     300-30f write == 0 pause
+//     v3 > 10
+//     2f0 rw
+//
+// Addresses and values are hex. Memory is watched for writes unless read or rw
+// says otherwise, registers (v0-vf, i, dt and st) only for writes.
+
+// What a watchpoint watches
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchTarget {
+    /// first and last address, inclusive
+    Memory(u16, u16),
+    V(u8),
+    Index,
+    DelayTimer,
+    SoundTimer,
+}
+
+// Reads, writes or both. Hits are always one or the other.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+// How the value is compared in a watch condition
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+
+    fn from_symbol(symbol: &str) -> Option<Comparison> {
+        match symbol {
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            ">" => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            _ => None,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+
+    fn holds(self, value: u16, operand: u16) -> bool {
+        match self {
+            Comparison::Equal => value == operand,
+            Comparison::NotEqual => value != operand,
+            Comparison::Less => value < operand,
+            Comparison::LessOrEqual => value <= operand,
+            Comparison::Greater => value > operand,
+            Comparison::GreaterOrEqual => value >= operand,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub target: WatchTarget,
+    pub access: Access,
+    /// only hit when the value read or written compares true
+    pub condition: Option<(Comparison, u16)>,
+    /// stop running when hit
+    pub pause: bool,
+}
+
+impl Watchpoint {
+
+    /// Parses a watchpoint written as described at the top of this module
+    pub fn parse(text: &str) -> Result<Watchpoint, String> {
+
+        let mut words = text.split_whitespace();
+        let target = match words.next() {
+            Some(target) => Watchpoint::parse_target(target)?,
+            None => return Err(String::from("watch needs an address or register")),
+        };
+
+        let mut watchpoint = Watchpoint {
+            target: target,
+            access: Access::Write,
+            condition: None,
+            pause: false,
+        };
+
+        while let Some(word) = words.next() {
+            match word.to_ascii_lowercase().as_str() {
+                "read" | "r" => watchpoint.access = Access::Read,
+                "write" | "w" => watchpoint.access = Access::Write,
+                "rw" => watchpoint.access = Access::ReadWrite,
+                "pause" => watchpoint.pause = true,
+                symbol => match Comparison::from_symbol(symbol) {
+                    Some(comparison) => {
+                        let value = words.next().ok_or_else(|| format!("{} needs a value to compare with", symbol))?;
+                        watchpoint.condition = Some((comparison, hex(value)?));
+                    },
+                    None => return Err(format!("unknown watch option '{}'", word)),
+                },
+            }
+        }
+
+        if watchpoint.access != Access::Write && !watchpoint.is_memory() {
+            return Err(String::from("registers can only be watched for writes"));
+        }
+
+        Ok(watchpoint)
+    }
+
+    // An address, a range of them, or a register
+    fn parse_target(text: &str) -> Result<WatchTarget, String> {
+        let lower = text.to_ascii_lowercase();
+        let target = match lower.as_str() {
+            "i" => WatchTarget::Index,
+            "dt" => WatchTarget::DelayTimer,
+            "st" => WatchTarget::SoundTimer,
+            _ if lower.len() == 2 && lower.starts_with('v') => match u8::from_str_radix(&lower[1..], 16) {
+                Ok(x) => WatchTarget::V(x),
+                Err(_) => return Err(format!("unknown register '{}'", text)),
+            },
+            _ => {
+                let mut parts = lower.splitn(2, '-');
+                let first = hex(parts.next().unwrap_or(""))?;
+                let last = match parts.next() {
+                    Some(last) => hex(last)?,
+                    None => first,
+                };
+                if last < first {
+                    return Err(format!("invalid address range '{}'", text));
+                }
+                WatchTarget::Memory(first, last)
+            },
+        };
+        Ok(target)
+    }
+
+    pub fn is_memory(&self) -> bool {
+        matches!(self.target, WatchTarget::Memory(..))
+    }
+
+    /// Whether an instruction reading or writing value at address hits this
+    pub fn hits_memory(&self, address: usize, access: Access, value: u8) -> bool {
+        let in_range = match self.target {
+            WatchTarget::Memory(first, last) => address >= first as usize && address <= last as usize,
+            _ => false,
+        };
+        in_range && (self.access == access || self.access == Access::ReadWrite) && self.allows(value as u16)
+    }
+
+    /// Whether a register changing to value hits this
+    pub fn hits_register(&self, register: WatchTarget, value: u16) -> bool {
+        self.target == register && self.allows(value)
+    }
+
+    fn allows(&self, value: u16) -> bool {
+        match self.condition {
+            Some((comparison, operand)) => comparison.holds(value, operand),
+            None => true,
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", TargetName(self.target))?;
+        match self.access {
+            Access::Read => write!(f, " read")?,
+            Access::Write => write!(f, " write")?,
+            Access::ReadWrite => write!(f, " rw")?,
+        }
+        if let Some((comparison, operand)) = self.condition {
+            write!(f, " {} {:#x}", comparison.symbol(), operand)?;
+        }
+        if self.pause {
+            write!(f, " pause")?;
+        }
+        Ok(())
+    }
+}
+
+// An instruction touching something watched
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchHit {
+    /// number of the watchpoint hit
+    pub id: usize,
+    /// address of the instruction
+    pub pc: u16,
+    /// the one address or register touched
+    pub target: WatchTarget,
+    /// Read or Write
+    pub access: Access,
+    /// the value before, the same as new for a read
+    pub old: u16,
+    pub new: u16,
+    pub pause: bool,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = if self.target == WatchTarget::Index { 6 } else { 4 };
+        write!(f, "watchpoint {}: ", self.id)?;
+        match (self.target, self.access) {
+            (WatchTarget::Memory(..), Access::Read) => {
+                write!(f, "{} read at {:#06x}, {:#04x}", TargetName(self.target), self.pc, self.new)
+            },
+            (WatchTarget::Memory(..), _) => {
+                write!(f, "{} written at {:#06x}, {:#04x} -> {:#04x}", TargetName(self.target), self.pc, self.old, self.new)
+            },
+            _ => write!(f, "{} changed at {:#06x}, {:#0w$x} -> {:#0w$x}",
+                        TargetName(self.target), self.pc, self.old, self.new, w = width),
+        }
+    }
+}
+
+// How a target is written, for watchpoints and their hits
+struct TargetName(WatchTarget);
+
+impl fmt::Display for TargetName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            WatchTarget::Memory(first, last) if first == last => write!(f, "{:#06x}", first),
+            WatchTarget::Memory(first, last) => write!(f, "{:#06x}-{:#06x}", first, last),
+            WatchTarget::V(x) => write!(f, "V{:X}", x),
+            WatchTarget::Index => write!(f, "I"),
+            WatchTarget::DelayTimer => write!(f, "DT"),
+            WatchTarget::SoundTimer => write!(f, "ST"),
+        }
+    }
+}
+
+// Addresses and values, with or without a 0x in front. The debugger reads its
+// arguments the same way.
+pub(crate) fn hex(text: &str) -> Result<u16, String> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid hex number '{}'", text))
+}
+
+#[cfg(test)]
+#[path = "./watch_test.rs"]
+mod watch_test;
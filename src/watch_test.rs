@@ -0,0 +1,172 @@
+use super::*;
+
+use crate::cpu::CPU;
+use crate::fault::StepOutcome;
+use crate::quirks::Quirks;
+
+// A cpu with a short program that writes through I, ready to step
+fn chip_with_program() -> CPU {
+    let mut chip = CPU::new(Quirks::default());
+    let program = [
+        0x60, 0x7B, // 0x200: LD V0, 0x7B
+        0xA3, 0x00, // 0x202: LD I, 0x300
+        0xF0, 0x33, // 0x204: LD B, V0
+        0xF1, 0x55, // 0x206: LD [I], V1
+        0xD0, 0x01, // 0x208: DRW V0, V0, 1
+        0x12, 0x0A, // 0x20A: JP 0x20A
+    ];
+    chip.memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+    chip
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(Watchpoint::parse("300").unwrap(), Watchpoint {
+        target: WatchTarget::Memory(0x300, 0x300),
+        access: Access::Write,
+        condition: None,
+        pause: false,
+    });
+    assert_eq!(Watchpoint::parse("0x300-30f rw == 0 pause").unwrap(), Watchpoint {
+        target: WatchTarget::Memory(0x300, 0x30F),
+        access: Access::ReadWrite,
+        condition: Some((Comparison::Equal, 0)),
+        pause: true,
+    });
+    assert_eq!(Watchpoint::parse("VA >= 10").unwrap().target, WatchTarget::V(0xA));
+    assert_eq!(Watchpoint::parse("i").unwrap().target, WatchTarget::Index);
+    assert_eq!(Watchpoint::parse("dt").unwrap().target, WatchTarget::DelayTimer);
+
+    assert!(Watchpoint::parse("").is_err());
+    assert!(Watchpoint::parse("30f-300").is_err());
+    assert!(Watchpoint::parse("vg").is_err());
+    assert!(Watchpoint::parse("300 ==").is_err());
+    assert!(Watchpoint::parse("300 sometimes").is_err());
+    assert!(Watchpoint::parse("v3 read").is_err());
+}
+
+#[test]
+fn test_display() {
+    let watchpoint = Watchpoint::parse("300-30f write == 0 pause").unwrap();
+    assert_eq!(watchpoint.to_string(), "0x0300-0x030f write == 0x0 pause");
+    assert_eq!(Watchpoint::parse("v3").unwrap().to_string(), "V3 write");
+
+    let hit = WatchHit {
+        id: 2,
+        pc: 0x206,
+        target: WatchTarget::Memory(0x300, 0x300),
+        access: Access::Write,
+        old: 0,
+        new: 5,
+        pause: false,
+    };
+    assert_eq!(hit.to_string(), "watchpoint 2: 0x0300 written at 0x0206, 0x00 -> 0x05");
+    let read = WatchHit { access: Access::Read, old: 5, ..hit };
+    assert_eq!(read.to_string(), "watchpoint 2: 0x0300 read at 0x0206, 0x05");
+    let index = WatchHit { target: WatchTarget::Index, old: 0x200, new: 0x300, ..hit };
+    assert_eq!(index.to_string(), "watchpoint 2: I changed at 0x0206, 0x0200 -> 0x0300");
+}
+
+#[test]
+fn test_hits_memory() {
+    let watchpoint = Watchpoint::parse("300-301 == 0").unwrap();
+    assert!(watchpoint.hits_memory(0x300, Access::Write, 0));
+    assert!(watchpoint.hits_memory(0x301, Access::Write, 0));
+    assert!(!watchpoint.hits_memory(0x302, Access::Write, 0));
+    assert!(!watchpoint.hits_memory(0x300, Access::Write, 1));
+    assert!(!watchpoint.hits_memory(0x300, Access::Read, 0));
+
+    let both = Watchpoint::parse("300 rw").unwrap();
+    assert!(both.hits_memory(0x300, Access::Read, 9));
+    assert!(both.hits_memory(0x300, Access::Write, 9));
+}
+
+#[test]
+fn test_memory_writes() {
+    let mut chip = chip_with_program();
+    chip.add_watchpoint(Watchpoint::parse("300-302").unwrap());
+    for _ in 0..4 {
+        chip.step().unwrap();
+    }
+
+    // LD B writes 1, 2 and 3, LD [I] writes V0 and V1 back over the first two
+    let hits = chip.take_watch_hits();
+    let writes: Vec<_> = hits.iter().map(|hit| (hit.pc, hit.target, hit.old, hit.new)).collect();
+    assert_eq!(writes, [
+        (0x204, WatchTarget::Memory(0x300, 0x300), 0, 1),
+        (0x204, WatchTarget::Memory(0x301, 0x301), 0, 2),
+        (0x204, WatchTarget::Memory(0x302, 0x302), 0, 3),
+        (0x206, WatchTarget::Memory(0x300, 0x300), 1, 0x7B),
+        (0x206, WatchTarget::Memory(0x301, 0x301), 2, 0),
+    ]);
+    assert!(chip.take_watch_hits().is_empty());
+}
+
+#[test]
+fn test_sprite_reads() {
+    let mut chip = chip_with_program();
+    chip.add_watchpoint(Watchpoint::parse("300 read").unwrap());
+    for _ in 0..5 {
+        chip.step().unwrap();
+    }
+
+    let hits = chip.take_watch_hits();
+    assert_eq!(hits.len(), 1);
+    assert_eq!((hits[0].pc, hits[0].access, hits[0].new), (0x208, Access::Read, 0x7B));
+}
+
+#[test]
+fn test_registers() {
+    let mut chip = chip_with_program();
+    chip.add_watchpoint(Watchpoint::parse("v0").unwrap());
+    chip.add_watchpoint(Watchpoint::parse("i > 2ff").unwrap());
+    for _ in 0..6 {
+        chip.step().unwrap();
+    }
+
+    let hits = chip.take_watch_hits();
+    assert_eq!(hits.len(), 2);
+    assert_eq!((hits[0].id, hits[0].pc, hits[0].old, hits[0].new), (1, 0x200, 0, 0x7B));
+    assert_eq!((hits[1].id, hits[1].pc, hits[1].old, hits[1].new), (2, 0x202, 0, 0x300));
+}
+
+#[test]
+fn test_pause() {
+    let mut chip = chip_with_program();
+    let id = chip.add_watchpoint(Watchpoint::parse("301 write == 2 pause").unwrap());
+
+    assert_eq!(chip.step(), Ok(StepOutcome::Executed));
+    assert_eq!(chip.step(), Ok(StepOutcome::Executed));
+    assert_eq!(chip.step(), Ok(StepOutcome::Paused));
+    assert!(chip.is_paused());
+    assert_eq!(chip.step(), Ok(StepOutcome::Paused));
+    assert_eq!(chip.memory[0x302], 3);
+
+    chip.resume();
+    assert!(chip.remove_watchpoint(id));
+    assert!(!chip.remove_watchpoint(id));
+    assert_eq!(chip.step(), Ok(StepOutcome::Executed));
+    assert!(chip.watchpoints().is_empty());
+}
+
+#[test]
+fn test_register_writes_that_keep_the_value() {
+    let mut chip = CPU::new(Quirks::default());
+    let program = [
+        0x63, 0x00, // 0x200: LD V3, 0x00
+        0xA3, 0x00, // 0x202: LD I, 0x300
+        0xF3, 0x65, // 0x204: LD V3, [I]
+        0x73, 0x00, // 0x206: ADD V3, 0x00
+        0x83, 0x43, // 0x208: XOR V3, V4
+    ];
+    chip.memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+    chip.add_watchpoint(Watchpoint::parse("v3 == 0").unwrap());
+    for _ in 0..5 {
+        chip.step().unwrap();
+    }
+
+    // V3 is 0 the whole time, each write still counts
+    let hits = chip.take_watch_hits();
+    let writes: Vec<_> = hits.iter().map(|hit| (hit.pc, hit.old, hit.new)).collect();
+    assert_eq!(writes, [(0x200, 0, 0), (0x204, 0, 0), (0x206, 0, 0), (0x208, 0, 0)]);
+}